@@ -1,7 +1,9 @@
 use anchor_lang::prelude::*;
-use crate::states::tier::TierInfo;
+use crate::states::tier::{TierInfo, AccrualMode};
 use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
 use crate::errors::MinerError;
+use crate::events::TierUpdated;
 
 #[derive(Accounts)]
 pub struct AddTier<'info> {
@@ -11,17 +13,23 @@ pub struct AddTier<'info> {
         init,
         payer = admin,
         space = 8 + TierInfo::INIT_SPACE,
-        seeds = [b"tier".as_ref(), &[mine_info.current_tier_nonce]],
+        seeds = [b"tier", mine_info.key().as_ref(), &[mine_info.current_tier_nonce]],
         bump
     )]
     pub tier_info: Account<'info, TierInfo>,
     #[account(
         mut,
-        seeds = [b"mine".as_ref()],
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
-        constraint = mine_info.admin == admin.key() && mine_info.is_active @ MinerError::InvalidMine
+        constraint = mine_info.is_active @ MinerError::InvalidMine
     )]
     pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_tier_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
     pub system_program: Program<'info, System>
 }
 
@@ -31,16 +39,43 @@ impl<'info> AddTier<'info> {
         minimum_token_amount: u64,
         apy: u64,
         lock_duration: u64,
+        accrual_mode: AccrualMode,
         bump: u8
     ) -> Result<()> {
-        self.tier_info.set_inner(TierInfo {
+        let tier_info = TierInfo {
+            mine: self.mine_info.key(),
             lock_duration,
             minimum_token_amount,
             apy,
             is_active: true,
             total_locked: 0,
             bump,
-            nonce: self.mine_info.current_tier_nonce
+            nonce: self.mine_info.current_tier_nonce,
+            accrual_mode,
+            reward_rate: 0,
+            reward_remaining: 0,
+            last_reward_ts: Clock::get()?.unix_timestamp as u64,
+            acc_reward_per_share: 0,
+            receipt_mint: None,
+            escrow_gating: false,
+            gates: Vec::new(),
+            boosts: Vec::new(),
+            detached_receipts: 0
+        };
+        if !self.mine_info.pays_interest_of(&tier_info) {
+            return err!(MinerError::RewardMintMismatch);
+        }
+        self.tier_info.set_inner(tier_info);
+        emit!(TierUpdated {
+            tier: self.tier_info.key(),
+            nonce: self.tier_info.nonce,
+            minimum_token_amount,
+            apy,
+            lock_duration,
+            is_active: true,
+            accrual_mode,
+            escrow_gating: false,
+            timestamp: Clock::get()?.unix_timestamp as u64
         });
         // Increase mine info tier nonce
         let mut mine_info = self.mine_info.clone().into_inner();
@@ -0,0 +1,75 @@
+use anchor_lang::prelude::*;
+use crate::states::allowlist::AllowList;
+use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
+use crate::states::tier::TierInfo;
+use crate::errors::MinerError;
+use crate::events::AllowlistPublished;
+
+/// Instruction for the admin to publish a merkle root of whitelisted
+/// beneficiaries for a TIER. Beneficiaries consume their leaf through
+/// `initialize_allowlist` without the admin creating one account per wallet.
+#[derive(Accounts)]
+#[instruction(_tier_nonce: u8, root: [u8; 32], _expiry: u64, capacity: u32)]
+pub struct PublishAllowList<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + AllowList::INIT_SPACE + AllowList::bitmap_len(capacity),
+        seeds = [b"allowlist", mine_info.key().as_ref(), root.as_ref()],
+        bump
+    )]
+    pub allowlist: Account<'info, AllowList>,
+    #[account(
+        seeds = [b"tier", mine_info.key().as_ref(), &[_tier_nonce]],
+        bump = tier_info.bump,
+        constraint = tier_info.is_active && _tier_nonce == tier_info.nonce @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_whitelister(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+    pub system_program: Program<'info, System>
+}
+
+impl<'info> PublishAllowList<'info> {
+    pub fn publish_allowlist(
+        &mut self,
+        expiry: u64,
+        capacity: u32,
+        bump: u8,
+        root: [u8; 32],
+        _tier_nonce: u8
+    ) -> Result<()> {
+        if capacity == 0 {
+            return err!(MinerError::InvalidArgument);
+        }
+        self.allowlist.set_inner(AllowList {
+            root,
+            tier: self.tier_info.key(),
+            expiry,
+            capacity,
+            bump,
+            claimed: vec![0; AllowList::bitmap_len(capacity)]
+        });
+        emit!(AllowlistPublished {
+            allowlist: self.allowlist.key(),
+            tier: self.tier_info.key(),
+            root,
+            expiry,
+            capacity,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
@@ -1,47 +1,60 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::TokenAccount;
-use crate::states::mine::{MineInfo, MineVault};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault, check_shortfall_kept, mine_shortfall};
 use crate::states::tier::TierInfo;
 use crate::states::user::UserInfo;
 use crate::errors::MinerError;
-use crate::math::{calculate_fee, calculate_interest, to_u128};
+use crate::events::InterestClaimed;
+use crate::contexts::init_staking::credit_referrer;
+use crate::transfers::{Payer, TokenRoute};
 
-/// Instruction to call for normal users to claim their interests
+/// Instruction to call for normal users to claim their interests. When the
+/// mine pays referrals on claims the referrer of a referred position is
+/// credited on the claimed interest. Token mode mines pay the interest in
+/// the reward mint.
 #[derive(Accounts)]
 pub struct ClaimInterest<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
     #[account(
         mut,
-        seeds = [b"user", signer.key().as_ref()],
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
         bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
         constraint = user_info.is_whitelist == false @ MinerError::OperationNotAllowed
     )]
     pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         constraint = (
-            token_account.mint == mine_info.token_mint &&
-            token_account.amount >= tier_info.minimum_token_amount &&
+            user_info.meets_gate(tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint)) &&
             token_account.owner == signer.key()
         ) @ MinerError::InvalidTokenAccount
     )]
     pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
     #[account(
-        seeds = [b"mine".as_ref()],
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
         constraint = mine_info.is_active @ MinerError::InvalidMine
     )]
     pub mine_info: Account<'info, MineInfo>,
     #[account(
         mut,
-        seeds = [b"mine-vault"],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump = mine_vault.bump
     )]
     pub mine_vault: Account<'info, MineVault>,
     #[account(
+        mut,
         constraint = (tier_info.key() == user_info.tier && tier_info.is_active) @ MinerError::InvalidTier
     )]
     pub tier_info: Account<'info, TierInfo>,
+    /// CHECK: referral account of the position's referrer, checked against
+    /// the derived address. Only needed when the mine pays referrals on
+    /// claims, the referrer is then credited on the claimed interest
+    #[account(mut)]
+    pub referrer_info: Option<UncheckedAccount<'info>>,
     #[account(
         mut,
         constraint = mine_info.fee_collector == fee_collector.key()
@@ -52,58 +65,124 @@ pub struct ClaimInterest<'info> {
         constraint = mine_info.penalty_fee_collector == penalty_collector.key()
     )]
     pub penalty_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(user_reward_account.mint) &&
+            user_reward_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(fee_collector_reward_account.mint) &&
+            fee_collector_reward_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(penalty_collector_reward_account.mint) &&
+            penalty_collector_reward_account.owner == mine_info.penalty_fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub penalty_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>
 }
 
 impl<'info> ClaimInterest<'info> {
     pub fn claim_interest(
         &mut self
     ) -> Result<()> {
-        // Update User info
+        // What the vaults lack before the claim, counting the interest it
+        // settles as owed already
+        let now = Clock::get()?.unix_timestamp as u64;
+        let shortfall = mine_shortfall(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault)?
+            .owing(self.user_info.unsettled_interest(&self.tier_info, now)?);
+
+        // Settle interest earned since the last checkpoint, interest from
+        // here on follows the current gating balance
+        let boost = self.tier_info.boost_for(&self.mine_info.token_mint, &self.token_account);
+        let mut tier_info = self.tier_info.clone().into_inner();
         let mut user_info = self.user_info.clone().into_inner();
-        let current_interest = user_info.accrued_interest;
-        let current_interval = (Clock::get()?.unix_timestamp as u64).saturating_sub(user_info.lock_ts);
-        let dev_fee = calculate_fee(
-            to_u128(current_interest)?, 
-            to_u128(self.mine_info.dev_fee)?
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        let (current_interest, payout) = user_info.claim(
+            &mut tier_info,
+            &self.mine_info,
+            &mut mine_vault,
+            boost,
+            now
         )?;
+        user_info.gate_account = Some(self.token_account.key());
+        self.tier_info.set_inner(tier_info);
+        self.user_info.set_inner(user_info);
 
-        // Calculate amount out based on early claim penalty and dev fee
-        let (amount_out, penalty) = if current_interval >= self.tier_info.lock_duration {
-            // update with the new interest and the lock timestamp
-            let new_interest = match calculate_interest(
-                to_u128(user_info.total_locked)?, 
-                to_u128(self.tier_info.apy)?, 
-                to_u128(self.tier_info.lock_duration)?
-            ) {
-                Ok(val) => val,
-                Err(_) => return err!(MinerError::MathsError)
-            };
-            user_info.accrued_interest = new_interest;
-            user_info.lock_ts = Clock::get()?.unix_timestamp as u64;
-            (current_interest.saturating_sub(dev_fee), 0_u64)
+        // Send amount out and fees
+        Payer {
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            owner: self.signer.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            penalty_collector: self.penalty_collector.to_account_info()
+        }.send(&payout, &TokenRoute {
+            token_program: &self.token_program,
+            mint: &self.reward_mint,
+            vault: &self.reward_vault,
+            holder: &self.user_reward_account,
+            fee_collector: &self.fee_collector_reward_account,
+            penalty_collector: &self.penalty_collector_reward_account
+        })?;
+
+        // Credit the referrer on the claimed interest
+        let bonus = if self.mine_info.referral_on_claim {
+            credit_referrer(
+                &self.referrer_info,
+                self.user_info.referrer,
+                0,
+                &self.mine_info,
+                self.user_info.key(),
+                current_interest,
+                false
+            )?
         } else {
-            let penalty = calculate_fee(
-                to_u128(current_interest)?, 
-                to_u128(self.mine_info.early_withdrawal_fee)?
-            )?;
-            user_info.accrued_interest = 0;
-            user_info.lock_ts = Clock::get()?.unix_timestamp as u64;
-            msg!("Penalty: {}", penalty);
-            (current_interest.saturating_sub(dev_fee).saturating_sub(penalty), penalty)
+            0
         };
-        let vault_bal = self.mine_vault.get_lamports();
-        msg!("Vault Balance: {}", vault_bal);
-        msg!(
-            "Amount Out {}, Dev Fee: {}, Interest: {}, Duration: {}", 
-            amount_out, dev_fee, current_interest, current_interval
-        );
-        // Send amount out and dev fee
-        self.fee_collector.add_lamports(dev_fee)?;
-        self.signer.add_lamports(amount_out)?;
-        self.penalty_collector.add_lamports(penalty)?;
-        self.mine_vault.sub_lamports(dev_fee.saturating_add(amount_out).saturating_add(penalty))?;
-        self.user_info.set_inner(user_info);
 
+        // Update vault liabilities
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_add(bonus);
+        self.mine_vault.set_inner(mine_vault);
+        check_shortfall_kept(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault, shortfall)?;
+
+        emit!(InterestClaimed {
+            owner: self.signer.key(),
+            user_info: self.user_info.key(),
+            tier: self.tier_info.key(),
+            interest: current_interest,
+            amount_out: payout.amount_out,
+            dev_fee: payout.dev_fee,
+            penalty: payout.penalty,
+            boost: self.user_info.boost,
+            timestamp: now
+        });
         Ok(())
     }
 }
\ No newline at end of file
@@ -1,73 +1,137 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::TokenAccount;
-use crate::states::mine::MineInfo;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use crate::states::mine::{MineInfo, MineVault, check_mine_solvency};
 use crate::states::tier::TierInfo;
 use crate::states::user::UserInfo;
 use crate::errors::MinerError;
-use crate::math::{calculate_interest, to_u128};
+use crate::events::InterestCompounded;
+use crate::contexts::init_staking::{credit_referrer, credit_upline};
 
-/// Instruction to call for users to restake their interests
+/// Instruction to call for users to restake their interests. The compounded
+/// interest is a top up and credits each referrer of the position's chain.
+/// Token mode mines can only compound when interest is paid in the stake
+/// mint.
 #[derive(Accounts)]
 pub struct Compound<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
     #[account(
         mut,
-        seeds = [b"user", signer.key().as_ref()],
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
         bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
         constraint = user_info.is_whitelist == false @ MinerError::OperationNotAllowed
     )]
     pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         constraint = (
-            token_account.mint == mine_info.token_mint &&
-            token_account.amount >= tier_info.minimum_token_amount &&
+            user_info.meets_gate(tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint)) &&
             token_account.owner == signer.key()
         ) @ MinerError::InvalidTokenAccount
     )]
     pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
     #[account(
-        seeds = [b"mine".as_ref()],
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
-        constraint = mine_info.is_active @ MinerError::InvalidMine
+        constraint = mine_info.is_active @ MinerError::InvalidMine,
+        constraint = mine_info.pays_in_stake_mint() @ MinerError::InvalidStakeMode
     )]
     pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
     #[account(
         mut,
         constraint = (tier_info.key() == user_info.tier && tier_info.is_active) @ MinerError::InvalidTier
     )]
     pub tier_info: Account<'info, TierInfo>,
+    /// CHECK: referral account of the position's referrer. Compounded
+    /// interest is a top up so the referrer is credited on it, the account
+    /// is checked against the derived address and required while that pays
+    #[account(mut)]
+    pub referrer_info: Option<UncheckedAccount<'info>>,
+    /// CHECK: second level referral account, credited on the compounded
+    /// interest like on a top up. Checked against the derived address and
+    /// required while the second level reward is set
+    #[account(mut)]
+    pub second_referrer_info: Option<UncheckedAccount<'info>>,
+    /// CHECK: third level referral account, see `second_referrer_info`
+    #[account(mut)]
+    pub third_referrer_info: Option<UncheckedAccount<'info>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>
 }
 
 impl<'info> Compound<'info> {
     pub fn compound_interest(
         &mut self
     ) -> Result<()> {
-        // Update User info
-        let current_interval = (Clock::get()?.unix_timestamp as u64) - self.user_info.lock_ts;
-        if current_interval < self.tier_info.lock_duration {
-            return err!(MinerError::OperationNotAllowed)
-        }
+        // Settle interest earned since the last checkpoint, interest from
+        // here on follows the current gating balance
+        let boost = self.tier_info.boost_for(&self.mine_info.token_mint, &self.token_account);
+        let mut tier_info = self.tier_info.clone().into_inner();
         let mut user_info = self.user_info.clone().into_inner();
-        let current_interest = user_info.accrued_interest;
-        let new_total_locked = user_info.total_locked.saturating_add(current_interest);
-        let new_interest = match calculate_interest(
-            to_u128(new_total_locked)?, 
-            to_u128(self.tier_info.apy)?, 
-            to_u128(self.tier_info.lock_duration)?
-        ) {
-            Ok(val) => val,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        user_info.accrued_interest = new_interest;
-        user_info.lock_ts = Clock::get()?.unix_timestamp as u64;
-        user_info.total_locked = new_total_locked;
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        let current_interest = user_info.compound(
+            &mut tier_info,
+            &mut mine_vault,
+            boost,
+            Clock::get()?.unix_timestamp as u64
+        )?;
+        user_info.gate_account = Some(self.token_account.key());
+        let new_total_locked = user_info.total_locked;
+        let new_interest = user_info.accrued_interest;
+        self.tier_info.set_inner(tier_info);
         self.user_info.set_inner(user_info);
 
-        // Update Tier total locked
-        let mut tier_info = self.tier_info.clone().into_inner();
-        tier_info.total_locked = tier_info.total_locked.saturating_add(current_interest);
-        self.tier_info.set_inner(tier_info);
+        // Credit the referrer chain on the compounded interest
+        let bonus = credit_referrer(
+            &self.referrer_info,
+            self.user_info.referrer,
+            0,
+            &self.mine_info,
+            self.user_info.key(),
+            current_interest,
+            false
+        )?;
+        let bonus = bonus.saturating_add(credit_upline(
+            [&self.second_referrer_info, &self.third_referrer_info],
+            self.user_info.upline_referrers,
+            &self.mine_info,
+            self.user_info.key(),
+            current_interest,
+            false
+        )?);
+
+        // Update vault liabilities
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_add(bonus);
+        self.mine_vault.set_inner(mine_vault);
+        check_mine_solvency(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault)?;
+        emit!(InterestCompounded {
+            owner: self.signer.key(),
+            user_info: self.user_info.key(),
+            tier: self.tier_info.key(),
+            compounded: current_interest,
+            total_locked: new_total_locked,
+            accrued_interest: new_interest,
+            boost: self.user_info.boost,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
 
         Ok(())
     }
@@ -0,0 +1,275 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{token, token_2022};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault, check_shortfall_kept, mine_shortfall};
+use crate::states::tier::TierInfo;
+use crate::states::user::UserInfo;
+use crate::errors::MinerError;
+use crate::events::GateEnforced;
+use crate::math::{calculate_fee, calculate_interest, to_u128};
+use crate::transfers::transfer_from_vault;
+
+/// Share of the position's earned interest paid to the caller, in basis points
+const GATE_BOUNTY: u64 = 500;
+
+/// Instruction anyone can call on a position whose holder no longer has the
+/// gating tokens of its tier in the token account the gate was last checked
+/// on. The position is moved to the given tier that still admits what the
+/// holder has, or its interest stops when none is given, and the caller is
+/// paid a bounty out of the interest the position earned. NFT positions are
+/// checked against the current NFT holder, who can re-point the gate with
+/// `update_gate_account` after buying the position. Token mode mines pay the
+/// bounty in the reward mint out of the reward vault.
+#[derive(Accounts)]
+pub struct EnforceGate<'info> {
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
+        constraint = user_info.authority(&position_nft).is_some() @ MinerError::InvalidArgument,
+        constraint = !user_info.is_whitelist @ MinerError::OperationNotAllowed,
+        constraint = user_info.escrowed_amount == 0 @ MinerError::GateSatisfied
+    )]
+    pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: token account the position's gate was last checked on, it may
+    /// have been closed or handed over in which case nothing is held
+    #[account(
+        constraint = user_info.gate_account == Some(token_account.key()) @ MinerError::InvalidTokenAccount
+    )]
+    pub token_account: UncheckedAccount<'info>,
+    /// Mint of the token held in `token_account`, required while it is open
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        mut,
+        constraint = tier_info.key() == user_info.tier @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        mut,
+        constraint = (
+            to_tier_info.mine == mine_info.key() &&
+            to_tier_info.key() != tier_info.key() &&
+            to_tier_info.is_active &&
+            !to_tier_info.escrow_gating &&
+            to_tier_info.apy <= tier_info.apy &&
+            to_tier_info.lock_duration <= tier_info.lock_duration
+        ) @ MinerError::InvalidTier
+    )]
+    pub to_tier_info: Option<Account<'info, TierInfo>>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(caller_reward_account.mint) &&
+            caller_reward_account.owner == caller.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub caller_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>
+}
+
+impl<'info> EnforceGate<'info> {
+    /// Gating token account of the position while it is open and still
+    /// held by the position holder
+    fn held_gate_account(&self) -> Result<Option<TokenAccount>> {
+        let account = self.token_account.to_account_info();
+        if account.data_is_empty() || (account.owner != &token::ID && account.owner != &token_2022::ID) {
+            return Ok(None);
+        }
+        let data = account.try_borrow_data()?;
+        let token_account = TokenAccount::try_deserialize(&mut &data[..])?;
+        if self.user_info.authority(&self.position_nft) != Some(token_account.owner) {
+            return Ok(None);
+        }
+        // Collection gates are read from the mint of the held token
+        match &self.gate_mint {
+            Some(gate_mint) if gate_mint.key() == token_account.mint => Ok(Some(token_account)),
+            _ => err!(MinerError::InvalidTokenAccount)
+        }
+    }
+
+    /// Whether a tier admits what the position holder still holds
+    fn admitted_by(&self, tier: &TierInfo, held: &Option<TokenAccount>) -> bool {
+        match held {
+            Some(token_account) => tier.admits(&self.mine_info.token_mint, token_account, &self.gate_mint),
+            None => tier.is_ungated()
+        }
+    }
+
+    /// Pays the caller's bounty in lamports, or in the reward mint for
+    /// token mode mines
+    fn pay_bounty(&mut self, bounty: u64) -> Result<()> {
+        if bounty == 0 {
+            return Ok(());
+        }
+        if self.mine_info.is_native() {
+            self.mine_vault.sub_lamports(bounty)?;
+            self.caller.add_lamports(bounty)?;
+            return Ok(());
+        }
+        let (Some(token_program), Some(reward_mint), Some(reward_vault), Some(caller_reward_account)) =
+            (&self.token_program, &self.reward_mint, &self.reward_vault, &self.caller_reward_account) else {
+            return err!(MinerError::MissingTokenAccounts);
+        };
+        transfer_from_vault(
+            token_program,
+            reward_vault,
+            caller_reward_account,
+            reward_mint,
+            &self.mine_vault,
+            &self.mine_info.key(),
+            bounty
+        )
+    }
+
+    pub fn enforce_gate(
+        &mut self
+    ) -> Result<()> {
+        let held = self.held_gate_account()?;
+        if self.admitted_by(&self.tier_info, &held) {
+            return err!(MinerError::GateSatisfied);
+        }
+        let balance = held.as_ref().map_or(0, |token_account| token_account.amount);
+        let to_tier_admits = self.to_tier_info.as_ref().is_some_and(|to_tier| self.admitted_by(to_tier, &held));
+        let now = Clock::get()?.unix_timestamp as u64;
+        let shortfall = mine_shortfall(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault)?;
+
+        // Settle the interest earned so far
+        let mut tier_info = self.tier_info.clone().into_inner();
+        let mut user_info = self.user_info.clone().into_inner();
+        let settled_interest = user_info.settle(&mut tier_info, now)?;
+        self.tier_info.set_inner(tier_info);
+        let previous_interest = user_info.accrued_interest;
+        let earned_interest = if self.tier_info.is_fixed() {
+            user_info.earned_interest(now)?
+        } else {
+            user_info.accrued_interest
+        };
+        let forfeited_interest = previous_interest.saturating_sub(earned_interest);
+
+        // Pay the bounty out of the earned interest
+        let bounty = calculate_fee(
+            to_u128(earned_interest)?,
+            to_u128(GATE_BOUNTY)?
+        )?;
+        self.pay_bounty(bounty)?;
+        user_info.accrued_interest = earned_interest.saturating_sub(bounty);
+
+        // Demote to the lower tier keeping the unlock time, otherwise stop
+        // the interest in place
+        let total_locked = user_info.total_locked;
+        let remaining = user_info.lock_ts
+            .saturating_add(user_info.lock_duration)
+            .saturating_sub(now);
+        match &mut self.to_tier_info {
+            Some(to_tier_info) => {
+                if !to_tier_admits {
+                    return err!(MinerError::InvalidTier);
+                }
+                // Receipts are minted per tier and must be redeemed first
+                if user_info.receipt_amount > 0 {
+                    return err!(MinerError::ReceiptsOutstanding);
+                }
+                let mut to_tier = to_tier_info.clone().into_inner();
+                to_tier.update_emission(now)?;
+                let new_interest = if !to_tier.is_fixed() {
+                    0
+                } else {
+                    match calculate_interest(
+                        to_u128(total_locked)?,
+                        to_u128(to_tier.apy)?,
+                        to_u128(remaining)?
+                    ) {
+                        Ok(val) => val,
+                        Err(_) => return err!(MinerError::MathsError)
+                    }
+                };
+                user_info.accrued_interest = user_info.accrued_interest.saturating_add(new_interest);
+                user_info.tier = to_tier_info.key();
+                user_info.apy = to_tier.apy;
+                user_info.boost = 0;
+                user_info.lock_ts = now;
+                user_info.lock_duration = remaining;
+                user_info.last_accrual_ts = now;
+                user_info.reward_debt = to_tier.reward_debt(total_locked)?;
+                to_tier.total_locked = to_tier.total_locked.saturating_add(total_locked);
+                to_tier_info.set_inner(to_tier);
+
+                let mut tier_info = self.tier_info.clone().into_inner();
+                tier_info.total_locked = tier_info.total_locked.saturating_sub(total_locked);
+                self.tier_info.set_inner(tier_info);
+            },
+            None => {
+                // Emission rewards are shared by stake, they only stop by
+                // leaving the tier
+                if self.tier_info.is_emission() || user_info.apy == 0 {
+                    return err!(MinerError::OperationNotAllowed);
+                }
+                user_info.apy = 0;
+                user_info.boost = 0;
+                user_info.last_accrual_ts = now;
+            }
+        }
+        let accrued_interest = user_info.accrued_interest;
+        self.user_info.set_inner(user_info);
+
+        // Update vault liabilities
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest
+            .saturating_add(settled_interest)
+            .saturating_sub(previous_interest)
+            .saturating_add(accrued_interest);
+        self.mine_vault.set_inner(mine_vault);
+        // Only a demotion adds interest, what the position was owed counts
+        check_shortfall_kept(
+            &self.mine_info,
+            &self.mine_vault,
+            &self.stake_vault,
+            &self.reward_vault,
+            shortfall.owing(settled_interest)
+        )?;
+
+        emit!(GateEnforced {
+            caller: self.caller.key(),
+            owner: self.user_info.owner,
+            user_info: self.user_info.key(),
+            from_tier: self.tier_info.key(),
+            to_tier: self.to_tier_info.as_ref().map(|tier| tier.key()),
+            balance,
+            forfeited_interest,
+            bounty,
+            timestamp: now
+        });
+        Ok(())
+    }
+}
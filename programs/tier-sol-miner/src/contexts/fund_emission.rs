@@ -0,0 +1,116 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault};
+use crate::states::roles::MineRoles;
+use crate::states::tier::TierInfo;
+use crate::errors::MinerError;
+use crate::transfers::{Deposit, TokenRoute};
+use crate::events::EmissionFunded;
+
+/// Instruction for the tier manager to fund an emission TIER's reward
+/// budget and set the rate it is emitted at. Funded lamports, or what
+/// arrives in the reward vault of a token mode mine, are counted as vault
+/// liabilities until they are claimed or forfeited.
+#[derive(Accounts)]
+pub struct FundEmission<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        constraint = (tier_info.mine == mine_info.key() && tier_info.is_emission()) @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.is_active @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_tier_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(admin_reward_account.mint) &&
+            admin_reward_account.owner == admin.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub admin_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>
+}
+
+impl<'info> FundEmission<'info> {
+    pub fn fund_emission(
+        &mut self,
+        amount: u64,
+        reward_rate: u64
+    ) -> Result<()> {
+        let actual_amount = Deposit {
+            signer: &self.admin,
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            system_program: &self.system_program,
+            tokens: TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.reward_mint,
+                vault: &self.reward_vault,
+                holder: &self.admin_reward_account,
+                fee_collector: &None,
+                penalty_collector: &None
+            }
+        }.fund(amount)?;
+        add_emission_budget(&mut self.tier_info, &mut self.mine_vault, actual_amount, reward_rate)
+    }
+}
+
+/// Adds `amount` to the reward budget of an emission TIER and sets the rate
+/// it is emitted at from now on. The whole budget is owed to stakers, so it
+/// is counted as a vault liability.
+pub fn add_emission_budget<'info>(
+    tier_info: &mut Account<'info, TierInfo>,
+    mine_vault: &mut Account<'info, MineVault>,
+    amount: u64,
+    reward_rate: u64
+) -> Result<()> {
+    // Emit at the old rate up to now before the new rate applies
+    let mut tier = tier_info.clone().into_inner();
+    tier.update_emission(Clock::get()?.unix_timestamp as u64)?;
+    tier.reward_remaining = tier.reward_remaining.saturating_add(amount);
+    tier.reward_rate = reward_rate;
+    emit!(EmissionFunded {
+        tier: tier_info.key(),
+        amount,
+        reward_rate,
+        reward_remaining: tier.reward_remaining,
+        timestamp: Clock::get()?.unix_timestamp as u64
+    });
+    tier_info.set_inner(tier);
+
+    // Update vault liabilities
+    let mut vault = mine_vault.clone().into_inner();
+    vault.total_promised_interest = vault.total_promised_interest.saturating_add(amount);
+    mine_vault.set_inner(vault);
+    Ok(())
+}
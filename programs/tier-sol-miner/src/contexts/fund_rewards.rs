@@ -0,0 +1,81 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault};
+use crate::errors::MinerError;
+use crate::transfers::{Deposit, TokenRoute};
+use crate::events::RewardsFunded;
+
+/// Instruction for the admin to top up the vault with lamports, or the reward
+/// vault of a token mode mine with the reward mint, for paying interest.
+/// Funded lamports are not earmarked, they add to the vault surplus above
+/// its liabilities, which backs the interest promised on new stakes and is
+/// the only part `withdraw_excess` can take back.
+#[derive(Accounts)]
+pub struct FundRewards<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.admin == admin.key() @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(admin_reward_account.mint) &&
+            admin_reward_account.owner == admin.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub admin_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>
+}
+
+impl<'info> FundRewards<'info> {
+    pub fn fund_rewards(
+        &mut self,
+        amount: u64
+    ) -> Result<()> {
+        if amount == 0 {
+            return err!(MinerError::InvalidDepositAmount);
+        }
+        let actual_amount = Deposit {
+            signer: &self.admin,
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            system_program: &self.system_program,
+            tokens: TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.reward_mint,
+                vault: &self.reward_vault,
+                holder: &self.admin_reward_account,
+                fee_collector: &None,
+                penalty_collector: &None
+            }
+        }.fund(amount)?;
+
+        emit!(RewardsFunded {
+            amount: actual_amount,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
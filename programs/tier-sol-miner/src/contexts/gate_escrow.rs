@@ -0,0 +1,108 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault};
+use crate::states::tier::TierInfo;
+use crate::errors::MinerError;
+use crate::transfers::{transfer_from_user, transfer_from_vault};
+
+/// Instruction for the admin to create the escrow that holds the gating
+/// tokens of positions in escrow gated tiers. The account is owned by the
+/// mine vault PDA.
+#[derive(Accounts)]
+pub struct InitGateEscrow<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.admin == admin.key() @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        constraint = token_mint.key() == mine_info.token_mint @ MinerError::InvalidTokenAccount,
+        mint::token_program = token_program
+    )]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"gate-escrow", mine_info.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = mine_vault,
+        token::token_program = token_program
+    )]
+    pub gate_escrow: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>
+}
+
+impl<'info> InitGateEscrow<'info> {
+    pub fn init_gate_escrow(
+        &mut self
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Locks the tier's minimum token amount in the gate escrow when the tier
+/// uses escrow gating. Returns the amount the escrow actually received,
+/// which is what gets released later.
+pub fn lock_gate_tokens<'info>(
+    tier_info: &TierInfo,
+    token_account: &InterfaceAccount<'info, TokenAccount>,
+    gate_escrow: &mut Option<InterfaceAccount<'info, TokenAccount>>,
+    token_mint: &Option<InterfaceAccount<'info, Mint>>,
+    token_program: &Option<Interface<'info, TokenInterface>>,
+    signer: &Signer<'info>
+) -> Result<u64> {
+    if !tier_info.escrow_gating {
+        return Ok(0);
+    }
+    let (Some(gate_escrow), Some(token_mint), Some(token_program)) = (gate_escrow, token_mint, token_program) else {
+        return err!(MinerError::GateEscrowRequired);
+    };
+    let before_deposit = gate_escrow.amount;
+    transfer_from_user(
+        token_program,
+        token_account,
+        gate_escrow,
+        token_mint,
+        signer,
+        tier_info.minimum_token_amount
+    )?;
+    gate_escrow.reload()?;
+    Ok(gate_escrow.amount.saturating_sub(before_deposit))
+}
+
+/// Returns the gating tokens a position has in escrow to `to`
+pub fn release_gate_tokens<'info>(
+    escrowed_amount: u64,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    gate_escrow: &Option<InterfaceAccount<'info, TokenAccount>>,
+    token_mint: &Option<InterfaceAccount<'info, Mint>>,
+    token_program: &Option<Interface<'info, TokenInterface>>,
+    mine_vault: &Account<'info, MineVault>,
+    mine: &Pubkey
+) -> Result<()> {
+    if escrowed_amount == 0 {
+        return Ok(());
+    }
+    let (Some(gate_escrow), Some(token_mint), Some(token_program)) = (gate_escrow, token_mint, token_program) else {
+        return err!(MinerError::GateEscrowRequired);
+    };
+    transfer_from_vault(
+        token_program,
+        gate_escrow,
+        to,
+        token_mint,
+        mine_vault,
+        mine,
+        escrowed_amount
+    )
+}
@@ -1,54 +1,107 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::TokenAccount;
-use solana_program::system_instruction;
-use crate::states::mine::{MineInfo, MineVault};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault, check_mine_solvency};
 use crate::states::tier::TierInfo;
 use crate::states::user::UserInfo;
 use crate::errors::MinerError;
-use crate::math::{calculate_fee, calculate_interest, to_u128};
+use crate::events::StakeIncreased;
+use crate::contexts::init_staking::{credit_referrer, credit_upline};
+use crate::transfers::{Deposit, TokenRoute};
 
-/// Instruction to call for users to increase their locked SOL
+/// Instruction to call for users to increase their locked stake, lamports
+/// or the stake mint of a token mode mine. Referred positions credit each
+/// referrer of their chain on the top up.
 #[derive(Accounts)]
 pub struct IncreaseStake<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
     #[account(
         mut,
-        seeds = [b"user", signer.key().as_ref()],
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
         bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
         constraint = user_info.is_whitelist == false @ MinerError::OperationNotAllowed
     )]
     pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         constraint = (
-            token_account.mint == mine_info.token_mint &&
-            token_account.amount >= tier_info.minimum_token_amount &&
+            user_info.meets_gate(tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint)) &&
             token_account.owner == signer.key()
         ) @ MinerError::InvalidTokenAccount
     )]
     pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
     #[account(
-        seeds = [b"mine".as_ref()],
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
         constraint = mine_info.is_active @ MinerError::InvalidMine
     )]
     pub mine_info: Account<'info, MineInfo>,
     #[account(
         mut,
-        seeds = [b"mine-vault"],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump = mine_vault.bump
     )]
     pub mine_vault: Account<'info, MineVault>,
     #[account(
         mut,
-        constraint = (tier_info.key() == user_info.tier && tier_info.is_active) @ MinerError::InvalidTier
+        constraint = (tier_info.key() == user_info.tier && tier_info.is_active) @ MinerError::InvalidTier,
+        constraint = mine_info.pays_interest_of(&tier_info) @ MinerError::RewardMintMismatch
     )]
     pub tier_info: Account<'info, TierInfo>,
+    /// CHECK: referral account of the position's referrer, credited on the
+    /// top up. Checked against the derived address and required while the
+    /// direct referral reward is set
+    #[account(mut)]
+    pub referrer_info: Option<UncheckedAccount<'info>>,
+    /// CHECK: referral account of the referrer's own referrer, credited on
+    /// the top up with the second level reward. Checked against the derived
+    /// address and required while that level pays
+    #[account(mut)]
+    pub second_referrer_info: Option<UncheckedAccount<'info>>,
+    /// CHECK: third level of the chain, checked like `second_referrer_info`
+    #[account(mut)]
+    pub third_referrer_info: Option<UncheckedAccount<'info>>,
     #[account(
         mut,
         constraint = mine_info.fee_collector == fee_collector.key()
     )]
     pub fee_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
     pub system_program: Program<'info, System>
 }
 
@@ -60,74 +113,69 @@ impl<'info> IncreaseStake<'info> {
         if amount <= 0 {
             return err!(MinerError::InvalidDepositAmount);
         }
-        // Calculate fees and transfer lamports to vault and fee collector
-        let dev_fee = match calculate_fee(
-            to_u128(amount)?, 
-            to_u128(self.mine_info.dev_fee)?
-        ) {
-            Ok(fee) => fee,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        let actual_amount = amount.saturating_sub(dev_fee);
-        let fee_transfer_ix = system_instruction::transfer(
-            self.signer.key,
-            &self.mine_info.fee_collector,
-            dev_fee
-        );
-        let actual_transfer_ix = system_instruction::transfer(
-            self.signer.key,
-            &self.mine_vault.key(),
-            actual_amount
-        );
-        solana_program::program::invoke_signed(
-            &fee_transfer_ix,
-            &[
-                self.signer.to_account_info(),
-                self.fee_collector.to_account_info(),
-                self.system_program.to_account_info(),
-            ],
-            &[],
-        )?;
-        solana_program::program::invoke_signed(
-            &actual_transfer_ix,
-            &[
-                self.signer.to_account_info(),
-                self.mine_vault.to_account_info(),
-                self.system_program.to_account_info(),
-            ],
-            &[],
-        )?;
+        // Calculate fees and take the deposit into the vault
+        let (dev_fee, actual_amount) = Deposit {
+            signer: &self.signer,
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            system_program: &self.system_program,
+            tokens: TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.stake_mint,
+                vault: &self.stake_vault,
+                holder: &self.user_stake_account,
+                fee_collector: &self.fee_collector_stake_account,
+                penalty_collector: &None
+            }
+        }.take(amount, &self.fee_collector.to_account_info())?;
 
-        // Update User info
+        // Settle what was earned on the old principal before it grows
+        let now = Clock::get()?.unix_timestamp as u64;
+        let boost = self.tier_info.boost_for(&self.mine_info.token_mint, &self.token_account);
+        let mut tier_info = self.tier_info.clone().into_inner();
         let mut user_info = self.user_info.clone().into_inner();
-        let current_lock_duration = (Clock::get()?.unix_timestamp as u64) - user_info.lock_ts;
-        let current_interest = match calculate_interest(
-            to_u128(user_info.total_locked)?, 
-            to_u128(self.tier_info.apy)?, 
-            to_u128(current_lock_duration)?
-        ) {
-            Ok(val) => val,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        let new_total_locked = user_info.total_locked.saturating_add(actual_amount);
-        let new_interest = match calculate_interest(
-            to_u128(new_total_locked)?, 
-            to_u128(self.tier_info.apy)?, 
-            to_u128(self.tier_info.lock_duration)?
-        ) {
-            Ok(val) => val,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        msg!("Interval: {}, New Accrued Interest: {}", current_lock_duration, new_interest.saturating_add(current_interest));
-        user_info.accrued_interest = new_interest.saturating_add(current_interest);
-        user_info.lock_ts = Clock::get()?.unix_timestamp as u64;
-        user_info.total_locked = new_total_locked;
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        user_info.top_up(&mut tier_info, &mut mine_vault, actual_amount, boost, now)?;
+        user_info.gate_account = Some(self.token_account.key());
+        let new_total_locked = user_info.total_locked;
+        let accrued_interest = user_info.accrued_interest;
+        self.tier_info.set_inner(tier_info);
         self.user_info.set_inner(user_info);
 
-        // Update Tier total locked
-        let mut tier_info = self.tier_info.clone().into_inner();
-        tier_info.total_locked = tier_info.total_locked.saturating_add(actual_amount);
-        self.tier_info.set_inner(tier_info);
+        // Credit the referrer on the top up
+        let bonus = credit_referrer(
+            &self.referrer_info,
+            self.user_info.referrer,
+            0,
+            &self.mine_info,
+            self.user_info.key(),
+            actual_amount,
+            false
+        )?;
+        let bonus = bonus.saturating_add(credit_upline(
+            [&self.second_referrer_info, &self.third_referrer_info],
+            self.user_info.upline_referrers,
+            &self.mine_info,
+            self.user_info.key(),
+            actual_amount,
+            false
+        )?);
+
+        // Update vault liabilities
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_add(bonus);
+        self.mine_vault.set_inner(mine_vault);
+        check_mine_solvency(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault)?;
+        emit!(StakeIncreased {
+            owner: self.signer.key(),
+            user_info: self.user_info.key(),
+            tier: self.tier_info.key(),
+            amount: actual_amount,
+            dev_fee,
+            total_locked: new_total_locked,
+            accrued_interest,
+            boost: self.user_info.boost,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
 
         Ok(())
     }
@@ -1,54 +1,66 @@
 use anchor_lang::prelude::*;
-use crate::states::user::UserInfo;
-use crate::states::mine::{MineInfo, MineVault};
+use crate::states::user::{UserInfo, UserCounter};
+use crate::states::mine::{MineInfo, MineVault, check_mine_solvency};
 use crate::states::referral::ReferralInfo;
 use crate::states::tier::TierInfo;
-use solana_program::system_instruction;
-use anchor_spl::token_interface::TokenAccount;
-use crate::math::{to_u128, calculate_fee, calculate_interest};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use crate::errors::MinerError;
+use crate::events::{StakeDeposited, ReferralCredited, GateEscrowed};
+use crate::contexts::gate_escrow::lock_gate_tokens;
+use crate::transfers::{Deposit, TokenRoute};
 
-/// Instruction to call for new users that have not started staking SOL
-/// in a TIER. Initializes new user info account and accepts lamports to
-/// deposit into the vault.
+/// Instruction to call for new users that have not started staking in a
+/// TIER. Initializes new user info account and accepts lamports to deposit
+/// into the vault, or the stake mint into its token vault when the mine is
+/// in token mode.
 #[derive(Accounts)]
 #[instruction(_tier_nonce: u8)]
 pub struct InitStaking<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserCounter::INIT_SPACE,
+        seeds = [b"user-counter", mine_info.key().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub user_counter: Account<'info, UserCounter>,
     #[account(
         init,
         payer = signer,
         space = 8 + UserInfo::INIT_SPACE,
-        seeds = [b"user", signer.key().as_ref()],
+        seeds = [b"user", mine_info.key().as_ref(), signer.key().as_ref(), user_counter.position_count.to_le_bytes().as_ref()],
         bump
     )]
     pub user_info: Account<'info, UserInfo>,
     #[account(
+        mut,
         constraint = (
-            token_account.mint == mine_info.token_mint &&
-            token_account.amount >= tier_info.minimum_token_amount &&
+            tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint) &&
             token_account.owner == signer.key()
         ) @ MinerError::InvalidTokenAccount
     )]
     pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
     #[account(
-        seeds = [b"mine".as_ref()],
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
         constraint = mine_info.is_active @ MinerError::InvalidMine
     )]
     pub mine_info: Account<'info, MineInfo>,
     #[account(
         mut,
-        seeds = [b"mine-vault"],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump = mine_vault.bump
     )]
     pub mine_vault: Account<'info, MineVault>,
     #[account(
         mut,
-        seeds = [b"tier".as_ref(), &[_tier_nonce]],
+        seeds = [b"tier", mine_info.key().as_ref(), &[_tier_nonce]],
         bump = tier_info.bump,
-        constraint = tier_info.is_active && _tier_nonce == tier_info.nonce @ MinerError::InvalidTier
+        constraint = tier_info.is_active && _tier_nonce == tier_info.nonce @ MinerError::InvalidTier,
+        constraint = mine_info.pays_interest_of(&tier_info) @ MinerError::RewardMintMismatch
     )]
     pub tier_info: Account<'info, TierInfo>,
     #[account(
@@ -56,50 +68,116 @@ pub struct InitStaking<'info> {
         constraint = mine_info.fee_collector == fee_collector.key()
     )]
     pub fee_collector: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"gate-escrow", mine_info.key().as_ref()],
+        bump
+    )]
+    pub gate_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        constraint = token_mint.key() == mine_info.token_mint @ MinerError::InvalidTokenAccount
+    )]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
     pub system_program: Program<'info, System>
 }
 
+/// Instruction to call for new users staking through the referral of an
+/// existing position, see `InitStaking`. The whole referrer chain is
+/// credited on the deposit, so token mode mines need to pay rewards in the
+/// stake mint.
 #[derive(Accounts)]
 #[instruction(_tier_nonce: u8)]
 pub struct InitStakingWithReferrer<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserCounter::INIT_SPACE,
+        seeds = [b"user-counter", mine_info.key().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub user_counter: Account<'info, UserCounter>,
     #[account(
         init,
         payer = signer,
         space = 8 + UserInfo::INIT_SPACE,
-        seeds = [b"user", signer.key().as_ref()],
+        seeds = [b"user", mine_info.key().as_ref(), signer.key().as_ref(), user_counter.position_count.to_le_bytes().as_ref()],
         bump
     )]
     pub user_info: Account<'info, UserInfo>,
     #[account(
+        mut,
         constraint = (
-            token_account.mint == mine_info.token_mint &&
-            token_account.amount >= tier_info.minimum_token_amount &&
+            tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint) &&
             token_account.owner == signer.key()
         ) @ MinerError::InvalidTokenAccount
     )]
     pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
     #[account(
-        seeds = [b"mine"],
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
-        constraint = mine_info.is_active @ MinerError::InvalidMine
+        constraint = mine_info.is_active @ MinerError::InvalidMine,
+        constraint = mine_info.pays_in_stake_mint() @ MinerError::RewardMintMismatch
     )]
     pub mine_info: Account<'info, MineInfo>,
     #[account(
         mut,
-        seeds = [b"mine-vault"],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump = mine_vault.bump
     )]
     pub mine_vault: Account<'info, MineVault>,
     #[account(
         mut,
-        seeds = [b"tier", &[_tier_nonce]],
+        seeds = [b"tier", mine_info.key().as_ref(), &[_tier_nonce]],
         bump = tier_info.bump,
-        constraint = tier_info.is_active && _tier_nonce == tier_info.nonce @ MinerError::InvalidTier
+        constraint = tier_info.is_active && _tier_nonce == tier_info.nonce @ MinerError::InvalidTier,
+        constraint = mine_info.pays_interest_of(&tier_info) @ MinerError::RewardMintMismatch
     )]
     pub tier_info: Account<'info, TierInfo>,
     #[account(
+        seeds = [
+            b"user",
+            mine_info.key().as_ref(),
+            referrer_user_info.owner.as_ref(),
+            referrer_user_info.index.to_le_bytes().as_ref()
+        ],
+        bump = referrer_user_info.bump,
         constraint = signer.key() != referrer_user_info.owner @ MinerError::InvalidReferrer
     )]
     pub referrer_user_info: Account<'info, UserInfo>,
@@ -111,11 +189,63 @@ pub struct InitStakingWithReferrer<'info> {
         bump
     )]
     pub referrer_info: Account<'info, ReferralInfo>,
+    /// CHECK: referral account of whoever referred `referrer_user_info`,
+    /// credited on the new deposit at the second level. Checked against the
+    /// derived address and required while that level pays a reward
+    #[account(mut)]
+    pub second_referrer_info: Option<UncheckedAccount<'info>>,
+    /// CHECK: one level above `second_referrer_info` and checked the same way
+    #[account(mut)]
+    pub third_referrer_info: Option<UncheckedAccount<'info>>,
     #[account(
         mut,
         constraint = mine_info.fee_collector == fee_collector.key() @ MinerError::InvalidFeeCollector
     )]
     pub fee_collector: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"gate-escrow", mine_info.key().as_ref()],
+        bump
+    )]
+    pub gate_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        constraint = token_mint.key() == mine_info.token_mint @ MinerError::InvalidTokenAccount
+    )]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
     pub system_program: Program<'info, System>
 }
 
@@ -124,76 +254,90 @@ impl<'info> InitStaking<'info> {
         &mut self,
         deposit_amount: u64,
         bump: u8,
+        counter_bump: u8,
         _tier_nonce: u8
     ) -> Result<()> {
         if deposit_amount <= 0 {
             return err!(MinerError::InvalidDepositAmount);
         }
-        // Calculate fees and transfer lamports to vault and fee collector
-        let dev_fee = match calculate_fee(to_u128(deposit_amount)?, to_u128(self.mine_info.dev_fee)?) {
-            Ok(fee) => fee,
-            Err(_e) => return err!(MinerError::MathsError)
-        };
-        let actual_amount = match deposit_amount.checked_sub(dev_fee) {
-            Some(val) => val,
-            None => return err!(MinerError::MathsError)
-        };
-        let fee_transfer_ix = system_instruction::transfer(
-            self.signer.key,
-            &self.mine_info.fee_collector,
-            dev_fee
-        );
-        let actual_transfer_ix = system_instruction::transfer(
-            self.signer.key,
-            &self.mine_vault.key(),
-            actual_amount
-        );
-        solana_program::program::invoke_signed(
-            &fee_transfer_ix,
-            &[
-                self.signer.to_account_info(),
-                self.fee_collector.to_account_info(),
-                self.system_program.to_account_info(),
-            ],
-            &[],
-        )?;
-        solana_program::program::invoke_signed(
-            &actual_transfer_ix,
-            &[
-                self.signer.to_account_info(),
-                self.mine_vault.to_account_info(),
-                self.system_program.to_account_info(),
-            ],
-            &[],
+        // Calculate fees and take the deposit into the vault
+        let (dev_fee, actual_amount) = Deposit {
+            signer: &self.signer,
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            system_program: &self.system_program,
+            tokens: TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.stake_mint,
+                vault: &self.stake_vault,
+                holder: &self.user_stake_account,
+                fee_collector: &self.fee_collector_stake_account,
+                penalty_collector: &None
+            }
+        }.take(deposit_amount, &self.fee_collector.to_account_info())?;
+
+        // Lock the gating tokens for escrow gated tiers
+        let escrowed_amount = lock_gate_tokens(
+            &self.tier_info,
+            &self.token_account,
+            &mut self.gate_escrow,
+            &self.token_mint,
+            &self.token_program,
+            &self.signer
         )?;
 
-        // Initialize User info
-        let interest_accrued = match calculate_interest(
-            to_u128(actual_amount)?, 
-            to_u128(self.tier_info.apy)?, 
-            to_u128(self.tier_info.lock_duration)?
-        ) {
-            Ok(interest) => interest,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        msg!(
-            "Interest: {}, APY: {}, Duration: {}, Dev Fee: {}, Total Locked: {}", 
-            interest_accrued, self.tier_info.apy, self.tier_info.lock_duration, dev_fee, actual_amount
-        );
-        self.user_info.set_inner(UserInfo {
+        // Initialize User info, only fixed tiers promise interest upfront
+        let boost = self.tier_info.boost_for(&self.mine_info.token_mint, &self.token_account);
+        let mut tier_info = self.tier_info.clone().into_inner();
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        let mut user_info = UserInfo::new(
             bump,
+            self.signer.key(),
+            self.user_counter.position_count,
+            self.tier_info.key()
+        );
+        user_info.open(
+            &mut tier_info,
+            &mut mine_vault,
+            actual_amount,
+            boost,
+            Clock::get()?.unix_timestamp as u64
+        )?;
+        user_info.escrowed_amount = escrowed_amount;
+        user_info.gate_account = Some(self.token_account.key());
+        let interest_accrued = user_info.accrued_interest;
+        self.user_info.set_inner(user_info);
+        self.tier_info.set_inner(tier_info);
+        emit!(StakeDeposited {
             owner: self.signer.key(),
-            total_locked: actual_amount,
-            accrued_interest: interest_accrued,
-            lock_ts: Clock::get()?.unix_timestamp as u64,
+            user_info: self.user_info.key(),
             tier: self.tier_info.key(),
-            is_whitelist: false
+            amount: actual_amount,
+            dev_fee,
+            accrued_interest: interest_accrued,
+            is_whitelist: false,
+            boost,
+            timestamp: Clock::get()?.unix_timestamp as u64
         });
+        if escrowed_amount > 0 {
+            emit!(GateEscrowed {
+                owner: self.signer.key(),
+                user_info: self.user_info.key(),
+                amount: escrowed_amount,
+                timestamp: Clock::get()?.unix_timestamp as u64
+            });
+        }
 
-        // Update Tier total locked
-        let mut tier_info = self.tier_info.clone().into_inner();
-        tier_info.total_locked = tier_info.total_locked.saturating_add(actual_amount);
-        self.tier_info.set_inner(tier_info);
+        // Update User position counter
+        let mut user_counter = self.user_counter.clone().into_inner();
+        user_counter.owner = self.signer.key();
+        user_counter.bump = counter_bump;
+        user_counter.position_count += 1;
+        self.user_counter.set_inner(user_counter);
+
+        // Update vault liabilities
+        self.mine_vault.set_inner(mine_vault);
+        check_mine_solvency(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault)?;
         Ok(())
     }
 }
@@ -203,98 +347,228 @@ impl<'info> InitStakingWithReferrer<'info> {
         &mut self,
         deposit_amount: u64,
         bump1: u8,
+        counter_bump: u8,
         bump2: u8,
         _tier_nonce: u8
     ) -> Result<()> {
         if deposit_amount <= 0 {
             return err!(MinerError::InvalidDepositAmount);
         }
-        // Calculate fees and transfer lamports to vault and fee collector
-        let dev_fee = match calculate_fee(to_u128(deposit_amount)?, to_u128(self.mine_info.dev_fee)?) {
-            Ok(fee) => fee,
-            Err(_e) => return err!(MinerError::MathsError)
-        };
-        let actual_amount = match deposit_amount.checked_sub(dev_fee) {
-            Some(val) => val,
-            None => return err!(MinerError::MathsError)
-        };
-        let fee_transfer_ix = system_instruction::transfer(
-            self.signer.key,
-            &self.mine_info.fee_collector,
-            dev_fee
-        );
-        let actual_transfer_ix = system_instruction::transfer(
-            self.signer.key,
-            &self.mine_vault.key(),
-            actual_amount
-        );
-        solana_program::program::invoke_signed(
-            &fee_transfer_ix,
-            &[
-                self.signer.to_account_info(),
-                self.fee_collector.to_account_info(),
-                self.system_program.to_account_info(),
-            ],
-            &[],
-        )?;
-        solana_program::program::invoke_signed(
-            &actual_transfer_ix,
-            &[
-                self.signer.to_account_info(),
-                self.mine_vault.to_account_info(),
-                self.system_program.to_account_info(),
-            ],
-            &[],
+        // Calculate fees and take the deposit into the vault
+        let (dev_fee, actual_amount) = Deposit {
+            signer: &self.signer,
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            system_program: &self.system_program,
+            tokens: TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.stake_mint,
+                vault: &self.stake_vault,
+                holder: &self.user_stake_account,
+                fee_collector: &self.fee_collector_stake_account,
+                penalty_collector: &None
+            }
+        }.take(deposit_amount, &self.fee_collector.to_account_info())?;
+
+        // Lock the gating tokens for escrow gated tiers
+        let escrowed_amount = lock_gate_tokens(
+            &self.tier_info,
+            &self.token_account,
+            &mut self.gate_escrow,
+            &self.token_mint,
+            &self.token_program,
+            &self.signer
         )?;
 
-        // Initialize User info
-        let interest_accrued = match calculate_interest(
-            to_u128(actual_amount)?, 
-            to_u128(self.tier_info.apy)?, 
-            to_u128(self.tier_info.lock_duration)?
-        ) {
-            Ok(interest) => interest,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        msg!(
-            "Interest: {}, APY: {}, Duration: {}, Dev Fee: {}, Total Locked: {}", 
-            interest_accrued, self.tier_info.apy, self.tier_info.lock_duration, dev_fee, actual_amount
+        // Initialize User info, only fixed tiers promise interest upfront
+        let boost = self.tier_info.boost_for(&self.mine_info.token_mint, &self.token_account);
+        let mut tier_info = self.tier_info.clone().into_inner();
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        let mut user_info = UserInfo::new(
+            bump1,
+            self.signer.key(),
+            self.user_counter.position_count,
+            self.tier_info.key()
         );
-        self.user_info.set_inner(UserInfo {
-            bump: bump1,
+        user_info.open(
+            &mut tier_info,
+            &mut mine_vault,
+            actual_amount,
+            boost,
+            Clock::get()?.unix_timestamp as u64
+        )?;
+        user_info.escrowed_amount = escrowed_amount;
+        user_info.gate_account = Some(self.token_account.key());
+        user_info.referrer = Some(self.referrer_user_info.key());
+        user_info.upline_referrers = [
+            self.referrer_user_info.referrer,
+            self.referrer_user_info.upline_referrers[0]
+        ];
+        let interest_accrued = user_info.accrued_interest;
+        self.user_info.set_inner(user_info);
+        self.tier_info.set_inner(tier_info);
+        emit!(StakeDeposited {
             owner: self.signer.key(),
-            total_locked: actual_amount,
-            accrued_interest: interest_accrued,
-            lock_ts: Clock::get()?.unix_timestamp as u64,
+            user_info: self.user_info.key(),
             tier: self.tier_info.key(),
-            is_whitelist: false
+            amount: actual_amount,
+            dev_fee,
+            accrued_interest: interest_accrued,
+            is_whitelist: false,
+            boost,
+            timestamp: Clock::get()?.unix_timestamp as u64
         });
+        if escrowed_amount > 0 {
+            emit!(GateEscrowed {
+                owner: self.signer.key(),
+                user_info: self.user_info.key(),
+                amount: escrowed_amount,
+                timestamp: Clock::get()?.unix_timestamp as u64
+            });
+        }
 
-        // Update Tier total locked
-        let mut tier_info = self.tier_info.clone().into_inner();
-        tier_info.total_locked = tier_info.total_locked.saturating_add(actual_amount);
-        self.tier_info.set_inner(tier_info);
+        // Update User position counter
+        let mut user_counter = self.user_counter.clone().into_inner();
+        user_counter.owner = self.signer.key();
+        user_counter.bump = counter_bump;
+        user_counter.position_count += 1;
+        self.user_counter.set_inner(user_counter);
 
         // Handle referral
-        let bonus = match calculate_fee(
-            to_u128(actual_amount)?, 
-            to_u128(self.mine_info.referral_reward)?
-        ) {
-            Ok(val) => val,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        let mut referral_info = self.referrer_info.clone().into_inner();
-        if referral_info.earnings == 0 {
-            referral_info.earnings = bonus;
-            referral_info.bump = bump2;
-            referral_info.count = 1;
-            referral_info.owner = self.referrer_user_info.owner;
-            referral_info.user_info = self.referrer_user_info.key();
-        } else {
-            referral_info.count += 1;
-            referral_info.earnings = referral_info.earnings.saturating_add(bonus);
-        }
-        self.referrer_info.set_inner(referral_info);
+        let bonus = credit_new_referral(
+            &mut self.referrer_info,
+            &self.referrer_user_info,
+            [&self.second_referrer_info, &self.third_referrer_info],
+            &self.mine_info,
+            self.user_info.key(),
+            actual_amount,
+            bump2
+        )?;
+
+        // Update vault liabilities
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_add(bonus);
+        self.mine_vault.set_inner(mine_vault);
+        check_mine_solvency(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Records a new referral of `referrer_user_info`, setting up its referral
+/// account on the first one, and credits the whole referrer chain with the
+/// reward of its level on the deposit. Returns the total bonus.
+pub fn credit_new_referral<'info>(
+    referrer_info: &mut Account<'info, ReferralInfo>,
+    referrer_user_info: &Account<'info, UserInfo>,
+    upline_info: [&Option<UncheckedAccount<'info>>; 2],
+    mine_info: &MineInfo,
+    user_info: Pubkey,
+    amount: u64,
+    bump: u8
+) -> Result<u64> {
+    let mut referral_info = referrer_info.clone().into_inner();
+    if referral_info.count == 0 {
+        referral_info.bump = bump;
+        referral_info.owner = referrer_user_info.owner;
+        referral_info.user_info = referrer_user_info.key();
+    }
+    referral_info.add_referral(0);
+    let direct_bonus = referral_info.credit(0, amount, mine_info.referral_reward)?;
+    referrer_info.set_inner(referral_info);
+    emit!(ReferralCredited {
+        referrer: referrer_user_info.owner,
+        referrer_info: referrer_info.key(),
+        user_info,
+        level: 0,
+        bonus: direct_bonus,
+        timestamp: Clock::get()?.unix_timestamp as u64
+    });
+    let upline_bonus = credit_upline(
+        upline_info,
+        [referrer_user_info.referrer, referrer_user_info.upline_referrers[0]],
+        mine_info,
+        user_info,
+        amount,
+        true
+    )?;
+    Ok(direct_bonus.saturating_add(upline_bonus))
+}
+
+/// Credits the referrers above the direct one with the reward of their level
+/// on a deposit, see `credit_referrer`. Returns the total bonus.
+pub fn credit_upline<'info>(
+    upline_info: [&Option<UncheckedAccount<'info>>; 2],
+    upline: [Option<Pubkey>; 2],
+    mine_info: &MineInfo,
+    user_info: Pubkey,
+    amount: u64,
+    is_new_referral: bool
+) -> Result<u64> {
+    let mut total_bonus = 0_u64;
+    for (index, (referrer_info, referrer)) in upline_info.into_iter().zip(upline).enumerate() {
+        let bonus = credit_referrer(
+            referrer_info,
+            referrer,
+            index + 1,
+            mine_info,
+            user_info,
+            amount,
+            is_new_referral
+        )?;
+        total_bonus = total_bonus.saturating_add(bonus);
+    }
+    Ok(total_bonus)
+}
+
+/// Credits the referral account of the `referrer` position with the reward of
+/// `level` on `amount`. The account is required whenever the position has a
+/// referrer at that level and the level pays a reward. A referrer that has
+/// since closed its position no longer has a referral account and is skipped.
+/// Returns the bonus credited.
+pub fn credit_referrer<'info>(
+    referrer_info: &Option<UncheckedAccount<'info>>,
+    referrer: Option<Pubkey>,
+    level: usize,
+    mine_info: &MineInfo,
+    user_info: Pubkey,
+    amount: u64,
+    is_new_referral: bool
+) -> Result<u64> {
+    let referral_reward = mine_info.referral_rate(level);
+    let Some(referrer) = referrer else {
+        return Ok(0);
+    };
+    if referral_reward == 0 {
+        return Ok(0);
+    }
+    let Some(referrer_info) = referrer_info else {
+        return err!(MinerError::InvalidReferrer);
+    };
+    let (referral_address, _) = Pubkey::find_program_address(
+        &[b"referral", referrer.as_ref()],
+        &crate::ID
+    );
+    if referrer_info.key() != referral_address {
+        return err!(MinerError::InvalidReferrer);
+    }
+    if referrer_info.data_is_empty() {
+        return Ok(0);
+    }
+    let mut referral_info = {
+        let data = referrer_info.try_borrow_data()?;
+        ReferralInfo::try_deserialize(&mut &data[..])?
+    };
+    if is_new_referral {
+        referral_info.add_referral(level);
+    }
+    let bonus = referral_info.credit(level, amount, referral_reward)?;
+    referral_info.try_serialize(&mut &mut referrer_info.try_borrow_mut_data()?[..])?;
+    emit!(ReferralCredited {
+        referrer: referral_info.owner,
+        referrer_info: referrer_info.key(),
+        user_info,
+        level: level as u8,
+        bonus,
+        timestamp: Clock::get()?.unix_timestamp as u64
+    });
+    Ok(bonus)
+}
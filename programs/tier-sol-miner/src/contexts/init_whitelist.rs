@@ -1,52 +1,64 @@
 use anchor_lang::prelude::*;
-use solana_program::system_instruction;
-use crate::states::mine::{MineInfo, MineVault};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault, check_mine_solvency};
 use crate::states::tier::TierInfo;
-use crate::states::user::UserInfo;
+use crate::states::user::{UserInfo, UserCounter};
 use crate::states::whitelist::WhitelistInfo;
+use crate::states::allowlist::AllowList;
 use crate::errors::MinerError;
-use crate::math::{calculate_fee, to_u128, calculate_interest};
+use crate::events::StakeDeposited;
+use crate::transfers::{Deposit, TokenRoute};
 
-/// Instruction to call for whitelisted users that have not started staking SOL
-/// in a TIER. Initializes new user info account and accepts lamports to
-/// deposit into the vault. Whitelisted users does not need to hold tokens
+/// Instruction to call for whitelisted users that have not started staking
+/// in a TIER. Initializes new user info account and accepts lamports, or the
+/// stake mint of a token mode mine, to deposit into the vault. Whitelisted
+/// users does not need to hold tokens
 #[derive(Accounts)]
 #[instruction(_tier_nonce: u8)]
 pub struct InitWhiteList<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserCounter::INIT_SPACE,
+        seeds = [b"user-counter", mine_info.key().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub user_counter: Account<'info, UserCounter>,
     #[account(
         init,
         payer = signer,
         space = 8 + UserInfo::INIT_SPACE,
-        seeds = [b"user", signer.key().as_ref()],
+        seeds = [b"user", mine_info.key().as_ref(), signer.key().as_ref(), user_counter.position_count.to_le_bytes().as_ref()],
         bump
     )]
     pub user_info: Account<'info, UserInfo>,
     #[account(
-        seeds = [b"mine"],
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
         constraint = mine_info.is_active @ MinerError::InvalidMine
     )]
     pub mine_info: Account<'info, MineInfo>,
     #[account(
         mut,
-        seeds = [b"mine-vault"],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump = mine_vault.bump
     )]
     pub mine_vault: Account<'info, MineVault>,
     #[account(
         mut,
-        seeds = [b"whitelist", signer.key().as_ref()],
+        seeds = [b"whitelist", mine_info.key().as_ref(), signer.key().as_ref()],
         bump = whitelist_info.bump,
         close = signer
     )]
     pub whitelist_info: Account<'info, WhitelistInfo>,
     #[account(
         mut,
-        seeds = [b"tier", &[_tier_nonce]],
+        seeds = [b"tier", mine_info.key().as_ref(), &[_tier_nonce]],
         bump = tier_info.bump,
-        constraint = tier_info.is_active && whitelist_info.tier == tier_info.key() && _tier_nonce == tier_info.nonce @ MinerError::InvalidTier
+        constraint = tier_info.is_active && whitelist_info.tier == tier_info.key() && _tier_nonce == tier_info.nonce @ MinerError::InvalidTier,
+        constraint = mine_info.pays_interest_of(&tier_info) @ MinerError::RewardMintMismatch
     )]
     pub tier_info: Account<'info, TierInfo>,
     #[account(
@@ -54,6 +66,132 @@ pub struct InitWhiteList<'info> {
         constraint = mine_info.fee_collector == fee_collector.key()
     )]
     pub fee_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>
+}
+
+/// Instruction to call for users whitelisted through a published merkle root.
+/// Verifies the proof, marks the leaf as claimed and initializes the user
+/// info account the same way `initialize_whitelist` does.
+#[derive(Accounts)]
+#[instruction(_tier_nonce: u8)]
+pub struct InitAllowList<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + UserCounter::INIT_SPACE,
+        seeds = [b"user-counter", mine_info.key().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub user_counter: Account<'info, UserCounter>,
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + UserInfo::INIT_SPACE,
+        seeds = [b"user", mine_info.key().as_ref(), signer.key().as_ref(), user_counter.position_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub user_info: Account<'info, UserInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.is_active @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        mut,
+        seeds = [b"allowlist", mine_info.key().as_ref(), allowlist.root.as_ref()],
+        bump = allowlist.bump
+    )]
+    pub allowlist: Account<'info, AllowList>,
+    #[account(
+        mut,
+        seeds = [b"tier", mine_info.key().as_ref(), &[_tier_nonce]],
+        bump = tier_info.bump,
+        constraint = tier_info.is_active && allowlist.tier == tier_info.key() && _tier_nonce == tier_info.nonce @ MinerError::InvalidTier,
+        constraint = mine_info.pays_interest_of(&tier_info) @ MinerError::RewardMintMismatch
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        mut,
+        constraint = mine_info.fee_collector == fee_collector.key()
+    )]
+    pub fee_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
     pub system_program: Program<'info, System>
 }
 
@@ -62,6 +200,7 @@ impl<'info> InitWhiteList<'info> {
         &mut self,
         deposit_amount: u64,
         bump: u8,
+        counter_bump: u8,
         _tier_nonce: u8
     ) -> Result<()> {
         if deposit_amount <= 0 {
@@ -71,67 +210,140 @@ impl<'info> InitWhiteList<'info> {
             return err!(MinerError::ExpiredWhiteList);
         }
 
-        // Calculate fees and transfer lamports to vault and fee collector
-        let dev_fee = match calculate_fee(
-            to_u128(deposit_amount)?, 
-            to_u128(self.mine_info.dev_fee)?
-        ) {
-            Ok(fee) => fee,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        let actual_amount = deposit_amount.saturating_sub(dev_fee);
-        let fee_transfer_ix = system_instruction::transfer(
-            self.signer.key,
-            &self.mine_info.fee_collector,
-            dev_fee
-        );
-        let actual_transfer_ix = system_instruction::transfer(
-            self.signer.key,
-            &self.mine_vault.key(),
-            actual_amount
-        );
-        solana_program::program::invoke_signed(
-            &fee_transfer_ix,
-            &[
-                self.signer.to_account_info(),
-                self.fee_collector.to_account_info(),
-                self.system_program.to_account_info(),
-            ],
-            &[],
-        )?;
-        solana_program::program::invoke_signed(
-            &actual_transfer_ix,
-            &[
-                self.signer.to_account_info(),
-                self.mine_vault.to_account_info(),
-                self.system_program.to_account_info(),
-            ],
-            &[],
-        )?;
+        let (dev_fee, actual_amount) = Deposit {
+            signer: &self.signer,
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            system_program: &self.system_program,
+            tokens: TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.stake_mint,
+                vault: &self.stake_vault,
+                holder: &self.user_stake_account,
+                fee_collector: &self.fee_collector_stake_account,
+                penalty_collector: &None
+            }
+        }.take(deposit_amount, &self.fee_collector.to_account_info())?;
+        WhitelistDeposit {
+            signer: &self.signer,
+            user_counter: &mut self.user_counter,
+            user_info: &mut self.user_info,
+            mine_info: &self.mine_info,
+            mine_vault: &mut self.mine_vault,
+            tier_info: &mut self.tier_info,
+            stake_vault: &self.stake_vault,
+            reward_vault: &self.reward_vault
+        }.open(actual_amount, dev_fee, bump, counter_bump)
+    }
+}
 
-        // Initialize User info
-        let interest_accrued = match calculate_interest(
-            to_u128(actual_amount)?, 
-            to_u128(self.tier_info.apy)?, 
-            to_u128(self.tier_info.lock_duration)?
-        ) {
-            Ok(interest) => interest,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        self.user_info.set_inner(UserInfo {
+impl<'info> InitAllowList<'info> {
+    pub fn consume_allowlist(
+        &mut self,
+        deposit_amount: u64,
+        bump: u8,
+        counter_bump: u8,
+        leaf_index: u32,
+        proof: Vec<[u8; 32]>,
+        _tier_nonce: u8
+    ) -> Result<()> {
+        if deposit_amount <= 0 {
+            return err!(MinerError::InvalidDepositAmount);
+        }
+        let mut allowlist = self.allowlist.clone().into_inner();
+        allowlist.claim(leaf_index, self.signer.key, &proof, Clock::get()?.unix_timestamp as u64)?;
+        self.allowlist.set_inner(allowlist);
+
+        let (dev_fee, actual_amount) = Deposit {
+            signer: &self.signer,
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            system_program: &self.system_program,
+            tokens: TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.stake_mint,
+                vault: &self.stake_vault,
+                holder: &self.user_stake_account,
+                fee_collector: &self.fee_collector_stake_account,
+                penalty_collector: &None
+            }
+        }.take(deposit_amount, &self.fee_collector.to_account_info())?;
+        WhitelistDeposit {
+            signer: &self.signer,
+            user_counter: &mut self.user_counter,
+            user_info: &mut self.user_info,
+            mine_info: &self.mine_info,
+            mine_vault: &mut self.mine_vault,
+            tier_info: &mut self.tier_info,
+            stake_vault: &self.stake_vault,
+            reward_vault: &self.reward_vault
+        }.open(actual_amount, dev_fee, bump, counter_bump)
+    }
+}
+
+/// Accounts shared by the whitelist and allowlist deposits
+struct WhitelistDeposit<'a, 'info> {
+    signer: &'a Signer<'info>,
+    user_counter: &'a mut Account<'info, UserCounter>,
+    user_info: &'a mut Account<'info, UserInfo>,
+    mine_info: &'a Account<'info, MineInfo>,
+    mine_vault: &'a mut Account<'info, MineVault>,
+    tier_info: &'a mut Account<'info, TierInfo>,
+    stake_vault: &'a Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    reward_vault: &'a Option<Box<InterfaceAccount<'info, TokenAccount>>>
+}
+
+impl<'a, 'info> WhitelistDeposit<'a, 'info> {
+    /// Opens a whitelisted position for the signer on the deposit taken
+    fn open(
+        self,
+        actual_amount: u64,
+        dev_fee: u64,
+        bump: u8,
+        counter_bump: u8
+    ) -> Result<()> {
+        // Initialize User info, only fixed tiers promise interest upfront
+        let mut tier_info = self.tier_info.clone().into_inner();
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        let mut user_info = UserInfo::new(
             bump,
+            self.signer.key(),
+            self.user_counter.position_count,
+            self.tier_info.key()
+        );
+        user_info.open(
+            &mut tier_info,
+            &mut mine_vault,
+            actual_amount,
+            0,
+            Clock::get()?.unix_timestamp as u64
+        )?;
+        user_info.is_whitelist = true;
+        let interest_accrued = user_info.accrued_interest;
+        self.user_info.set_inner(user_info);
+        self.tier_info.set_inner(tier_info);
+        emit!(StakeDeposited {
             owner: self.signer.key(),
-            total_locked: actual_amount,
-            accrued_interest: interest_accrued,
-            lock_ts: Clock::get()?.unix_timestamp as u64,
+            user_info: self.user_info.key(),
             tier: self.tier_info.key(),
-            is_whitelist: true
+            amount: actual_amount,
+            dev_fee,
+            accrued_interest: interest_accrued,
+            is_whitelist: true,
+            boost: 0,
+            timestamp: Clock::get()?.unix_timestamp as u64
         });
 
-        // Update Tier total locked
-        let mut tier_info = self.tier_info.clone().into_inner();
-        tier_info.total_locked = tier_info.total_locked.saturating_add(actual_amount);
-        self.tier_info.set_inner(tier_info);
+        // Update User position counter
+        let mut user_counter = self.user_counter.clone().into_inner();
+        user_counter.owner = self.signer.key();
+        user_counter.bump = counter_bump;
+        user_counter.position_count += 1;
+        self.user_counter.set_inner(user_counter);
+
+        // Update vault liabilities
+        self.mine_vault.set_inner(mine_vault);
+        check_mine_solvency(self.mine_info, self.mine_vault, self.stake_vault, self.reward_vault)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
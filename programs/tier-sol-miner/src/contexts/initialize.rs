@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
-use crate::states::mine::{MineVault, MineInfo};
+use crate::states::mine::{MineConfig, MineVault, MineInfo};
+use crate::states::roles::MineRoles;
+use crate::events::MineUpdated;
 
 #[derive(Accounts)]
+#[instruction(id: u64)]
 pub struct Initialize<'info> {
     #[account(mut)]
     pub initializer: Signer<'info>,
@@ -9,7 +12,7 @@ pub struct Initialize<'info> {
         init,
         payer = initializer,
         space = 8 + MineInfo::INIT_SPACE,
-        seeds = [b"mine".as_ref()],
+        seeds = [b"mine", initializer.key().as_ref(), id.to_le_bytes().as_ref()],
         bump
     )]
     pub mine_info: Account<'info, MineInfo>,
@@ -17,39 +20,77 @@ pub struct Initialize<'info> {
         init,
         payer = initializer,
         space = 8 + MineVault::INIT_SPACE,
-        seeds = [b"mine-vault".as_ref()],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump
     )]
     pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        init,
+        payer = initializer,
+        space = 8 + MineRoles::INIT_SPACE,
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump
+    )]
+    pub roles: Account<'info, MineRoles>,
     pub system_program: Program<'info, System>
 }
 
 impl<'info> Initialize<'info> {
     pub fn initialize_mine(
         &mut self,
+        id: u64,
         bump1: u8,
         bump2: u8,
-        fee_collector: Pubkey,
-        penalty_fee_collector: Pubkey,
-        token_mint: Pubkey,
-        dev_fee: u64,
-        early_withdrawal_fee: u64,
-        referral_reward: u64
+        bump3: u8,
+        config: MineConfig
     ) -> Result<()> {
         self.mine_info.set_inner(MineInfo {
+            creator: *self.initializer.key,
+            id,
             admin: *self.initializer.key,
-            token_mint,
-            fee_collector,
-            penalty_fee_collector,
-            dev_fee,
-            early_withdrawal_fee,
-            referral_reward,
+            token_mint: config.token_mint,
+            fee_collector: config.fee_collector,
+            penalty_fee_collector: config.penalty_fee_collector,
+            dev_fee: config.dev_fee,
+            early_withdrawal_fee: config.early_withdrawal_fee,
+            referral_reward: config.referral_reward,
             is_active: true,
             bump: bump1,
-            current_tier_nonce: 0
+            current_tier_nonce: 0,
+            pending_admin: None,
+            timelock_delay: config.timelock_delay,
+            max_dev_fee: config.max_dev_fee,
+            max_early_withdrawal_fee: config.max_early_withdrawal_fee,
+            max_referral_reward: config.max_referral_reward,
+            stake_mint: None,
+            reward_mint: None,
+            referral_on_claim: false,
+            upline_referral_rewards: [0; 2]
         });
+        self.mine_info.validate_fees()?;
         self.mine_vault.set_inner(MineVault {
-            bump: bump2
+            bump: bump2,
+            total_principal: 0,
+            total_promised_interest: 0
+        });
+        self.roles.set_inner(MineRoles {
+            tier_manager: *self.initializer.key,
+            whitelister: *self.initializer.key,
+            fee_manager: *self.initializer.key,
+            pauser: *self.initializer.key,
+            bump: bump3
+        });
+        emit!(MineUpdated {
+            mine: self.mine_info.key(),
+            fee_collector: config.fee_collector,
+            penalty_fee_collector: config.penalty_fee_collector,
+            dev_fee: config.dev_fee,
+            early_withdrawal_fee: config.early_withdrawal_fee,
+            referral_reward: config.referral_reward,
+            referral_on_claim: false,
+            upline_referral_rewards: [0; 2],
+            is_active: true,
+            timestamp: Clock::get()?.unix_timestamp as u64
         });
         Ok(())
     }
@@ -0,0 +1,187 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault, check_mine_solvency};
+use crate::states::tier::TierInfo;
+use crate::states::user::UserInfo;
+use crate::errors::MinerError;
+use crate::events::TierMigrated;
+use crate::transfers::{Payer, TokenRoute};
+
+/// Instruction to call for users to move their locked stake to another TIER.
+/// Interest earned so far is settled and the lock restarts in the
+/// destination TIER. Moving before the lock ends pays the early withdrawal
+/// fee on the settled interest unless the destination lock is not shorter.
+/// Token mode mines pay the settled interest in the reward mint.
+#[derive(Accounts)]
+#[instruction(_to_tier_nonce: u8)]
+pub struct MigrateTier<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
+        constraint = !user_info.is_whitelist @ MinerError::OperationNotAllowed,
+        constraint = user_info.receipt_amount == 0 @ MinerError::ReceiptsOutstanding,
+        constraint = (
+            !to_tier_info.escrow_gating ||
+            user_info.escrow_covers(&mine_info.token_mint, &to_tier_info)
+        ) @ MinerError::GateEscrowRequired
+    )]
+    pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        constraint = (
+            (
+                to_tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint) ||
+                user_info.escrow_covers(&mine_info.token_mint, &to_tier_info)
+            ) &&
+            token_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.is_active @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        mut,
+        constraint = from_tier_info.key() == user_info.tier @ MinerError::InvalidTier
+    )]
+    pub from_tier_info: Account<'info, TierInfo>,
+    #[account(
+        mut,
+        seeds = [b"tier", mine_info.key().as_ref(), &[_to_tier_nonce]],
+        bump = to_tier_info.bump,
+        constraint = (
+            to_tier_info.is_active &&
+            _to_tier_nonce == to_tier_info.nonce &&
+            to_tier_info.key() != from_tier_info.key()
+        ) @ MinerError::InvalidTier,
+        constraint = mine_info.pays_interest_of(&to_tier_info) @ MinerError::RewardMintMismatch
+    )]
+    pub to_tier_info: Account<'info, TierInfo>,
+    #[account(
+        mut,
+        constraint = mine_info.fee_collector == fee_collector.key()
+    )]
+    pub fee_collector: SystemAccount<'info>,
+    #[account(
+        mut,
+        constraint = mine_info.penalty_fee_collector == penalty_collector.key()
+    )]
+    pub penalty_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(user_reward_account.mint) &&
+            user_reward_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(fee_collector_reward_account.mint) &&
+            fee_collector_reward_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(penalty_collector_reward_account.mint) &&
+            penalty_collector_reward_account.owner == mine_info.penalty_fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub penalty_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>
+}
+
+impl<'info> MigrateTier<'info> {
+    pub fn migrate_tier(
+        &mut self,
+        _to_tier_nonce: u8
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp as u64;
+        let boost = self.to_tier_info.boost_for(&self.mine_info.token_mint, &self.token_account);
+        let mut from_tier_info = self.from_tier_info.clone().into_inner();
+        let mut to_tier_info = self.to_tier_info.clone().into_inner();
+        let mut user_info = self.user_info.clone().into_inner();
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        let (earned_interest, payout) = user_info.migrate(
+            &mut from_tier_info,
+            &mut to_tier_info,
+            &self.mine_info,
+            &mut mine_vault,
+            boost,
+            now
+        )?;
+        user_info.tier = self.to_tier_info.key();
+        user_info.gate_account = Some(self.token_account.key());
+        let new_interest = user_info.accrued_interest;
+        self.from_tier_info.set_inner(from_tier_info);
+        self.to_tier_info.set_inner(to_tier_info);
+        self.user_info.set_inner(user_info);
+
+        // Send the settled interest and fees
+        Payer {
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            owner: self.signer.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            penalty_collector: self.penalty_collector.to_account_info()
+        }.send(&payout, &TokenRoute {
+            token_program: &self.token_program,
+            mint: &self.reward_mint,
+            vault: &self.reward_vault,
+            holder: &self.user_reward_account,
+            fee_collector: &self.fee_collector_reward_account,
+            penalty_collector: &self.penalty_collector_reward_account
+        })?;
+
+        // Update vault liabilities
+        self.mine_vault.set_inner(mine_vault);
+        check_mine_solvency(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault)?;
+
+        emit!(TierMigrated {
+            owner: self.signer.key(),
+            user_info: self.user_info.key(),
+            from_tier: self.from_tier_info.key(),
+            to_tier: self.to_tier_info.key(),
+            settled_interest: earned_interest,
+            dev_fee: payout.dev_fee,
+            penalty: payout.penalty,
+            accrued_interest: new_interest,
+            timestamp: now
+        });
+        Ok(())
+    }
+}
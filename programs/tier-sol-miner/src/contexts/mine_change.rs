@@ -0,0 +1,196 @@
+use anchor_lang::prelude::*;
+use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
+use crate::states::pending::PendingMineChange;
+use crate::errors::MinerError;
+use crate::events::{MineChangeQueued, MineUpdated, ChangeCancelled};
+
+/// Instruction for the fee manager to queue a fee or timelock change. The
+/// change can only be executed once the mine's timelock delay has passed.
+#[derive(Accounts)]
+pub struct QueueMineChange<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + PendingMineChange::INIT_SPACE,
+        seeds = [b"pending-mine", mine_info.key().as_ref()],
+        bump
+    )]
+    pub pending_change: Account<'info, PendingMineChange>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_fee_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+    pub system_program: Program<'info, System>
+}
+
+/// Instruction anyone can call to apply a queued mine change after its
+/// effective timestamp. Rent goes back to the proposer.
+#[derive(Accounts)]
+pub struct ExecuteMineChange<'info> {
+    pub executor: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"pending-mine", mine_info.key().as_ref()],
+        bump = pending_change.bump,
+        close = proposer
+    )]
+    pub pending_change: Account<'info, PendingMineChange>,
+    #[account(
+        mut,
+        constraint = pending_change.proposer == proposer.key() @ MinerError::InvalidArgument
+    )]
+    pub proposer: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>
+}
+
+/// Instruction for the fee manager to drop a queued mine change
+#[derive(Accounts)]
+pub struct CancelMineChange<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"pending-mine", mine_info.key().as_ref()],
+        bump = pending_change.bump,
+        close = proposer
+    )]
+    pub pending_change: Account<'info, PendingMineChange>,
+    #[account(
+        mut,
+        constraint = pending_change.proposer == proposer.key() @ MinerError::InvalidArgument
+    )]
+    pub proposer: SystemAccount<'info>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_fee_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>
+}
+
+impl<'info> QueueMineChange<'info> {
+    pub fn queue_mine_change(
+        &mut self,
+        dev_fee: Option<u64>,
+        early_withdrawal_fee: Option<u64>,
+        referral_reward: Option<u64>,
+        upline_referral_rewards: Option<[u64; 2]>,
+        timelock_delay: Option<u64>,
+        bump: u8
+    ) -> Result<()> {
+        if dev_fee.is_none()
+            && early_withdrawal_fee.is_none()
+            && referral_reward.is_none()
+            && upline_referral_rewards.is_none()
+            && timelock_delay.is_none() {
+            return err!(MinerError::InvalidArgument);
+        }
+        // Reject fees above their caps before they sit in the queue
+        let mut prospective = self.mine_info.clone().into_inner();
+        if let Some(val) = dev_fee {
+            prospective.dev_fee = val;
+        }
+        if let Some(val) = early_withdrawal_fee {
+            prospective.early_withdrawal_fee = val;
+        }
+        if let Some(val) = referral_reward {
+            prospective.referral_reward = val;
+        }
+        if let Some(val) = upline_referral_rewards {
+            prospective.upline_referral_rewards = val;
+        }
+        prospective.validate_fees()?;
+        let effective_ts = (Clock::get()?.unix_timestamp as u64).saturating_add(self.mine_info.timelock_delay);
+        self.pending_change.set_inner(PendingMineChange {
+            proposer: self.admin.key(),
+            dev_fee,
+            early_withdrawal_fee,
+            referral_reward,
+            upline_referral_rewards,
+            timelock_delay,
+            effective_ts,
+            bump
+        });
+        emit!(MineChangeQueued {
+            dev_fee,
+            early_withdrawal_fee,
+            referral_reward,
+            upline_referral_rewards,
+            timelock_delay,
+            effective_ts
+        });
+        Ok(())
+    }
+}
+
+impl<'info> ExecuteMineChange<'info> {
+    pub fn execute_mine_change(
+        &mut self
+    ) -> Result<()> {
+        if (Clock::get()?.unix_timestamp as u64) < self.pending_change.effective_ts {
+            return err!(MinerError::TimelockNotElapsed);
+        }
+        let mut mine_info = self.mine_info.clone().into_inner();
+        if let Some(val) = self.pending_change.dev_fee {
+            mine_info.dev_fee = val;
+        }
+        if let Some(val) = self.pending_change.early_withdrawal_fee {
+            mine_info.early_withdrawal_fee = val;
+        }
+        if let Some(val) = self.pending_change.referral_reward {
+            mine_info.referral_reward = val;
+        }
+        if let Some(val) = self.pending_change.upline_referral_rewards {
+            mine_info.upline_referral_rewards = val;
+        }
+        if let Some(val) = self.pending_change.timelock_delay {
+            mine_info.timelock_delay = val;
+        }
+        mine_info.validate_fees()?;
+        emit!(MineUpdated {
+            mine: self.mine_info.key(),
+            fee_collector: mine_info.fee_collector,
+            penalty_fee_collector: mine_info.penalty_fee_collector,
+            dev_fee: mine_info.dev_fee,
+            early_withdrawal_fee: mine_info.early_withdrawal_fee,
+            referral_reward: mine_info.referral_reward,
+            referral_on_claim: mine_info.referral_on_claim,
+            upline_referral_rewards: mine_info.upline_referral_rewards,
+            is_active: mine_info.is_active,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        self.mine_info.set_inner(mine_info);
+        Ok(())
+    }
+}
+
+impl<'info> CancelMineChange<'info> {
+    pub fn cancel_mine_change(
+        &mut self
+    ) -> Result<()> {
+        emit!(ChangeCancelled {
+            pending_change: self.pending_change.key(),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
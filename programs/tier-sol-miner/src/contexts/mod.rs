@@ -11,6 +11,27 @@ pub mod update_tier;
 pub mod claim_interest;
 pub mod withdraw;
 pub mod referral_withdraw;
+pub mod revoke_whitelist;
+pub mod update_whitelist;
+pub mod allowlist;
+pub mod migrate_tier;
+pub mod fund_rewards;
+pub mod withdraw_excess;
+pub mod transfer_admin;
+pub mod set_roles;
+pub mod mine_change;
+pub mod tier_change;
+pub mod refresh_terms;
+pub mod fund_emission;
+pub mod reclaim_emission;
+pub mod token_vault;
+pub mod receipt;
+pub mod position_nft;
+pub mod gate_escrow;
+pub mod enforce_gate;
+pub mod update_gate_account;
+pub mod set_tier_gates;
+pub mod set_tier_boosts;
 
 pub use initialize::*;
 pub use add_tier::*;
@@ -24,4 +45,25 @@ pub use update_mine::*;
 pub use update_tier::*;
 pub use claim_interest::*;
 pub use withdraw::*;
-pub use referral_withdraw::*;
\ No newline at end of file
+pub use referral_withdraw::*;
+pub use revoke_whitelist::*;
+pub use update_whitelist::*;
+pub use allowlist::*;
+pub use migrate_tier::*;
+pub use fund_rewards::*;
+pub use withdraw_excess::*;
+pub use transfer_admin::*;
+pub use set_roles::*;
+pub use mine_change::*;
+pub use tier_change::*;
+pub use refresh_terms::*;
+pub use fund_emission::*;
+pub use reclaim_emission::*;
+pub use token_vault::*;
+pub use receipt::*;
+pub use position_nft::*;
+pub use gate_escrow::*;
+pub use enforce_gate::*;
+pub use update_gate_account::*;
+pub use set_tier_gates::*;
+pub use set_tier_boosts::*;
\ No newline at end of file
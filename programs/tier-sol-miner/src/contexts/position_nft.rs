@@ -0,0 +1,295 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, CreateAccount, Transfer};
+use anchor_spl::associated_token::{self, AssociatedToken, Create};
+use anchor_spl::token_2022::Token2022;
+use solana_program::program::invoke_signed;
+use spl_token_2022::extension::{metadata_pointer, BaseStateWithExtensions, ExtensionType, StateWithExtensions};
+use spl_token_2022::instruction::{self as token_instruction, AuthorityType};
+use spl_token_2022::state::Mint;
+use spl_token_metadata_interface::instruction as metadata_instruction;
+use spl_token_metadata_interface::state::{Field, TokenMetadata};
+use crate::states::mine::{MineInfo, MineVault};
+use crate::states::user::UserInfo;
+use crate::errors::MinerError;
+use crate::events::PositionNftMinted;
+
+const POSITION_NFT_SYMBOL: &str = "POS";
+
+/// Instruction for users to turn a position into a Token-2022 NFT. From
+/// then on the NFT holder, not the original signer, acts on the position.
+#[derive(Accounts)]
+pub struct MintPositionNft<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user", mine_info.key().as_ref(), signer.key().as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
+        constraint = !user_info.is_whitelist @ MinerError::OperationNotAllowed,
+        constraint = user_info.nft_mint.is_none() @ MinerError::OperationNotAllowed
+    )]
+    pub user_info: Account<'info, UserInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.is_active @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    /// CHECK: created and initialized as a Token-2022 mint by this instruction
+    #[account(
+        mut,
+        seeds = [b"position", user_info.key().as_ref()],
+        bump
+    )]
+    pub position_mint: UncheckedAccount<'info>,
+    /// CHECK: associated token account of the signer, created by the
+    /// associated token program which validates its address
+    #[account(mut)]
+    pub position_account: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token2022>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>
+}
+
+/// Instruction anyone can call to write a position's current amount and
+/// unlock time into its NFT metadata. The caller pays for any extra rent.
+#[derive(Accounts)]
+pub struct SyncPositionNft<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        constraint = user_info.nft_mint == Some(position_mint.key()) @ MinerError::InvalidArgument
+    )]
+    pub user_info: Account<'info, UserInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    /// CHECK: the position mint recorded on the position
+    #[account(mut)]
+    pub position_mint: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>
+}
+
+/// Key value pairs stored in the metadata of a position NFT
+fn position_fields(user_info: &UserInfo) -> Vec<(String, String)> {
+    vec![
+        ("tier".to_string(), user_info.tier.to_string()),
+        ("amount".to_string(), user_info.total_locked.to_string()),
+        ("unlock_ts".to_string(), user_info.lock_ts.saturating_add(user_info.lock_duration).to_string())
+    ]
+}
+
+/// Space of a mint with the metadata pointer extension and its metadata
+fn position_mint_len(metadata: &TokenMetadata) -> Result<usize> {
+    let mint_len = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::MetadataPointer])?;
+    Ok(mint_len.saturating_add(metadata.tlv_size_of()?))
+}
+
+impl<'info> MintPositionNft<'info> {
+    pub fn mint_position_nft(
+        &mut self,
+        uri: String,
+        bump: u8
+    ) -> Result<()> {
+        let mine_key = self.mine_info.key();
+        let user_key = self.user_info.key();
+        let vault_seeds: &[&[u8]] = &[b"mine-vault", mine_key.as_ref(), &[self.mine_vault.bump]];
+        let mint_seeds: &[&[u8]] = &[b"position", user_key.as_ref(), &[bump]];
+        let name = format!("Position #{}", self.user_info.index);
+        let metadata = TokenMetadata {
+            mint: self.position_mint.key(),
+            name: name.clone(),
+            symbol: POSITION_NFT_SYMBOL.to_string(),
+            uri: uri.clone(),
+            additional_metadata: position_fields(&self.user_info),
+            ..Default::default()
+        };
+
+        // Create the mint with room for the pointer, rent covers the metadata
+        // the token program appends on initialization
+        let mint_len = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::MetadataPointer])?;
+        let lamports = Rent::get()?.minimum_balance(position_mint_len(&metadata)?);
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                self.system_program.to_account_info(),
+                CreateAccount {
+                    from: self.signer.to_account_info(),
+                    to: self.position_mint.to_account_info()
+                },
+                &[mint_seeds]
+            ),
+            lamports,
+            mint_len as u64,
+            &self.token_program.key()
+        )?;
+        invoke_signed(
+            &metadata_pointer::instruction::initialize(
+                &self.token_program.key(),
+                &self.position_mint.key(),
+                Some(self.mine_vault.key()),
+                Some(self.position_mint.key())
+            )?,
+            &[self.position_mint.to_account_info()],
+            &[]
+        )?;
+        invoke_signed(
+            &token_instruction::initialize_mint2(
+                &self.token_program.key(),
+                &self.position_mint.key(),
+                &self.mine_vault.key(),
+                None,
+                0
+            )?,
+            &[self.position_mint.to_account_info()],
+            &[]
+        )?;
+
+        // Write the metadata, the mine vault stays update authority so the
+        // fields can be synced with the position
+        invoke_signed(
+            &metadata_instruction::initialize(
+                &self.token_program.key(),
+                &self.position_mint.key(),
+                &self.mine_vault.key(),
+                &self.position_mint.key(),
+                &self.mine_vault.key(),
+                name,
+                POSITION_NFT_SYMBOL.to_string(),
+                uri
+            ),
+            &[
+                self.position_mint.to_account_info(),
+                self.mine_vault.to_account_info(),
+                self.position_mint.to_account_info(),
+                self.mine_vault.to_account_info()
+            ],
+            &[vault_seeds]
+        )?;
+        for (key, value) in position_fields(&self.user_info) {
+            invoke_signed(
+                &metadata_instruction::update_field(
+                    &self.token_program.key(),
+                    &self.position_mint.key(),
+                    &self.mine_vault.key(),
+                    Field::Key(key),
+                    value
+                ),
+                &[self.position_mint.to_account_info(), self.mine_vault.to_account_info()],
+                &[vault_seeds]
+            )?;
+        }
+
+        // Mint the single token to the signer and fix the supply
+        associated_token::create(CpiContext::new(
+            self.associated_token_program.to_account_info(),
+            Create {
+                payer: self.signer.to_account_info(),
+                associated_token: self.position_account.to_account_info(),
+                authority: self.signer.to_account_info(),
+                mint: self.position_mint.to_account_info(),
+                system_program: self.system_program.to_account_info(),
+                token_program: self.token_program.to_account_info()
+            }
+        ))?;
+        invoke_signed(
+            &token_instruction::mint_to(
+                &self.token_program.key(),
+                &self.position_mint.key(),
+                &self.position_account.key(),
+                &self.mine_vault.key(),
+                &[],
+                1
+            )?,
+            &[
+                self.position_mint.to_account_info(),
+                self.position_account.to_account_info(),
+                self.mine_vault.to_account_info()
+            ],
+            &[vault_seeds]
+        )?;
+        invoke_signed(
+            &token_instruction::set_authority(
+                &self.token_program.key(),
+                &self.position_mint.key(),
+                None,
+                AuthorityType::MintTokens,
+                &self.mine_vault.key(),
+                &[]
+            )?,
+            &[self.position_mint.to_account_info(), self.mine_vault.to_account_info()],
+            &[vault_seeds]
+        )?;
+
+        let mut user_info = self.user_info.clone().into_inner();
+        user_info.nft_mint = Some(self.position_mint.key());
+        self.user_info.set_inner(user_info);
+        emit!(PositionNftMinted {
+            owner: self.signer.key(),
+            user_info: user_key,
+            nft_mint: self.position_mint.key(),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
+
+impl<'info> SyncPositionNft<'info> {
+    pub fn sync_position_nft(
+        &mut self
+    ) -> Result<()> {
+        let mine_key = self.mine_info.key();
+        let vault_seeds: &[&[u8]] = &[b"mine-vault", mine_key.as_ref(), &[self.mine_vault.bump]];
+        let fields = position_fields(&self.user_info);
+
+        // Top up rent in case the new values take more space
+        let mut metadata = {
+            let data = self.position_mint.try_borrow_data()?;
+            let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+            mint.get_variable_len_extension::<TokenMetadata>()?
+        };
+        for (key, value) in fields.iter() {
+            metadata.update(Field::Key(key.clone()), value.clone());
+        }
+        let required = Rent::get()?.minimum_balance(position_mint_len(&metadata)?);
+        let missing = required.saturating_sub(self.position_mint.lamports());
+        if missing > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    self.system_program.to_account_info(),
+                    Transfer {
+                        from: self.payer.to_account_info(),
+                        to: self.position_mint.to_account_info()
+                    }
+                ),
+                missing
+            )?;
+        }
+        for (key, value) in fields {
+            invoke_signed(
+                &metadata_instruction::update_field(
+                    &self.token_program.key(),
+                    &self.position_mint.key(),
+                    &self.mine_vault.key(),
+                    Field::Key(key),
+                    value
+                ),
+                &[self.position_mint.to_account_info(), self.mine_vault.to_account_info()],
+                &[vault_seeds]
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault};
+use crate::states::roles::MineRoles;
+use crate::states::tier::TierInfo;
+use crate::states::user::UserInfo;
+use crate::errors::MinerError;
+use crate::events::{ReceiptMintCreated, ReceiptMinted, ReceiptBurned};
+use crate::transfers::{mint_from_vault, burn_from_user, Payer, TokenRoute};
+use crate::math::{muldiv, to_u128, to_u64};
+
+/// Instruction for the tier manager to create the receipt mint of a TIER.
+/// One receipt token is worth one lamport of staked principal, or one base
+/// unit of the stake mint on token mode mines, and the mint authority is the
+/// mine vault PDA. Token mode mines create it under the token program of
+/// the stake mint, which pays out redeemed principal.
+#[derive(Accounts)]
+pub struct InitReceiptMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        constraint = (tier_info.mine == mine_info.key() && tier_info.receipt_mint.is_none()) @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = (mine_info.is_native() || stake_mint.is_some()) @ MinerError::MissingTokenAccounts
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode,
+        constraint = *stake_mint.to_account_info().owner == token_program.key() @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_tier_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+    #[account(
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"receipt", tier_info.key().as_ref()],
+        bump,
+        mint::decimals = stake_mint.as_ref().map_or(9, |stake_mint| stake_mint.decimals),
+        mint::authority = mine_vault,
+        mint::token_program = token_program
+    )]
+    pub receipt_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>
+}
+
+/// Instruction for users to mint receipt tokens against the principal of
+/// a position. The backed principal cannot leave the position until its
+/// holder burns the same amount of receipts. Receipts held by anyone else
+/// are redeemed from the tier once the position is terminated.
+#[derive(Accounts)]
+pub struct MintReceipt<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
+        constraint = !user_info.is_whitelist @ MinerError::OperationNotAllowed
+    )]
+    pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.is_active @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        constraint = (
+            tier_info.key() == user_info.tier &&
+            tier_info.receipt_mint == Some(receipt_mint.key())
+        ) @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(mut)]
+    pub receipt_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = receipt_account.mint == receipt_mint.key() @ MinerError::InvalidTokenAccount
+    )]
+    pub receipt_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Instruction to burn receipt tokens and redeem the principal they back.
+/// The position holder redeems out of the position given in `user_info`,
+/// receipts are fungible across the tier so nobody else can. Any holder
+/// redeems out of the receipts of the tier that were detached from
+/// terminated positions when no position is given. Token mode mines pay
+/// the principal from the stake vault.
+#[derive(Accounts)]
+pub struct BurnReceipt<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
+        constraint = user_info.tier == tier_info.key() @ MinerError::InvalidTier
+    )]
+    pub user_info: Option<Account<'info, UserInfo>>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        mut,
+        constraint = (
+            tier_info.mine == mine_info.key() &&
+            tier_info.receipt_mint == Some(receipt_mint.key())
+        ) @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(mut)]
+    pub receipt_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = (
+            receipt_account.mint == receipt_mint.key() &&
+            receipt_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub receipt_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = mine_info.fee_collector == fee_collector.key()
+    )]
+    pub fee_collector: SystemAccount<'info>,
+    #[account(
+        mut,
+        constraint = mine_info.penalty_fee_collector == penalty_collector.key()
+    )]
+    pub penalty_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(penalty_collector_stake_account.mint) &&
+            penalty_collector_stake_account.owner == mine_info.penalty_fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub penalty_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> InitReceiptMint<'info> {
+    pub fn init_receipt_mint(
+        &mut self
+    ) -> Result<()> {
+        let mut tier_info = self.tier_info.clone().into_inner();
+        tier_info.receipt_mint = Some(self.receipt_mint.key());
+        self.tier_info.set_inner(tier_info);
+        emit!(ReceiptMintCreated {
+            tier: self.tier_info.key(),
+            receipt_mint: self.receipt_mint.key(),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
+
+impl<'info> MintReceipt<'info> {
+    pub fn mint_receipt(
+        &mut self,
+        amount: u64
+    ) -> Result<()> {
+        let available = self.user_info.total_locked.saturating_sub(self.user_info.receipt_amount);
+        if amount == 0 || amount > available {
+            return err!(MinerError::InvalidArgument);
+        }
+        mint_from_vault(
+            &self.token_program,
+            &self.receipt_mint,
+            &self.receipt_account,
+            &self.mine_vault,
+            &self.mine_info.key(),
+            amount
+        )?;
+        let mut user_info = self.user_info.clone().into_inner();
+        user_info.receipt_amount = user_info.receipt_amount.saturating_add(amount);
+        self.user_info.set_inner(user_info);
+        emit!(ReceiptMinted {
+            owner: self.signer.key(),
+            user_info: self.user_info.key(),
+            receipt_mint: self.receipt_mint.key(),
+            amount,
+            receipt_amount: self.user_info.receipt_amount,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
+
+impl<'info> BurnReceipt<'info> {
+    pub fn burn_receipt(
+        &mut self,
+        amount: u64
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut tier_info = self.tier_info.clone().into_inner();
+        let (settled_interest, forfeited_interest, is_early) = match &mut self.user_info {
+            Some(user_info) => {
+                if amount == 0 || amount > user_info.receipt_amount {
+                    return err!(MinerError::InvalidArgument);
+                }
+                // Settle interest earned since the last checkpoint, it stays
+                // with the position
+                let mut position = user_info.clone().into_inner();
+                let settled_interest = position.settle(&mut tier_info, now)?;
+
+                // Fixed positions give up the interest the redeemed principal
+                // would still have earned over the rest of the lock
+                let forfeited_interest = if !tier_info.is_fixed() {
+                    0_u64
+                } else {
+                    let unearned = position.accrued_interest.saturating_sub(position.earned_interest(now)?);
+                    match muldiv(to_u128(unearned)?, to_u128(amount)?, to_u128(position.total_locked)?) {
+                        Some(val) => to_u64(val)?,
+                        None => return err!(MinerError::MathsError)
+                    }
+                };
+                let is_early = position.is_locked(now);
+
+                // Update User info
+                position.total_locked = position.total_locked.saturating_sub(amount);
+                position.receipt_amount = position.receipt_amount.saturating_sub(amount);
+                position.accrued_interest = position.accrued_interest.saturating_sub(forfeited_interest);
+                position.reward_debt = tier_info.reward_debt(position.total_locked)?;
+                user_info.set_inner(position);
+                tier_info.total_locked = tier_info.total_locked.saturating_sub(amount);
+                (settled_interest, forfeited_interest, is_early)
+            },
+            None => {
+                if amount == 0 || amount > tier_info.detached_receipts {
+                    return err!(MinerError::InvalidArgument);
+                }
+                tier_info.detached_receipts = tier_info.detached_receipts.saturating_sub(amount);
+                (0_u64, 0_u64, false)
+            }
+        };
+        self.tier_info.set_inner(tier_info);
+
+        burn_from_user(
+            &self.token_program,
+            &self.receipt_mint,
+            &self.receipt_account,
+            &self.signer,
+            amount
+        )?;
+
+        // Calculate fees and pay the redeemed principal to the holder,
+        // redeeming from a position that is still locked is an early withdrawal
+        let token_program = Some(self.token_program.clone());
+        let payout = Payer {
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            owner: self.signer.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            penalty_collector: self.penalty_collector.to_account_info()
+        }.pay(amount, is_early, &TokenRoute {
+            token_program: &token_program,
+            mint: &self.stake_mint,
+            vault: &self.stake_vault,
+            holder: &self.user_stake_account,
+            fee_collector: &self.fee_collector_stake_account,
+            penalty_collector: &self.penalty_collector_stake_account
+        })?;
+
+        // Update vault liabilities
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        mine_vault.total_principal = mine_vault.total_principal.saturating_sub(amount);
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest
+            .saturating_add(settled_interest)
+            .saturating_sub(forfeited_interest);
+        self.mine_vault.set_inner(mine_vault);
+
+        emit!(ReceiptBurned {
+            owner: self.signer.key(),
+            user_info: self.user_info.as_ref().map(|user_info| user_info.key()),
+            receipt_mint: self.receipt_mint.key(),
+            amount,
+            amount_out: payout.amount_out,
+            dev_fee: payout.dev_fee,
+            penalty: payout.penalty,
+            receipt_amount: match &self.user_info {
+                Some(user_info) => user_info.receipt_amount,
+                None => self.tier_info.detached_receipts
+            },
+            timestamp: now
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use anchor_lang::prelude::*;
+use crate::states::mine::{MineInfo, MineVault};
+use crate::states::roles::MineRoles;
+use crate::states::tier::TierInfo;
+use crate::errors::MinerError;
+use crate::events::EmissionReclaimed;
+
+/// Instruction for the tier manager to take back the reward budget an
+/// emission TIER has not emitted yet, once the tier is deactivated or its
+/// rate is set to zero. What was emitted up to now stays with the stakers,
+/// the rest leaves the vault liabilities so `withdraw_excess` can release it.
+#[derive(Accounts)]
+pub struct ReclaimEmission<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        constraint = (tier_info.mine == mine_info.key() && tier_info.is_emission()) @ MinerError::InvalidTier,
+        constraint = (!tier_info.is_active || tier_info.reward_rate == 0) @ MinerError::OperationNotAllowed
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_tier_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+}
+
+impl<'info> ReclaimEmission<'info> {
+    pub fn reclaim_emission(
+        &mut self
+    ) -> Result<()> {
+        // Emit up to now so stakers keep what they earned before the reclaim
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut tier_info = self.tier_info.clone().into_inner();
+        tier_info.update_emission(now)?;
+        let amount = tier_info.reward_remaining;
+        if amount == 0 {
+            return err!(MinerError::InvalidArgument);
+        }
+        tier_info.reward_remaining = 0;
+        tier_info.reward_rate = 0;
+        self.tier_info.set_inner(tier_info);
+
+        // Update vault liabilities
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_sub(amount);
+        self.mine_vault.set_inner(mine_vault);
+
+        emit!(EmissionReclaimed {
+            tier: self.tier_info.key(),
+            amount,
+            timestamp: now
+        });
+        Ok(())
+    }
+}
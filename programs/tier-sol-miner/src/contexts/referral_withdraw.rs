@@ -1,23 +1,28 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::TokenAccount;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use crate::states::mine::{MineInfo, MineVault};
 use crate::states::tier::TierInfo;
 use crate::states::user::UserInfo;
 use crate::states::referral::ReferralInfo;
 use crate::errors::MinerError;
-use crate::math::{calculate_fee, to_u128};
+use crate::events::ReferralWithdrawn;
+use crate::transfers::{Payer, TokenRoute};
 
-/// Instruction to call for users to increase their locked SOL
+/// Instruction to call for users to withdraw the referral rewards their
+/// position earned, minus the dev fee. Token mode mines pay them in the
+/// reward mint.
 #[derive(Accounts)]
 pub struct WithdrawReward<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
     #[account(
         mut,
-        seeds = [b"user", signer.key().as_ref()],
-        bump = user_info.bump
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized
     )]
     pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         seeds = [b"referral", user_info.key().as_ref()],
@@ -26,25 +31,25 @@ pub struct WithdrawReward<'info> {
     pub referrer_info: Account<'info, ReferralInfo>,
     #[account(
         constraint = (
-            token_account.mint == mine_info.token_mint &&
-            token_account.amount >= tier_info.minimum_token_amount &&
+            user_info.meets_gate(tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint)) &&
             token_account.owner == signer.key()
         ) @ MinerError::InvalidTokenAccount
     )]
     pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
     #[account(
         constraint = (tier_info.key() == user_info.tier) @ MinerError::InvalidTier
     )]
     pub tier_info: Account<'info, TierInfo>,
     #[account(
-        seeds = [b"mine"],
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
         constraint = mine_info.is_active @ MinerError::InvalidMine
     )]
     pub mine_info: Account<'info, MineInfo>,
     #[account(
         mut,
-        seeds = [b"mine-vault"],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump = mine_vault.bump
     )]
     pub mine_vault: Account<'info, MineVault>,
@@ -52,32 +57,98 @@ pub struct WithdrawReward<'info> {
         mut,
         constraint = mine_info.fee_collector == fee_collector.key()
     )]
-    pub fee_collector: SystemAccount<'info>
+    pub fee_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(user_reward_account.mint) &&
+            user_reward_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(fee_collector_reward_account.mint) &&
+            fee_collector_reward_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>
 }
 
 impl<'info> WithdrawReward<'info> {
     pub fn withdraw(
         &mut self
     ) -> Result<()> {
-        // Calculate fees and transfer lamports to vault and fee collector
-        let dev_fee = match calculate_fee(
-            to_u128(self.referrer_info.earnings)?, 
-            to_u128(self.mine_info.dev_fee)?
-        ) {
-            Ok(fee) => fee,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        let actual_amount = self.referrer_info.earnings.saturating_sub(dev_fee);
-        self.mine_vault.sub_lamports(dev_fee)?;
-        self.mine_vault.sub_lamports(actual_amount)?;
-        self.signer.add_lamports(actual_amount)?;
-        self.fee_collector.add_lamports(dev_fee)?;
+        // Calculate fees and pay the referrer and fee collector
+        let earnings = self.referrer_info.earnings;
+        let payout = Payer {
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            owner: self.signer.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            penalty_collector: self.signer.to_account_info()
+        }.pay(earnings, false, &TokenRoute {
+            token_program: &self.token_program,
+            mint: &self.reward_mint,
+            vault: &self.reward_vault,
+            holder: &self.user_reward_account,
+            fee_collector: &self.fee_collector_reward_account,
+            penalty_collector: &None
+        })?;
+
+        emit!(ReferralWithdrawn {
+            owner: self.signer.key(),
+            referrer_info: self.referrer_info.key(),
+            earnings,
+            amount_out: payout.amount_out,
+            dev_fee: payout.dev_fee,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+
+        // Update vault liabilities
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_sub(earnings);
+        self.mine_vault.set_inner(mine_vault);
 
         // Update Referral info
         let mut ref_info = self.referrer_info.clone().into_inner();
         ref_info.earnings = 0;
         self.referrer_info.set_inner(ref_info);
-        msg!("New Earnings {}", self.referrer_info.earnings);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Closes the referral account of a position that is being closed, if it
+/// was ever created. Returns its unclaimed earnings, which are forfeited and
+/// must leave the vault liabilities.
+pub fn close_referral_account<'info>(
+    referrer_info: &UncheckedAccount<'info>,
+    receiver: &AccountInfo<'info>
+) -> Result<u64> {
+    if referrer_info.data_is_empty() {
+        return Ok(0);
+    }
+    let earnings = {
+        let data = referrer_info.try_borrow_data()?;
+        ReferralInfo::try_deserialize(&mut &data[..])?.earnings
+    };
+    let lamports = referrer_info.lamports();
+    referrer_info.sub_lamports(lamports)?;
+    receiver.add_lamports(lamports)?;
+    referrer_info.assign(&System::id());
+    referrer_info.realloc(0, false)?;
+    Ok(earnings)
+}
@@ -0,0 +1,121 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use crate::states::mine::{MineInfo, MineVault, check_mine_solvency};
+use crate::states::tier::TierInfo;
+use crate::states::user::UserInfo;
+use crate::errors::MinerError;
+use crate::events::TermsRefreshed;
+use crate::math::{boosted_apy, calculate_interest, to_u128};
+
+/// Instruction for users to opt in to their tier's current APY and lock
+/// duration. The interest earned so far under the old terms is kept and
+/// the lock restarts under the new ones. Token mode mines pass their stake
+/// and reward vaults for the solvency check.
+#[derive(Accounts)]
+pub struct RefreshTerms<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
+        constraint = !user_info.is_whitelist @ MinerError::OperationNotAllowed
+    )]
+    pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        constraint = (
+            user_info.meets_gate(tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint)) &&
+            token_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.is_active @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        mut,
+        constraint = (tier_info.key() == user_info.tier && tier_info.is_active) @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+}
+
+impl<'info> RefreshTerms<'info> {
+    pub fn refresh_terms(
+        &mut self
+    ) -> Result<()> {
+        // Settle under the old terms, the new ones apply from now on
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut tier_info = self.tier_info.clone().into_inner();
+        let mut user_info = self.user_info.clone().into_inner();
+        let settled_interest = user_info.settle(&mut tier_info, now)?;
+        self.tier_info.set_inner(tier_info);
+        let previous_interest = user_info.accrued_interest;
+        let boost = self.tier_info.boost_for(&self.mine_info.token_mint, &self.token_account);
+        if self.tier_info.is_fixed() {
+            // Interest earned under the old terms, the rest of the lock is dropped
+            let current_interest = user_info.earned_interest(now)?;
+
+            // Restart the lock under the current tier terms
+            let new_interest = match calculate_interest(
+                to_u128(user_info.total_locked)?,
+                to_u128(boosted_apy(self.tier_info.apy, boost)?)?,
+                to_u128(self.tier_info.lock_duration)?
+            ) {
+                Ok(val) => val,
+                Err(_) => return err!(MinerError::MathsError)
+            };
+            user_info.accrued_interest = new_interest.saturating_add(current_interest);
+        }
+        user_info.lock_ts = now;
+        user_info.apy = self.tier_info.apy;
+        user_info.boost = boost;
+        user_info.gate_account = Some(self.token_account.key());
+        user_info.lock_duration = self.tier_info.lock_duration;
+        let accrued_interest = user_info.accrued_interest;
+        self.user_info.set_inner(user_info);
+
+        // Update vault liabilities
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest
+            .saturating_add(settled_interest)
+            .saturating_sub(previous_interest)
+            .saturating_add(accrued_interest);
+        self.mine_vault.set_inner(mine_vault);
+        check_mine_solvency(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault)?;
+
+        emit!(TermsRefreshed {
+            owner: self.signer.key(),
+            user_info: self.user_info.key(),
+            tier: self.tier_info.key(),
+            apy: self.tier_info.apy,
+            lock_duration: self.tier_info.lock_duration,
+            accrued_interest,
+            timestamp: now
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use anchor_lang::prelude::*;
+use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
+use crate::states::whitelist::WhitelistInfo;
+use crate::errors::MinerError;
+use crate::events::WhitelistUpdated;
+
+/// Instruction for the admin to revoke a whitelist entry that has not been
+/// consumed yet. Closes the whitelist info account back to the admin.
+#[derive(Accounts)]
+pub struct RevokeWhiteList<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub beneficiary: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"whitelist", mine_info.key().as_ref(), beneficiary.key().as_ref()],
+        bump = whitelist_info.bump,
+        constraint = whitelist_info.beneficiary == beneficiary.key() @ MinerError::InvalidArgument,
+        close = admin
+    )]
+    pub whitelist_info: Account<'info, WhitelistInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_whitelister(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+}
+
+impl<'info> RevokeWhiteList<'info> {
+    pub fn revoke_whitelist(
+        &mut self
+    ) -> Result<()> {
+        emit!(WhitelistUpdated {
+            beneficiary: self.beneficiary.key(),
+            tier: self.whitelist_info.tier,
+            expiry: self.whitelist_info.expiry,
+            revoked: true,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
+use crate::errors::MinerError;
+use crate::events::RolesUpdated;
+
+/// Instruction for the admin to assign operator roles
+#[derive(Accounts)]
+pub struct SetRoles<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.admin == admin.key() @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump
+    )]
+    pub roles: Account<'info, MineRoles>
+}
+
+impl<'info> SetRoles<'info> {
+    pub fn set_roles(
+        &mut self,
+        tier_manager: Option<Pubkey>,
+        whitelister: Option<Pubkey>,
+        fee_manager: Option<Pubkey>,
+        pauser: Option<Pubkey>
+    ) -> Result<()> {
+        let mut roles = self.roles.clone().into_inner();
+        if let Some(val) = tier_manager {
+            roles.tier_manager = val;
+        }
+        if let Some(val) = whitelister {
+            roles.whitelister = val;
+        }
+        if let Some(val) = fee_manager {
+            roles.fee_manager = val;
+        }
+        if let Some(val) = pauser {
+            roles.pauser = val;
+        }
+        emit!(RolesUpdated {
+            tier_manager: roles.tier_manager,
+            whitelister: roles.whitelister,
+            fee_manager: roles.fee_manager,
+            pauser: roles.pauser,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        self.roles.set_inner(roles);
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use crate::states::tier::{TierInfo, BoostBracket, MAX_BOOST_BRACKETS};
+use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
+use crate::errors::MinerError;
+use crate::events::TierBoostsUpdated;
+use crate::math::BASIS_POINTS;
+
+/// Instruction for the tier manager to set the APY boost brackets of a TIER.
+/// Positions pick up the new brackets the next time their boost is computed.
+#[derive(Accounts)]
+pub struct SetTierBoosts<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        constraint = tier_info.mine == mine_info.key() @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_tier_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+}
+
+impl<'info> SetTierBoosts<'info> {
+    pub fn set_tier_boosts(
+        &mut self,
+        boosts: Vec<BoostBracket>
+    ) -> Result<()> {
+        if boosts.len() > MAX_BOOST_BRACKETS {
+            return err!(MinerError::InvalidArgument);
+        }
+        // Brackets must be sorted by balance and at most double the APY
+        if boosts.windows(2).any(|pair| pair[0].min_balance >= pair[1].min_balance)
+            || boosts.iter().any(|bracket| bracket.boost == 0 || bracket.boost > BASIS_POINTS) {
+            return err!(MinerError::InvalidArgument);
+        }
+        let mut tier_info = self.tier_info.clone().into_inner();
+        tier_info.boosts = boosts;
+        self.tier_info.set_inner(tier_info);
+        emit!(TierBoostsUpdated {
+            tier: self.tier_info.key(),
+            boosts: self.tier_info.boosts.clone(),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
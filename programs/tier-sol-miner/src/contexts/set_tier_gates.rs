@@ -0,0 +1,55 @@
+use anchor_lang::prelude::*;
+use crate::states::tier::{TierInfo, GateAsset, GateKind, MAX_TIER_GATES};
+use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
+use crate::errors::MinerError;
+use crate::events::TierGatesUpdated;
+
+/// Instruction for the tier manager to set the assets accepted by the token
+/// gate of a TIER. An empty list falls back to the mine's token mint.
+#[derive(Accounts)]
+pub struct SetTierGates<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        constraint = tier_info.mine == mine_info.key() @ MinerError::InvalidTier,
+        constraint = !tier_info.escrow_gating @ MinerError::OperationNotAllowed
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_tier_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+}
+
+impl<'info> SetTierGates<'info> {
+    pub fn set_tier_gates(
+        &mut self,
+        gates: Vec<GateAsset>
+    ) -> Result<()> {
+        if gates.len() > MAX_TIER_GATES {
+            return err!(MinerError::InvalidArgument);
+        }
+        // Fungible gates need a minimum, any collection member qualifies
+        if gates.iter().any(|gate| gate.kind == GateKind::Mint && gate.minimum == 0) {
+            return err!(MinerError::InvalidArgument);
+        }
+        let mut tier_info = self.tier_info.clone().into_inner();
+        tier_info.gates = gates;
+        self.tier_info.set_inner(tier_info);
+        emit!(TierGatesUpdated {
+            tier: self.tier_info.key(),
+            gates: self.tier_info.gates.clone(),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
@@ -1,12 +1,19 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use crate::states::mine::{MineInfo, MineVault};
+use crate::states::roles::MineRoles;
 use crate::states::tier::TierInfo;
 use crate::states::user::UserInfo;
-use crate::states::referral::ReferralInfo;
 use crate::errors::MinerError;
-use crate::math::{to_u128, calculate_fee};
+use crate::contexts::referral_withdraw::close_referral_account;
+use crate::events::{StakeTerminated, GateReleased};
+use crate::contexts::gate_escrow::release_gate_tokens;
+use crate::transfers::{Payer, TokenRoute};
 
-/// Instruction to call for users to restake their interests
+/// Instruction for the pauser to force close a position. The holder gets
+/// their principal back minus the dev fee, without the early withdrawal fee,
+/// and the position's interest is forfeited. Principal backing receipts
+/// stays in the vault for the receipt holders.
 #[derive(Accounts)]
 pub struct TerminateStaking<'info> {
     #[account(mut)]
@@ -17,33 +24,41 @@ pub struct TerminateStaking<'info> {
         close = admin
     )]
     pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: referral account derived from the terminated position, which
+    /// may never have been created. When it was, its rent goes to the admin
+    /// and its unclaimed earnings are forfeited with the position
     #[account(
         mut,
         seeds = [b"referral", user_info.key().as_ref()],
-        bump = referrer_info.bump,
-        close = admin
+        bump
     )]
-    pub referrer_info: Option<Account<'info, ReferralInfo>>,
+    pub referrer_info: UncheckedAccount<'info>,
     #[account(
-        seeds = [b"mine"],
-        bump = mine_info.bump,
-        constraint = mine_info.admin == admin.key() @ MinerError::OperationNotAllowed
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
     )]
     pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_pauser(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
     #[account(
         mut,
-        seeds = [b"mine-vault"],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump = mine_vault.bump
     )]
     pub mine_vault: Account<'info, MineVault>,
     #[account(
         mut,
-        constraint = tier_info.key() == user_info.tier @ MinerError::InvalidTier
+        constraint = (tier_info.key() == user_info.tier && tier_info.mine == mine_info.key()) @ MinerError::InvalidTier
     )]
     pub tier_info: Account<'info, TierInfo>,
     #[account(
         mut,
-        constraint = user_info.owner == user_account.key()
+        constraint = user_info.authority(&position_nft) == Some(user_account.key()) @ MinerError::Unauthorized
     )]
     pub user_account: SystemAccount<'info>,
     #[account(
@@ -51,6 +66,52 @@ pub struct TerminateStaking<'info> {
         constraint = mine_info.fee_collector == fee_collector.key()
     )]
     pub fee_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == user_account.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            user_token_account.owner == user_account.key() &&
+            user_token_account.mint == mine_info.token_mint
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"gate-escrow", mine_info.key().as_ref()],
+        bump
+    )]
+    pub gate_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        constraint = token_mint.key() == mine_info.token_mint @ MinerError::InvalidTokenAccount
+    )]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
     pub system_program: Program<'info, System>
 }
 
@@ -58,25 +119,79 @@ impl<'info> TerminateStaking<'info> {
     pub fn terminate(
         &mut self
     ) -> Result<()> {
-        // Calculate fees and transfer lamports to vault and fee collector
-        let dev_fee = match calculate_fee(
-            to_u128(self.user_info.total_locked)?, 
-            to_u128(self.mine_info.dev_fee)?
-        ) {
-            Ok(fee) => fee,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        let amount_out = self.user_info.total_locked.saturating_sub(dev_fee);
-        self.mine_vault.sub_lamports(dev_fee)?;
-        self.fee_collector.add_lamports(dev_fee)?;
-        self.mine_vault.sub_lamports(amount_out)?;
-        self.user_account.add_lamports(amount_out)?;
-
-        // Update Tier total locked
+        // Settle what the position earned and take it out of the tier and
+        // the vault liabilities, its interest is forfeited
         let mut tier_info = self.tier_info.clone().into_inner();
-        tier_info.total_locked = tier_info.total_locked.saturating_sub(self.user_info.total_locked);
+        let mut user_info = self.user_info.clone().into_inner();
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        user_info.close(&mut tier_info, &mut mine_vault, Clock::get()?.unix_timestamp as u64)?;
+
+        // Principal backing receipts stays in the vault for their holders,
+        // only the rest goes back to the position holder
+        let detached = user_info.receipt_amount;
+        let released = user_info.total_locked.saturating_sub(detached);
+        tier_info.detached_receipts = tier_info.detached_receipts.saturating_add(detached);
+        mine_vault.total_principal = mine_vault.total_principal.saturating_add(detached);
         self.tier_info.set_inner(tier_info);
+        self.user_info.set_inner(user_info);
+
+        // Calculate fees and pay the holder and fee collector,
+        // terminations carry no early withdrawal penalty
+        let payout = Payer {
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            owner: self.user_account.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            penalty_collector: self.user_account.to_account_info()
+        }.pay(released, false, &TokenRoute {
+            token_program: &self.token_program,
+            mint: &self.stake_mint,
+            vault: &self.stake_vault,
+            holder: &self.user_stake_account,
+            fee_collector: &self.fee_collector_stake_account,
+            penalty_collector: &None
+        })?;
+
+        // Return the escrowed gating tokens to the position holder
+        let escrowed_amount = self.user_info.escrowed_amount;
+        if escrowed_amount > 0 {
+            let Some(user_token_account) = &self.user_token_account else {
+                return err!(MinerError::GateEscrowRequired);
+            };
+            release_gate_tokens(
+                escrowed_amount,
+                user_token_account,
+                &self.gate_escrow,
+                &self.token_mint,
+                &self.token_program,
+                &self.mine_vault,
+                &self.mine_info.key()
+            )?;
+            emit!(GateReleased {
+                owner: self.user_account.key(),
+                user_info: self.user_info.key(),
+                amount: escrowed_amount,
+                timestamp: Clock::get()?.unix_timestamp as u64
+            });
+        }
+
+        // Update vault liabilities, unclaimed referral earnings are forfeited
+        let referral_earnings = close_referral_account(
+            &self.referrer_info,
+            &self.admin.to_account_info()
+        )?;
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_sub(referral_earnings);
+        self.mine_vault.set_inner(mine_vault);
 
+        emit!(StakeTerminated {
+            owner: self.user_info.owner,
+            user_info: self.user_info.key(),
+            tier: self.tier_info.key(),
+            principal: released,
+            amount_out: payout.amount_out,
+            dev_fee: payout.dev_fee,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,167 @@
+use anchor_lang::prelude::*;
+use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
+use crate::states::tier::TierInfo;
+use crate::states::pending::PendingTierChange;
+use crate::errors::MinerError;
+use crate::events::{TierChangeQueued, TierUpdated, ChangeCancelled};
+
+/// Instruction for the tier manager to queue an APY or lock duration change
+/// for a TIER. Stakers get the mine's timelock delay to exit before it applies.
+#[derive(Accounts)]
+pub struct QueueTierChange<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + PendingTierChange::INIT_SPACE,
+        seeds = [b"pending-tier".as_ref(), tier_info.key().as_ref()],
+        bump
+    )]
+    pub pending_change: Account<'info, PendingTierChange>,
+    #[account(
+        constraint = tier_info.mine == mine_info.key() @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_tier_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+    pub system_program: Program<'info, System>
+}
+
+/// Instruction anyone can call to apply a queued tier change after its
+/// effective timestamp. Rent goes back to the proposer.
+#[derive(Accounts)]
+pub struct ExecuteTierChange<'info> {
+    pub executor: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"pending-tier".as_ref(), tier_info.key().as_ref()],
+        bump = pending_change.bump,
+        close = proposer
+    )]
+    pub pending_change: Account<'info, PendingTierChange>,
+    #[account(
+        mut,
+        constraint = pending_change.proposer == proposer.key() @ MinerError::InvalidArgument
+    )]
+    pub proposer: SystemAccount<'info>,
+    #[account(
+        mut,
+        constraint = pending_change.tier == tier_info.key() @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>
+}
+
+/// Instruction for the tier manager to drop a queued tier change
+#[derive(Accounts)]
+pub struct CancelTierChange<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"pending-tier".as_ref(), tier_info.key().as_ref()],
+        bump = pending_change.bump,
+        close = proposer
+    )]
+    pub pending_change: Account<'info, PendingTierChange>,
+    #[account(
+        mut,
+        constraint = pending_change.proposer == proposer.key() @ MinerError::InvalidArgument
+    )]
+    pub proposer: SystemAccount<'info>,
+    #[account(
+        constraint = tier_info.mine == mine_info.key() @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_tier_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>
+}
+
+impl<'info> QueueTierChange<'info> {
+    pub fn queue_tier_change(
+        &mut self,
+        apy: Option<u64>,
+        lock_duration: Option<u64>,
+        bump: u8
+    ) -> Result<()> {
+        if apy.is_none() && lock_duration.is_none() {
+            return err!(MinerError::InvalidArgument);
+        }
+        let effective_ts = (Clock::get()?.unix_timestamp as u64).saturating_add(self.mine_info.timelock_delay);
+        self.pending_change.set_inner(PendingTierChange {
+            proposer: self.admin.key(),
+            tier: self.tier_info.key(),
+            apy,
+            lock_duration,
+            effective_ts,
+            bump
+        });
+        emit!(TierChangeQueued {
+            tier: self.tier_info.key(),
+            apy,
+            lock_duration,
+            effective_ts
+        });
+        Ok(())
+    }
+}
+
+impl<'info> ExecuteTierChange<'info> {
+    pub fn execute_tier_change(
+        &mut self
+    ) -> Result<()> {
+        if (Clock::get()?.unix_timestamp as u64) < self.pending_change.effective_ts {
+            return err!(MinerError::TimelockNotElapsed);
+        }
+        let mut tier_info = self.tier_info.clone().into_inner();
+        if let Some(val) = self.pending_change.apy {
+            tier_info.apy = val;
+        }
+        if let Some(val) = self.pending_change.lock_duration {
+            tier_info.lock_duration = val;
+        }
+        emit!(TierUpdated {
+            tier: self.tier_info.key(),
+            nonce: tier_info.nonce,
+            minimum_token_amount: tier_info.minimum_token_amount,
+            apy: tier_info.apy,
+            lock_duration: tier_info.lock_duration,
+            is_active: tier_info.is_active,
+            accrual_mode: tier_info.accrual_mode,
+            escrow_gating: tier_info.escrow_gating,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        self.tier_info.set_inner(tier_info);
+        Ok(())
+    }
+}
+
+impl<'info> CancelTierChange<'info> {
+    pub fn cancel_tier_change(
+        &mut self
+    ) -> Result<()> {
+        emit!(ChangeCancelled {
+            pending_change: self.pending_change.key(),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault};
+use crate::errors::MinerError;
+use crate::events::TokenModeEnabled;
+
+/// Instruction for the admin to create the token account that holds a
+/// mint for the mine. The account is owned by the mine vault PDA.
+#[derive(Accounts)]
+pub struct InitTokenVault<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.admin == admin.key() @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        mint::token_program = token_program
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"token-vault", mine_info.key().as_ref(), mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = mine_vault,
+        token::token_program = token_program
+    )]
+    pub token_vault: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>
+}
+
+/// Instruction for the admin to switch the mine from SOL to a staked mint,
+/// with interest paid in the same or a separate reward mint. Only allowed
+/// before anything has been staked.
+#[derive(Accounts)]
+pub struct EnableTokenMode<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.admin == admin.key() @ MinerError::InvalidMine,
+        constraint = mine_info.is_native() @ MinerError::InvalidStakeMode
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump,
+        constraint = mine_vault.total_liabilities() == 0 @ MinerError::OperationNotAllowed
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_mint.key().as_ref()],
+        bump
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_mint.key().as_ref()],
+        bump
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+}
+
+impl<'info> InitTokenVault<'info> {
+    pub fn init_token_vault(
+        &mut self
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'info> EnableTokenMode<'info> {
+    pub fn enable_token_mode(
+        &mut self
+    ) -> Result<()> {
+        let mut mine_info = self.mine_info.clone().into_inner();
+        mine_info.stake_mint = Some(self.stake_mint.key());
+        mine_info.reward_mint = Some(self.reward_mint.key());
+        self.mine_info.set_inner(mine_info);
+        emit!(TokenModeEnabled {
+            mine: self.mine_info.key(),
+            stake_mint: self.stake_mint.key(),
+            reward_mint: self.reward_mint.key(),
+            stake_vault: self.stake_vault.key(),
+            reward_vault: self.reward_vault.key(),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use anchor_lang::prelude::*;
+use crate::states::mine::MineInfo;
+use crate::errors::MinerError;
+use crate::events::{AdminTransferProposed, AdminTransferred};
+
+/// Instruction for the admin to propose a new admin or cancel a pending
+/// proposal. The transfer only completes once the new key accepts it.
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.admin == admin.key() @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>
+}
+
+/// Instruction for the proposed admin to accept the admin role
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub new_admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.pending_admin == Some(new_admin.key()) @ MinerError::OperationNotAllowed
+    )]
+    pub mine_info: Account<'info, MineInfo>
+}
+
+impl<'info> ProposeAdmin<'info> {
+    pub fn propose_admin(
+        &mut self,
+        new_admin: Pubkey
+    ) -> Result<()> {
+        if new_admin == self.mine_info.admin {
+            return err!(MinerError::InvalidArgument);
+        }
+        let mut mine_info = self.mine_info.clone().into_inner();
+        mine_info.pending_admin = Some(new_admin);
+        self.mine_info.set_inner(mine_info);
+        emit!(AdminTransferProposed {
+            admin: self.admin.key(),
+            pending_admin: Some(new_admin),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+
+    pub fn cancel_admin_transfer(
+        &mut self
+    ) -> Result<()> {
+        if self.mine_info.pending_admin.is_none() {
+            return err!(MinerError::OperationNotAllowed);
+        }
+        let mut mine_info = self.mine_info.clone().into_inner();
+        mine_info.pending_admin = None;
+        self.mine_info.set_inner(mine_info);
+        emit!(AdminTransferProposed {
+            admin: self.admin.key(),
+            pending_admin: None,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
+
+impl<'info> AcceptAdmin<'info> {
+    pub fn accept_admin(
+        &mut self
+    ) -> Result<()> {
+        let mut mine_info = self.mine_info.clone().into_inner();
+        let previous_admin = mine_info.admin;
+        mine_info.admin = self.new_admin.key();
+        mine_info.pending_admin = None;
+        self.mine_info.set_inner(mine_info);
+        emit!(AdminTransferred {
+            previous_admin,
+            new_admin: self.new_admin.key(),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
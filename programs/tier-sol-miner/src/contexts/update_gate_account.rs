@@ -0,0 +1,61 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use crate::states::mine::MineInfo;
+use crate::states::tier::TierInfo;
+use crate::states::user::UserInfo;
+use crate::errors::MinerError;
+use crate::events::GateAccountUpdated;
+
+/// Instruction for the position holder to move the gate onto another token
+/// account of theirs that the tier admits. Whoever buys an NFT position
+/// calls it so the gate is enforced on what they hold, not on the seller's
+/// account. The position's terms are left as they are.
+#[derive(Accounts)]
+pub struct UpdateGateAccount<'info> {
+    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
+        constraint = !user_info.is_whitelist @ MinerError::OperationNotAllowed,
+        constraint = user_info.escrowed_amount == 0 @ MinerError::OperationNotAllowed
+    )]
+    pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        constraint = (
+            tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint) &&
+            token_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        constraint = tier_info.key() == user_info.tier @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+}
+
+impl<'info> UpdateGateAccount<'info> {
+    pub fn update_gate_account(
+        &mut self
+    ) -> Result<()> {
+        let mut user_info = self.user_info.clone().into_inner();
+        user_info.gate_account = Some(self.token_account.key());
+        self.user_info.set_inner(user_info);
+
+        emit!(GateAccountUpdated {
+            holder: self.signer.key(),
+            user_info: self.user_info.key(),
+            gate_account: self.token_account.key(),
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
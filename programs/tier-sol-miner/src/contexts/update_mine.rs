@@ -1,6 +1,8 @@
 use anchor_lang::prelude::*;
 use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
 use crate::errors::MinerError;
+use crate::events::MineUpdated;
 
 #[derive(Accounts)]
 pub struct UpdateMine<'info> {
@@ -8,11 +10,19 @@ pub struct UpdateMine<'info> {
     pub admin: Signer<'info>,
     #[account(
         mut,
-        seeds = [b"mine".as_ref()],
-        bump = mine_info.bump,
-        constraint = mine_info.admin == admin.key() @ MinerError::InvalidMine
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
     )]
-    pub mine_info: Account<'info, MineInfo>
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = (
+            roles.is_fee_manager(&mine_info.admin, admin.key) ||
+            roles.is_pauser(&mine_info.admin, admin.key)
+        ) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>
 }
 
 impl<'info> UpdateMine<'info> {
@@ -20,11 +30,20 @@ impl<'info> UpdateMine<'info> {
         &mut self,
         fee_collector: Option<Pubkey>,
         penalty_fee_collector: Option<Pubkey>,
-        dev_fee: Option<u64>,
-        early_withdrawal_fee: Option<u64>,
-        referral_reward: Option<u64>,
-        is_active: Option<bool>
+        is_active: Option<bool>,
+        referral_on_claim: Option<bool>
     ) -> Result<()> {
+        // Fee collectors need the fee manager and pausing needs the pauser.
+        // Fee amounts are changed through the timelocked `queue_mine_change`
+        let updates_fees = fee_collector.is_some()
+            || penalty_fee_collector.is_some()
+            || referral_on_claim.is_some();
+        if updates_fees && !self.roles.is_fee_manager(&self.mine_info.admin, self.admin.key) {
+            return err!(MinerError::Unauthorized);
+        }
+        if is_active.is_some() && !self.roles.is_pauser(&self.mine_info.admin, self.admin.key) {
+            return err!(MinerError::Unauthorized);
+        }
         let mut mine_info = self.mine_info.clone().into_inner();
         match fee_collector {
             Some(val) => {mine_info.fee_collector = val},
@@ -34,22 +53,25 @@ impl<'info> UpdateMine<'info> {
             Some(val) => {mine_info.penalty_fee_collector = val},
             None => {}
         };
-        match dev_fee {
-            Some(val) => {mine_info.dev_fee = val},
-            None => {}
-        };
-        match early_withdrawal_fee {
-            Some(val) => {mine_info.early_withdrawal_fee = val},
-            None => {}
-        };
-        match referral_reward {
-            Some(val) => {mine_info.referral_reward = val},
-            None => {}
-        };
         match is_active {
             Some(val) => {mine_info.is_active = val},
             None => {}
         }
+        if let Some(val) = referral_on_claim {
+            mine_info.referral_on_claim = val;
+        }
+        emit!(MineUpdated {
+            mine: self.mine_info.key(),
+            fee_collector: mine_info.fee_collector,
+            penalty_fee_collector: mine_info.penalty_fee_collector,
+            dev_fee: mine_info.dev_fee,
+            early_withdrawal_fee: mine_info.early_withdrawal_fee,
+            referral_reward: mine_info.referral_reward,
+            referral_on_claim: mine_info.referral_on_claim,
+            upline_referral_rewards: mine_info.upline_referral_rewards,
+            is_active: mine_info.is_active,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
         self.mine_info.set_inner(mine_info);
         Ok(())
     }
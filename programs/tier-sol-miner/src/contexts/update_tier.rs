@@ -1,7 +1,9 @@
 use anchor_lang::prelude::*;
 use crate::states::tier::TierInfo;
 use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
 use crate::errors::MinerError;
+use crate::events::TierUpdated;
 
 #[derive(Accounts)]
 pub struct UpdateTier<'info> {
@@ -9,14 +11,20 @@ pub struct UpdateTier<'info> {
     pub admin: Signer<'info>,
     #[account(
         mut,
+        constraint = tier_info.mine == mine_info.key() @ MinerError::InvalidTier
     )]
     pub tier_info: Account<'info, TierInfo>,
     #[account(
-        seeds = [b"mine".as_ref()],
-        bump = mine_info.bump,
-        constraint = mine_info.admin == admin.key() @ MinerError::InvalidMine
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
     )]
     pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_tier_manager(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
     //pub system_program: Program<'info, System>
 }
 
@@ -24,27 +32,36 @@ impl<'info> UpdateTier<'info> {
     pub fn update_tier(
         &mut self,
         minimum_token_amount: Option<u64>,
-        apy: Option<u64>,
-        lock_duration: Option<u64>,
-        is_active: Option<bool>
+        is_active: Option<bool>,
+        escrow_gating: Option<bool>
     ) -> Result<()> {
         let mut tier_info = self.tier_info.clone().into_inner();
         match minimum_token_amount {
             Some(val) => {tier_info.minimum_token_amount = val},
             None => {}
         };
-        match apy {
-            Some(val) => {tier_info.apy = val},
-            None => {}
-        };
-        match lock_duration {
-            Some(val) => {tier_info.lock_duration = val},
-            None => {}
-        };
         match is_active {
             Some(val) => {tier_info.is_active = val},
             None => {}
         }
+        // Gate escrow only holds the mine's token mint, not the tier gates
+        if let Some(val) = escrow_gating {
+            if val && !tier_info.gates.is_empty() {
+                return err!(MinerError::OperationNotAllowed);
+            }
+            tier_info.escrow_gating = val;
+        }
+        emit!(TierUpdated {
+            tier: self.tier_info.key(),
+            nonce: tier_info.nonce,
+            minimum_token_amount: tier_info.minimum_token_amount,
+            apy: tier_info.apy,
+            lock_duration: tier_info.lock_duration,
+            is_active: tier_info.is_active,
+            accrual_mode: tier_info.accrual_mode,
+            escrow_gating: tier_info.escrow_gating,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
         self.tier_info.set_inner(tier_info);
         Ok(())
     }
@@ -0,0 +1,62 @@
+use anchor_lang::prelude::*;
+use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
+use crate::states::tier::TierInfo;
+use crate::states::whitelist::WhitelistInfo;
+use crate::errors::MinerError;
+use crate::events::WhitelistUpdated;
+
+/// Instruction for the admin to amend the expiry or target tier of a
+/// whitelist entry before the beneficiary consumes it.
+#[derive(Accounts)]
+pub struct UpdateWhiteList<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub beneficiary: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"whitelist", mine_info.key().as_ref(), beneficiary.key().as_ref()],
+        bump = whitelist_info.bump,
+        constraint = whitelist_info.beneficiary == beneficiary.key() @ MinerError::InvalidArgument
+    )]
+    pub whitelist_info: Account<'info, WhitelistInfo>,
+    #[account(
+        constraint = (tier_info.is_active && tier_info.mine == mine_info.key()) @ MinerError::InvalidTier
+    )]
+    pub tier_info: Option<Account<'info, TierInfo>>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_whitelister(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
+}
+
+impl<'info> UpdateWhiteList<'info> {
+    pub fn update_whitelist(
+        &mut self,
+        expiry: Option<u64>
+    ) -> Result<()> {
+        let mut whitelist_info = self.whitelist_info.clone().into_inner();
+        if let Some(val) = expiry {
+            whitelist_info.expiry = val;
+        }
+        if let Some(tier_info) = &self.tier_info {
+            whitelist_info.tier = tier_info.key();
+        }
+        emit!(WhitelistUpdated {
+            beneficiary: self.beneficiary.key(),
+            tier: whitelist_info.tier,
+            expiry: whitelist_info.expiry,
+            revoked: false,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        self.whitelist_info.set_inner(whitelist_info);
+        Ok(())
+    }
+}
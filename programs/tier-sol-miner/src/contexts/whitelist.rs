@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
+use crate::states::mine::MineInfo;
+use crate::states::roles::MineRoles;
 use crate::states::tier::TierInfo;
 use crate::states::whitelist::WhitelistInfo;
 use crate::errors::MinerError;
+use crate::events::WhitelistUpdated;
 
 #[derive(Accounts)]
 #[instruction(_tier_nonce: u8)]
@@ -13,17 +16,28 @@ pub struct WhiteList<'info> {
         init,
         payer = admin,
         space = 8 + WhitelistInfo::INIT_SPACE,
-        seeds = [b"whitelist", beneficiary.key().as_ref()],
+        seeds = [b"whitelist", mine_info.key().as_ref(), beneficiary.key().as_ref()],
         bump
     )]
     pub whitelist_info: Account<'info, WhitelistInfo>,
     #[account(
         mut,
-        seeds = [b"tier".as_ref(), &[_tier_nonce]],
+        seeds = [b"tier", mine_info.key().as_ref(), &[_tier_nonce]],
         bump = tier_info.bump,
         constraint = tier_info.is_active && _tier_nonce == tier_info.nonce @ MinerError::InvalidTier
     )]
     pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        seeds = [b"roles", mine_info.key().as_ref()],
+        bump = roles.bump,
+        constraint = roles.is_whitelister(&mine_info.admin, admin.key) @ MinerError::Unauthorized
+    )]
+    pub roles: Account<'info, MineRoles>,
     pub system_program: Program<'info, System>
 }
 
@@ -40,6 +54,13 @@ impl<'info> WhiteList<'info> {
             expiry,
             tier: self.tier_info.key()
         });
+        emit!(WhitelistUpdated {
+            beneficiary: self.beneficiary.key(),
+            tier: self.tier_info.key(),
+            expiry,
+            revoked: false,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
         Ok(())
     }
 }
\ No newline at end of file
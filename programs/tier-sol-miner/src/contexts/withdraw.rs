@@ -1,49 +1,60 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::TokenAccount;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use crate::states::mine::{MineInfo, MineVault};
-use crate::states::referral::ReferralInfo;
 use crate::states::tier::TierInfo;
 use crate::states::user::UserInfo;
 use crate::errors::MinerError;
-use crate::math::{calculate_fee, to_u128};
+use crate::contexts::referral_withdraw::close_referral_account;
+use crate::events::{StakeWithdrawn, GateReleased};
+use crate::contexts::gate_escrow::release_gate_tokens;
+use crate::transfers::{Payer, TokenRoute};
 
-/// Instruction to call for users to increase their locked SOL
+/// Instruction to call for users to close a position and withdraw its
+/// principal and interest. Withdrawing before the lock ends pays the early
+/// withdrawal fee on the interest. Token mode mines pay the principal in the
+/// stake mint and the interest in the reward mint.
 #[derive(Accounts)]
 pub struct WithdrawStake<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
     #[account(
         mut,
-        seeds = [b"user", signer.key().as_ref()],
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
         bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
         constraint = user_info.is_whitelist == false @ MinerError::OperationNotAllowed,
+        constraint = user_info.receipt_amount == 0 @ MinerError::ReceiptsOutstanding,
         close = signer
     )]
     pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: referral account derived from the withdrawn position. It only
+    /// exists once the position referred someone, and is then closed to the
+    /// signer with its unclaimed earnings forfeited
     #[account(
         mut,
         seeds = [b"referral", user_info.key().as_ref()],
-        bump = referrer_info.bump,
-        close = signer
+        bump
     )]
-    pub referrer_info: Option<Account<'info, ReferralInfo>>,
+    pub referrer_info: UncheckedAccount<'info>,
     #[account(
+        mut,
         constraint = (
-            token_account.mint == mine_info.token_mint &&
-            token_account.amount >= tier_info.minimum_token_amount &&
+            user_info.meets_gate(tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint)) &&
             token_account.owner == signer.key()
         ) @ MinerError::InvalidTokenAccount
     )]
     pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
     #[account(
-        seeds = [b"mine"],
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
         constraint = mine_info.is_active @ MinerError::InvalidMine
     )]
     pub mine_info: Account<'info, MineInfo>,
     #[account(
         mut,
-        seeds = [b"mine-vault"],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump = mine_vault.bump
     )]
     pub mine_vault: Account<'info, MineVault>,
@@ -62,6 +73,87 @@ pub struct WithdrawStake<'info> {
         constraint = mine_info.penalty_fee_collector == penalty_collector.key()
     )]
     pub penalty_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(user_reward_account.mint) &&
+            user_reward_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(fee_collector_reward_account.mint) &&
+            fee_collector_reward_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(penalty_collector_stake_account.mint) &&
+            penalty_collector_stake_account.owner == mine_info.penalty_fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub penalty_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(penalty_collector_reward_account.mint) &&
+            penalty_collector_reward_account.owner == mine_info.penalty_fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub penalty_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"gate-escrow", mine_info.key().as_ref()],
+        bump
+    )]
+    pub gate_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        constraint = token_mint.key() == mine_info.token_mint @ MinerError::InvalidTokenAccount
+    )]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
     pub system_program: Program<'info, System>
 }
 
@@ -69,62 +161,122 @@ impl<'info> WithdrawStake<'info> {
     pub fn withdraw(
         &mut self
     ) -> Result<()> {
-        // Calculate fees and transfer lamports to vault and fee collector
-        let total_withdrawal = self.user_info.accrued_interest.saturating_add(self.user_info.total_locked);
-        let lock_duration = (Clock::get()?.unix_timestamp as u64).saturating_sub(self.user_info.lock_ts);
-        let dev_fee = match calculate_fee(
-            to_u128(total_withdrawal)?, 
-            to_u128(self.mine_info.dev_fee)?
-        ) {
-            Ok(fee) => fee,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        let amount_out = if lock_duration >= self.tier_info.lock_duration {
-            total_withdrawal.saturating_sub(dev_fee)
-        } else {
-            let penalty = calculate_fee(
-                to_u128(total_withdrawal)?, 
-                to_u128(self.mine_info.early_withdrawal_fee)?
-            )?;
-            self.mine_vault.sub_lamports(penalty)?;
-            self.penalty_collector.add_lamports(penalty)?;
-            total_withdrawal.saturating_sub(dev_fee).saturating_sub(penalty)
-        };
-
-        self.mine_vault.sub_lamports(dev_fee)?;
-        self.fee_collector.add_lamports(dev_fee)?;
-        self.mine_vault.sub_lamports(amount_out)?;
-        self.signer.add_lamports(amount_out)?;
-
-        // Update Tier total locked
+        // Settle interest earned since the last checkpoint and take the
+        // position out of the tier and the vault liabilities
+        let now = Clock::get()?.unix_timestamp as u64;
         let mut tier_info = self.tier_info.clone().into_inner();
-        tier_info.total_locked = tier_info.total_locked.saturating_sub(self.user_info.total_locked);
+        let mut user_info = self.user_info.clone().into_inner();
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        user_info.close(&mut tier_info, &mut mine_vault, now)?;
         self.tier_info.set_inner(tier_info);
+        self.user_info.set_inner(user_info);
+
+        // Calculate fees and pay the holder and fee collectors
+        let payout = Payer {
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            owner: self.signer.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            penalty_collector: self.penalty_collector.to_account_info()
+        }.pay_position(
+            self.user_info.total_locked,
+            self.user_info.accrued_interest,
+            self.user_info.is_locked(now),
+            &TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.stake_mint,
+                vault: &self.stake_vault,
+                holder: &self.user_stake_account,
+                fee_collector: &self.fee_collector_stake_account,
+                penalty_collector: &self.penalty_collector_stake_account
+            },
+            &TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.reward_mint,
+                vault: &self.reward_vault,
+                holder: &self.user_reward_account,
+                fee_collector: &self.fee_collector_reward_account,
+                penalty_collector: &self.penalty_collector_reward_account
+            }
+        )?;
 
+        // Return the escrowed gating tokens
+        let escrowed_amount = self.user_info.escrowed_amount;
+        release_gate_tokens(
+            escrowed_amount,
+            &self.token_account,
+            &self.gate_escrow,
+            &self.token_mint,
+            &self.token_program,
+            &self.mine_vault,
+            &self.mine_info.key()
+        )?;
+        if escrowed_amount > 0 {
+            emit!(GateReleased {
+                owner: self.signer.key(),
+                user_info: self.user_info.key(),
+                amount: escrowed_amount,
+                timestamp: Clock::get()?.unix_timestamp as u64
+            });
+        }
+
+        // Update vault liabilities, unclaimed referral earnings are forfeited
+        let referral_earnings = close_referral_account(
+            &self.referrer_info,
+            &self.signer.to_account_info()
+        )?;
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_sub(referral_earnings);
+        self.mine_vault.set_inner(mine_vault);
+
+        emit!(StakeWithdrawn {
+            owner: self.signer.key(),
+            user_info: self.user_info.key(),
+            tier: self.tier_info.key(),
+            principal: self.user_info.total_locked,
+            interest: self.user_info.accrued_interest,
+            amount_out: payout.amount_out,
+            dev_fee: payout.dev_fee,
+            penalty: payout.penalty,
+            is_partial: false,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
         Ok(())
     }
 }
 
-/// Instruction to call for whitelist users to increase their locked SOL
+/// Instruction to call for whitelisted users to close their position, see
+/// `WithdrawStake`. Whitelisted positions hold no gating tokens, so no token
+/// account is checked.
 #[derive(Accounts)]
 pub struct WithdrawWhitelistStake<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
     #[account(
         mut,
+        seeds = [b"user", mine_info.key().as_ref(), signer.key().as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
         constraint = user_info.is_whitelist == true @ MinerError::OperationNotAllowed,
         close = signer
     )]
     pub user_info: Account<'info, UserInfo>,
+    /// CHECK: referral account derived from the whitelisted position, which
+    /// can refer stakers like any other. Closed to the signer when the
+    /// position ever referred someone
     #[account(
-        seeds = [b"mine"],
+        mut,
+        seeds = [b"referral", user_info.key().as_ref()],
+        bump
+    )]
+    pub referrer_info: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
         bump = mine_info.bump,
         constraint = mine_info.is_active @ MinerError::InvalidMine
     )]
     pub mine_info: Account<'info, MineInfo>,
     #[account(
         mut,
-        seeds = [b"mine-vault"],
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
         bump = mine_vault.bump
     )]
     pub mine_vault: Account<'info, MineVault>,
@@ -143,6 +295,77 @@ pub struct WithdrawWhitelistStake<'info> {
         constraint = mine_info.penalty_fee_collector == penalty_collector.key()
     )]
     pub penalty_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(user_reward_account.mint) &&
+            user_reward_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(fee_collector_reward_account.mint) &&
+            fee_collector_reward_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(penalty_collector_stake_account.mint) &&
+            penalty_collector_stake_account.owner == mine_info.penalty_fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub penalty_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(penalty_collector_reward_account.mint) &&
+            penalty_collector_reward_account.owner == mine_info.penalty_fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub penalty_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
     pub system_program: Program<'info, System>
 }
 
@@ -150,38 +373,257 @@ impl<'info> WithdrawWhitelistStake<'info> {
     pub fn withdraw(
         &mut self
     ) -> Result<()> {
-        // Calculate fees and transfer lamports to vault and fee collector
-        let total_withdrawal = self.user_info.accrued_interest.saturating_add(self.user_info.total_locked);
-        let lock_duration = (Clock::get()?.unix_timestamp as u64).saturating_sub(self.user_info.lock_ts);
-        let dev_fee = match calculate_fee(
-            to_u128(total_withdrawal)?, 
-            to_u128(self.mine_info.dev_fee)?
-        ) {
-            Ok(fee) => fee,
-            Err(_) => return err!(MinerError::MathsError)
-        };
-        let amount_out = if lock_duration >= self.tier_info.lock_duration {
-            total_withdrawal.saturating_sub(dev_fee)
-        } else {
-            let penalty = calculate_fee(
-                to_u128(total_withdrawal)?, 
-                to_u128(self.mine_info.early_withdrawal_fee)?
-            )?;
-            self.mine_vault.sub_lamports(penalty)?;
-            self.penalty_collector.add_lamports(penalty)?;
-            total_withdrawal.saturating_sub(dev_fee).saturating_sub(penalty)
-        };
+        // Settle interest earned since the last checkpoint and take the
+        // position out of the tier and the vault liabilities
+        let now = Clock::get()?.unix_timestamp as u64;
+        let mut tier_info = self.tier_info.clone().into_inner();
+        let mut user_info = self.user_info.clone().into_inner();
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        user_info.close(&mut tier_info, &mut mine_vault, now)?;
+        self.tier_info.set_inner(tier_info);
+        self.user_info.set_inner(user_info);
+
+        // Calculate fees and pay the holder and fee collectors
+        let payout = Payer {
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            owner: self.signer.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            penalty_collector: self.penalty_collector.to_account_info()
+        }.pay_position(
+            self.user_info.total_locked,
+            self.user_info.accrued_interest,
+            self.user_info.is_locked(now),
+            &TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.stake_mint,
+                vault: &self.stake_vault,
+                holder: &self.user_stake_account,
+                fee_collector: &self.fee_collector_stake_account,
+                penalty_collector: &self.penalty_collector_stake_account
+            },
+            &TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.reward_mint,
+                vault: &self.reward_vault,
+                holder: &self.user_reward_account,
+                fee_collector: &self.fee_collector_reward_account,
+                penalty_collector: &self.penalty_collector_reward_account
+            }
+        )?;
 
-        self.mine_vault.sub_lamports(dev_fee)?;
-        self.fee_collector.add_lamports(dev_fee)?;
-        self.mine_vault.sub_lamports(amount_out)?;
-        self.signer.add_lamports(amount_out)?;
+        // Update vault liabilities, unclaimed referral earnings are forfeited
+        let referral_earnings = close_referral_account(
+            &self.referrer_info,
+            &self.signer.to_account_info()
+        )?;
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_sub(referral_earnings);
+        self.mine_vault.set_inner(mine_vault);
+
+        emit!(StakeWithdrawn {
+            owner: self.signer.key(),
+            user_info: self.user_info.key(),
+            tier: self.tier_info.key(),
+            principal: self.user_info.total_locked,
+            interest: self.user_info.accrued_interest,
+            amount_out: payout.amount_out,
+            dev_fee: payout.dev_fee,
+            penalty: payout.penalty,
+            is_partial: false,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
+
+/// Instruction to call for users to withdraw part of their locked SOL.
+/// Fees only apply to the withdrawn principal and its share of interest.
+#[derive(Accounts)]
+pub struct WithdrawPartialStake<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"user", mine_info.key().as_ref(), user_info.owner.as_ref(), user_info.index.to_le_bytes().as_ref()],
+        bump = user_info.bump,
+        constraint = user_info.authority(&position_nft) == Some(signer.key()) @ MinerError::Unauthorized,
+        constraint = !user_info.is_whitelist @ MinerError::OperationNotAllowed
+    )]
+    pub user_info: Account<'info, UserInfo>,
+    pub position_nft: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        constraint = (
+            user_info.meets_gate(tier_info.admits(&mine_info.token_mint, &token_account, &gate_mint)) &&
+            token_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+    pub gate_mint: Option<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.is_active @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        mut,
+        constraint = (tier_info.key() == user_info.tier) @ MinerError::InvalidTier
+    )]
+    pub tier_info: Account<'info, TierInfo>,
+    #[account(
+        mut,
+        constraint = mine_info.fee_collector == fee_collector.key()
+    )]
+    pub fee_collector: SystemAccount<'info>,
+    #[account(
+        mut,
+        constraint = mine_info.penalty_fee_collector == penalty_collector.key()
+    )]
+    pub penalty_collector: SystemAccount<'info>,
+    #[account(
+        constraint = mine_info.stake_mint == Some(stake_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(user_stake_account.mint) &&
+            user_stake_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(user_reward_account.mint) &&
+            user_reward_account.owner == signer.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub user_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(fee_collector_stake_account.mint) &&
+            fee_collector_stake_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(fee_collector_reward_account.mint) &&
+            fee_collector_reward_account.owner == mine_info.fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub fee_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.stake_mint == Some(penalty_collector_stake_account.mint) &&
+            penalty_collector_stake_account.owner == mine_info.penalty_fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub penalty_collector_stake_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(penalty_collector_reward_account.mint) &&
+            penalty_collector_reward_account.owner == mine_info.penalty_fee_collector
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub penalty_collector_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>
+}
 
-        // Update Tier total locked
+impl<'info> WithdrawPartialStake<'info> {
+    pub fn withdraw_partial(
+        &mut self,
+        amount: u64
+    ) -> Result<()> {
+        if amount == 0 || amount >= self.user_info.total_locked {
+            return err!(MinerError::InvalidArgument);
+        }
+        if amount > self.user_info.total_locked.saturating_sub(self.user_info.receipt_amount) {
+            return err!(MinerError::ReceiptsOutstanding);
+        }
+        // Settle interest earned since the last checkpoint and take the
+        // withdrawn principal and its share of interest out of the position
+        let now = Clock::get()?.unix_timestamp as u64;
         let mut tier_info = self.tier_info.clone().into_inner();
-        tier_info.total_locked = tier_info.total_locked.saturating_sub(self.user_info.total_locked);
+        let mut user_info = self.user_info.clone().into_inner();
+        let mut mine_vault = self.mine_vault.clone().into_inner();
+        let is_early = user_info.is_locked(now);
+        let withdrawn_interest = user_info.withdraw_part(&mut tier_info, &mut mine_vault, amount, now)?;
         self.tier_info.set_inner(tier_info);
+        self.user_info.set_inner(user_info);
+        self.mine_vault.set_inner(mine_vault);
+
+        // Calculate fees on the withdrawn portion only
+        let payout = Payer {
+            mine_info: &self.mine_info,
+            mine_vault: &self.mine_vault,
+            owner: self.signer.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            penalty_collector: self.penalty_collector.to_account_info()
+        }.pay_position(
+            amount,
+            withdrawn_interest,
+            is_early,
+            &TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.stake_mint,
+                vault: &self.stake_vault,
+                holder: &self.user_stake_account,
+                fee_collector: &self.fee_collector_stake_account,
+                penalty_collector: &self.penalty_collector_stake_account
+            },
+            &TokenRoute {
+                token_program: &self.token_program,
+                mint: &self.reward_mint,
+                vault: &self.reward_vault,
+                holder: &self.user_reward_account,
+                fee_collector: &self.fee_collector_reward_account,
+                penalty_collector: &self.penalty_collector_reward_account
+            }
+        )?;
 
+        emit!(StakeWithdrawn {
+            owner: self.signer.key(),
+            user_info: self.user_info.key(),
+            tier: self.tier_info.key(),
+            principal: amount,
+            interest: withdrawn_interest,
+            amount_out: payout.amount_out,
+            dev_fee: payout.dev_fee,
+            penalty: payout.penalty,
+            is_partial: true,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
         Ok(())
     }
 }
\ No newline at end of file
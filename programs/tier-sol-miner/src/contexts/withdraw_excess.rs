@@ -0,0 +1,110 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::states::mine::{MineInfo, MineVault, check_mine_solvency};
+use crate::errors::MinerError;
+use crate::events::ExcessWithdrawn;
+use crate::transfers::transfer_from_vault;
+
+/// Instruction for the admin to withdraw vault lamports that are not owed
+/// to stakers. The vault never goes below its liabilities plus the rent
+/// exempt minimum. Token mode mines withdraw the reward mint from the reward
+/// vault instead, down to what the vaults owe.
+#[derive(Accounts)]
+pub struct WithdrawExcess<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"mine", mine_info.creator.as_ref(), mine_info.id.to_le_bytes().as_ref()],
+        bump = mine_info.bump,
+        constraint = mine_info.admin == admin.key() @ MinerError::InvalidMine
+    )]
+    pub mine_info: Account<'info, MineInfo>,
+    #[account(
+        mut,
+        seeds = [b"mine-vault", mine_info.key().as_ref()],
+        bump = mine_vault.bump
+    )]
+    pub mine_vault: Account<'info, MineVault>,
+    #[account(
+        constraint = mine_info.reward_mint == Some(reward_mint.key()) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        constraint = (
+            mine_info.reward_mint == Some(admin_reward_account.mint) &&
+            admin_reward_account.owner == admin.key()
+        ) @ MinerError::InvalidTokenAccount
+    )]
+    pub admin_reward_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        seeds = [b"token-vault", mine_info.key().as_ref(), stake_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.stake_mint == Some(stake_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub stake_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"token-vault", mine_info.key().as_ref(), reward_vault.mint.as_ref()],
+        bump,
+        constraint = mine_info.reward_mint == Some(reward_vault.mint) @ MinerError::InvalidStakeMode
+    )]
+    pub reward_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>
+}
+
+impl<'info> WithdrawExcess<'info> {
+    pub fn withdraw_excess(
+        &mut self,
+        amount: u64
+    ) -> Result<()> {
+        if !self.mine_info.is_native() {
+            return self.withdraw_excess_tokens(amount);
+        }
+        let rent = Rent::get()?.minimum_balance(self.mine_vault.to_account_info().data_len());
+        let excess = self.mine_vault.get_lamports()
+            .saturating_sub(rent)
+            .saturating_sub(self.mine_vault.total_liabilities());
+        if amount == 0 || amount > excess {
+            return err!(MinerError::InsufficientVaultBalance);
+        }
+        self.mine_vault.sub_lamports(amount)?;
+        self.admin.add_lamports(amount)?;
+        emit!(ExcessWithdrawn {
+            admin: self.admin.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+    /// Moves `amount` of the reward mint to the admin, the vaults must still
+    /// cover their liabilities afterwards
+    fn withdraw_excess_tokens(
+        &mut self,
+        amount: u64
+    ) -> Result<()> {
+        let (Some(token_program), Some(reward_mint), Some(reward_vault), Some(admin_reward_account)) =
+            (&self.token_program, &self.reward_mint, &self.reward_vault, &self.admin_reward_account) else {
+            return err!(MinerError::MissingTokenAccounts);
+        };
+        if amount == 0 {
+            return err!(MinerError::InsufficientVaultBalance);
+        }
+        transfer_from_vault(
+            token_program,
+            reward_vault,
+            admin_reward_account,
+            reward_mint,
+            &self.mine_vault,
+            &self.mine_info.key(),
+            amount
+        )?;
+        check_mine_solvency(&self.mine_info, &self.mine_vault, &self.stake_vault, &self.reward_vault)?;
+        emit!(ExcessWithdrawn {
+            admin: self.admin.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp as u64
+        });
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -25,5 +25,37 @@ pub enum MinerError {
     #[msg("Integer Conversion Failure")]
     ConversionFailure,
     #[msg("Maths Error due to Overflow or Underflow")]
-    MathsError
+    MathsError,
+    #[msg("Merkle proof is invalid for the allowlist")]
+    InvalidProof,
+    #[msg("Allowlist entry has already been claimed")]
+    AlreadyClaimed,
+    #[msg("Vault balance cannot cover its liabilities")]
+    InsufficientVaultBalance,
+    #[msg("Signer does not hold the role required for this operation")]
+    Unauthorized,
+    #[msg("Queued change is not effective yet")]
+    TimelockNotElapsed,
+    #[msg("Dev fee exceeds the protocol maximum")]
+    DevFeeTooHigh,
+    #[msg("Early withdrawal fee exceeds the protocol maximum")]
+    EarlyWithdrawalFeeTooHigh,
+    #[msg("Referral reward exceeds the protocol maximum")]
+    ReferralRewardTooHigh,
+    #[msg("Dev fee plus early withdrawal fee exceeds 100%")]
+    CombinedFeeTooHigh,
+    #[msg("Instruction does not match the asset staked in this mine")]
+    InvalidStakeMode,
+    #[msg("Principal backed by receipt tokens must be redeemed first")]
+    ReceiptsOutstanding,
+    #[msg("Tier locks its gating tokens, the gate escrow must be provided")]
+    GateEscrowRequired,
+    #[msg("Position still meets the token gate of its tier")]
+    GateSatisfied,
+    #[msg("Referral rates must not increase up the referrer chain")]
+    InvalidReferralLevels,
+    #[msg("Token mode mines need the token accounts of the mints they move")]
+    MissingTokenAccounts,
+    #[msg("Rewards worked out on the stake must be paid in the stake mint")]
+    RewardMintMismatch
 }
\ No newline at end of file
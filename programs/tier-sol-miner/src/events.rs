@@ -0,0 +1,343 @@
+use anchor_lang::prelude::*;
+use crate::states::tier::{AccrualMode, GateAsset, BoostBracket};
+
+#[event]
+pub struct MineUpdated {
+    pub mine: Pubkey,
+    pub fee_collector: Pubkey,
+    pub penalty_fee_collector: Pubkey,
+    pub dev_fee: u64,
+    pub early_withdrawal_fee: u64,
+    pub referral_reward: u64,
+    pub referral_on_claim: bool,
+    pub upline_referral_rewards: [u64; 2],
+    pub is_active: bool,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct AdminTransferProposed {
+    pub admin: Pubkey,
+    pub pending_admin: Option<Pubkey>,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct AdminTransferred {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct RolesUpdated {
+    pub tier_manager: Pubkey,
+    pub whitelister: Pubkey,
+    pub fee_manager: Pubkey,
+    pub pauser: Pubkey,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct TierUpdated {
+    pub tier: Pubkey,
+    pub nonce: u8,
+    pub minimum_token_amount: u64,
+    pub apy: u64,
+    pub lock_duration: u64,
+    pub is_active: bool,
+    pub accrual_mode: AccrualMode,
+    pub escrow_gating: bool,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct MineChangeQueued {
+    pub dev_fee: Option<u64>,
+    pub early_withdrawal_fee: Option<u64>,
+    pub referral_reward: Option<u64>,
+    pub upline_referral_rewards: Option<[u64; 2]>,
+    pub timelock_delay: Option<u64>,
+    pub effective_ts: u64
+}
+
+#[event]
+pub struct TierChangeQueued {
+    pub tier: Pubkey,
+    pub apy: Option<u64>,
+    pub lock_duration: Option<u64>,
+    pub effective_ts: u64
+}
+
+#[event]
+pub struct ChangeCancelled {
+    pub pending_change: Pubkey,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct WhitelistUpdated {
+    pub beneficiary: Pubkey,
+    pub tier: Pubkey,
+    pub expiry: u64,
+    pub revoked: bool,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct AllowlistPublished {
+    pub allowlist: Pubkey,
+    pub tier: Pubkey,
+    pub root: [u8; 32],
+    pub expiry: u64,
+    pub capacity: u32,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct StakeDeposited {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub tier: Pubkey,
+    pub amount: u64,
+    pub dev_fee: u64,
+    pub accrued_interest: u64,
+    pub is_whitelist: bool,
+    pub boost: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct ReferralCredited {
+    pub referrer: Pubkey,
+    pub referrer_info: Pubkey,
+    pub user_info: Pubkey,
+    pub level: u8,
+    pub bonus: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct StakeIncreased {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub tier: Pubkey,
+    pub amount: u64,
+    pub dev_fee: u64,
+    pub total_locked: u64,
+    pub accrued_interest: u64,
+    pub boost: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct InterestCompounded {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub tier: Pubkey,
+    pub compounded: u64,
+    pub total_locked: u64,
+    pub accrued_interest: u64,
+    pub boost: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct InterestClaimed {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub tier: Pubkey,
+    pub interest: u64,
+    pub amount_out: u64,
+    pub dev_fee: u64,
+    pub penalty: u64,
+    pub boost: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct TierMigrated {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub from_tier: Pubkey,
+    pub to_tier: Pubkey,
+    pub settled_interest: u64,
+    pub dev_fee: u64,
+    pub penalty: u64,
+    pub accrued_interest: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct TermsRefreshed {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub tier: Pubkey,
+    pub apy: u64,
+    pub lock_duration: u64,
+    pub accrued_interest: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct StakeWithdrawn {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub tier: Pubkey,
+    pub principal: u64,
+    pub interest: u64,
+    pub amount_out: u64,
+    pub dev_fee: u64,
+    pub penalty: u64,
+    pub is_partial: bool,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct StakeTerminated {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub tier: Pubkey,
+    pub principal: u64,
+    pub amount_out: u64,
+    pub dev_fee: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct ReferralWithdrawn {
+    pub owner: Pubkey,
+    pub referrer_info: Pubkey,
+    pub earnings: u64,
+    pub amount_out: u64,
+    pub dev_fee: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct RewardsFunded {
+    pub amount: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct EmissionFunded {
+    pub tier: Pubkey,
+    pub amount: u64,
+    pub reward_rate: u64,
+    pub reward_remaining: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct EmissionReclaimed {
+    pub tier: Pubkey,
+    pub amount: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct ExcessWithdrawn {
+    pub admin: Pubkey,
+    pub amount: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct TokenModeEnabled {
+    pub mine: Pubkey,
+    pub stake_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    pub stake_vault: Pubkey,
+    pub reward_vault: Pubkey,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct ReceiptMintCreated {
+    pub tier: Pubkey,
+    pub receipt_mint: Pubkey,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct ReceiptMinted {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub receipt_mint: Pubkey,
+    pub amount: u64,
+    pub receipt_amount: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct ReceiptBurned {
+    pub owner: Pubkey,
+    pub user_info: Option<Pubkey>,
+    pub receipt_mint: Pubkey,
+    pub amount: u64,
+    pub amount_out: u64,
+    pub dev_fee: u64,
+    pub penalty: u64,
+    pub receipt_amount: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct PositionNftMinted {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub nft_mint: Pubkey,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct GateEscrowed {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub amount: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct GateReleased {
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub amount: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct GateEnforced {
+    pub caller: Pubkey,
+    pub owner: Pubkey,
+    pub user_info: Pubkey,
+    pub from_tier: Pubkey,
+    pub to_tier: Option<Pubkey>,
+    pub balance: u64,
+    pub forfeited_interest: u64,
+    pub bounty: u64,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct GateAccountUpdated {
+    pub holder: Pubkey,
+    pub user_info: Pubkey,
+    pub gate_account: Pubkey,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct TierGatesUpdated {
+    pub tier: Pubkey,
+    pub gates: Vec<GateAsset>,
+    pub timestamp: u64
+}
+
+#[event]
+pub struct TierBoostsUpdated {
+    pub tier: Pubkey,
+    pub boosts: Vec<BoostBracket>,
+    pub timestamp: u64
+}
@@ -2,8 +2,13 @@ mod states;
 mod contexts;
 mod math;
 mod errors;
+mod merkle;
+mod events;
+mod transfers;
 
 use contexts::*;
+use states::mine::MineConfig;
+use states::tier::{AccrualMode, GateAsset, BoostBracket};
 use anchor_lang::prelude::*;
 
 declare_id!("K35hGi544FaiNx7s1MJuLuBxhr993Bq59CJR9mBaUna");
@@ -14,22 +19,15 @@ pub mod tier_sol_miner {
 
     pub fn initialize(
         ctx: Context<Initialize>,
-        fee_collector: Pubkey,
-        penalty_fee_collector: Pubkey,
-        token_mint: Pubkey,
-        dev_fee: u64,
-        early_withdrawal_fee: u64,
-        referral_reward: u64
+        id: u64,
+        config: MineConfig
     ) -> Result<()> {
         ctx.accounts.initialize_mine(
+            id,
             ctx.bumps.mine_info,
             ctx.bumps.mine_vault,
-            fee_collector,
-            penalty_fee_collector,
-            token_mint,
-            dev_fee,
-            early_withdrawal_fee,
-            referral_reward,
+            ctx.bumps.roles,
+            config
         )?;
         Ok(())
     }
@@ -37,11 +35,13 @@ pub mod tier_sol_miner {
     pub fn add_tier(
         ctx: Context<AddTier>,
         apy: u64, minimum_token_amount: u64,
-        lock_duration: u64
+        lock_duration: u64,
+        accrual_mode: AccrualMode
     ) -> Result<()> {
         ctx.accounts.add_tier(
             minimum_token_amount,
             apy, lock_duration,
+            accrual_mode,
             ctx.bumps.tier_info
         )?;
         Ok(())
@@ -60,6 +60,38 @@ pub mod tier_sol_miner {
         Ok(())
     }
 
+    pub fn revoke_whitelist(
+        ctx: Context<RevokeWhiteList>
+    ) -> Result<()> {
+        ctx.accounts.revoke_whitelist()?;
+        Ok(())
+    }
+
+    pub fn update_whitelist(
+        ctx: Context<UpdateWhiteList>,
+        expiry: Option<u64>
+    ) -> Result<()> {
+        ctx.accounts.update_whitelist(expiry)?;
+        Ok(())
+    }
+
+    pub fn publish_allowlist(
+        ctx: Context<PublishAllowList>,
+        tier_nonce: u8,
+        root: [u8; 32],
+        expiry: u64,
+        capacity: u32
+    ) -> Result<()> {
+        ctx.accounts.publish_allowlist(
+            expiry,
+            capacity,
+            ctx.bumps.allowlist,
+            root,
+            tier_nonce
+        )?;
+        Ok(())
+    }
+
     pub fn initialize_staking(
         ctx: Context<InitStaking>,
         tier_nonce: u8,
@@ -68,6 +100,7 @@ pub mod tier_sol_miner {
         ctx.accounts.initialize(
             deposit_amount,
             ctx.bumps.user_info,
+            ctx.bumps.user_counter,
             tier_nonce
         )?;
         Ok(())
@@ -81,6 +114,7 @@ pub mod tier_sol_miner {
         ctx.accounts.initialize(
             deposit_amount,
             ctx.bumps.user_info,
+            ctx.bumps.user_counter,
             ctx.bumps.referrer_info,
             tier_nonce
         )?;
@@ -95,6 +129,25 @@ pub mod tier_sol_miner {
         ctx.accounts.consume_whitelist(
             deposit_amount,
             ctx.bumps.user_info,
+            ctx.bumps.user_counter,
+            tier_nonce
+        )?;
+        Ok(())
+    }
+
+    pub fn initialize_allowlist(
+        ctx: Context<InitAllowList>,
+        tier_nonce: u8,
+        deposit_amount: u64,
+        leaf_index: u32,
+        proof: Vec<[u8; 32]>
+    ) -> Result<()> {
+        ctx.accounts.consume_allowlist(
+            deposit_amount,
+            ctx.bumps.user_info,
+            ctx.bumps.user_counter,
+            leaf_index,
+            proof,
             tier_nonce
         )?;
         Ok(())
@@ -117,6 +170,23 @@ pub mod tier_sol_miner {
         Ok(())
     }
 
+    pub fn migrate_tier(
+        ctx: Context<MigrateTier>,
+        to_tier_nonce: u8
+    ) -> Result<()> {
+        ctx.accounts.migrate_tier(
+            to_tier_nonce
+        )?;
+        Ok(())
+    }
+
+    pub fn refresh_terms(
+        ctx: Context<RefreshTerms>
+    ) -> Result<()> {
+        ctx.accounts.refresh_terms()?;
+        Ok(())
+    }
+
     pub fn terminate_staking(
         ctx: Context<TerminateStaking>,
     ) -> Result<()> {
@@ -138,6 +208,16 @@ pub mod tier_sol_miner {
         Ok(())
     }
 
+    pub fn withdraw_partial(
+        ctx: Context<WithdrawPartialStake>,
+        amount: u64
+    ) -> Result<()> {
+        ctx.accounts.withdraw_partial(
+            amount
+        )?;
+        Ok(())
+    }
+
     pub fn whitelist_withdraw(
         ctx: Context<WithdrawWhitelistStake>
     ) -> Result<()> {
@@ -152,39 +232,255 @@ pub mod tier_sol_miner {
         Ok(())
     }
 
+    pub fn fund_rewards(
+        ctx: Context<FundRewards>,
+        amount: u64
+    ) -> Result<()> {
+        ctx.accounts.fund_rewards(amount)?;
+        Ok(())
+    }
+
+    pub fn fund_emission(
+        ctx: Context<FundEmission>,
+        amount: u64,
+        reward_rate: u64
+    ) -> Result<()> {
+        ctx.accounts.fund_emission(amount, reward_rate)?;
+        Ok(())
+    }
+
+    pub fn reclaim_emission(
+        ctx: Context<ReclaimEmission>
+    ) -> Result<()> {
+        ctx.accounts.reclaim_emission()?;
+        Ok(())
+    }
+
+    pub fn init_token_vault(
+        ctx: Context<InitTokenVault>
+    ) -> Result<()> {
+        ctx.accounts.init_token_vault()?;
+        Ok(())
+    }
+
+    pub fn init_gate_escrow(
+        ctx: Context<InitGateEscrow>
+    ) -> Result<()> {
+        ctx.accounts.init_gate_escrow()?;
+        Ok(())
+    }
+
+    pub fn enable_token_mode(
+        ctx: Context<EnableTokenMode>
+    ) -> Result<()> {
+        ctx.accounts.enable_token_mode()?;
+        Ok(())
+    }
+
+    pub fn init_receipt_mint(
+        ctx: Context<InitReceiptMint>
+    ) -> Result<()> {
+        ctx.accounts.init_receipt_mint()?;
+        Ok(())
+    }
+
+    pub fn mint_receipt(
+        ctx: Context<MintReceipt>,
+        amount: u64
+    ) -> Result<()> {
+        ctx.accounts.mint_receipt(amount)?;
+        Ok(())
+    }
+
+    pub fn burn_receipt(
+        ctx: Context<BurnReceipt>,
+        amount: u64
+    ) -> Result<()> {
+        ctx.accounts.burn_receipt(amount)?;
+        Ok(())
+    }
+
+    pub fn mint_position_nft(
+        ctx: Context<MintPositionNft>,
+        uri: String
+    ) -> Result<()> {
+        ctx.accounts.mint_position_nft(
+            uri,
+            ctx.bumps.position_mint
+        )?;
+        Ok(())
+    }
+
+    pub fn sync_position_nft(
+        ctx: Context<SyncPositionNft>
+    ) -> Result<()> {
+        ctx.accounts.sync_position_nft()?;
+        Ok(())
+    }
+
+    pub fn enforce_gate(
+        ctx: Context<EnforceGate>
+    ) -> Result<()> {
+        ctx.accounts.enforce_gate()?;
+        Ok(())
+    }
+
+    pub fn update_gate_account(
+        ctx: Context<UpdateGateAccount>
+    ) -> Result<()> {
+        ctx.accounts.update_gate_account()?;
+        Ok(())
+    }
+
+    pub fn withdraw_excess(
+        ctx: Context<WithdrawExcess>,
+        amount: u64
+    ) -> Result<()> {
+        ctx.accounts.withdraw_excess(amount)?;
+        Ok(())
+    }
+
     pub fn update_tier(
         ctx: Context<UpdateTier>,
         minimum_token_amount: Option<u64>,
-        apy: Option<u64>,
-        lock_duration: Option<u64>,
-        is_active: Option<bool>
+        is_active: Option<bool>,
+        escrow_gating: Option<bool>
     ) -> Result<()> {
         ctx.accounts.update_tier(
             minimum_token_amount, 
-            apy, 
-            lock_duration, 
-            is_active
+            is_active,
+            escrow_gating
         )?;
         Ok(())
     }
 
+    pub fn set_tier_gates(
+        ctx: Context<SetTierGates>,
+        gates: Vec<GateAsset>
+    ) -> Result<()> {
+        ctx.accounts.set_tier_gates(gates)?;
+        Ok(())
+    }
+
+    pub fn set_tier_boosts(
+        ctx: Context<SetTierBoosts>,
+        boosts: Vec<BoostBracket>
+    ) -> Result<()> {
+        ctx.accounts.set_tier_boosts(boosts)?;
+        Ok(())
+    }
+
     pub fn update_mine(
         ctx: Context<UpdateMine>,
         fee_collector: Option<Pubkey>,
         penalty_fee_collector: Option<Pubkey>,
-        dev_fee: Option<u64>,
-        early_withdrawal_fee: Option<u64>,
-        referral_reward: Option<u64>,
-        is_active: Option<bool>
+        is_active: Option<bool>,
+        referral_on_claim: Option<bool>
     ) -> Result<()> {
         ctx.accounts.update_mine(
             fee_collector, 
             penalty_fee_collector, 
-            dev_fee, 
-            early_withdrawal_fee, 
-            referral_reward, 
-            is_active
+            is_active,
+            referral_on_claim
+        )?;
+        Ok(())
+    }
+
+    pub fn queue_mine_change(
+        ctx: Context<QueueMineChange>,
+        dev_fee: Option<u64>,
+        early_withdrawal_fee: Option<u64>,
+        referral_reward: Option<u64>,
+        upline_referral_rewards: Option<[u64; 2]>,
+        timelock_delay: Option<u64>
+    ) -> Result<()> {
+        ctx.accounts.queue_mine_change(
+            dev_fee,
+            early_withdrawal_fee,
+            referral_reward,
+            upline_referral_rewards,
+            timelock_delay,
+            ctx.bumps.pending_change
+        )?;
+        Ok(())
+    }
+
+    pub fn execute_mine_change(
+        ctx: Context<ExecuteMineChange>
+    ) -> Result<()> {
+        ctx.accounts.execute_mine_change()?;
+        Ok(())
+    }
+
+    pub fn cancel_mine_change(
+        ctx: Context<CancelMineChange>
+    ) -> Result<()> {
+        ctx.accounts.cancel_mine_change()?;
+        Ok(())
+    }
+
+    pub fn queue_tier_change(
+        ctx: Context<QueueTierChange>,
+        apy: Option<u64>,
+        lock_duration: Option<u64>
+    ) -> Result<()> {
+        ctx.accounts.queue_tier_change(
+            apy,
+            lock_duration,
+            ctx.bumps.pending_change
+        )?;
+        Ok(())
+    }
+
+    pub fn execute_tier_change(
+        ctx: Context<ExecuteTierChange>
+    ) -> Result<()> {
+        ctx.accounts.execute_tier_change()?;
+        Ok(())
+    }
+
+    pub fn cancel_tier_change(
+        ctx: Context<CancelTierChange>
+    ) -> Result<()> {
+        ctx.accounts.cancel_tier_change()?;
+        Ok(())
+    }
+
+    pub fn propose_admin(
+        ctx: Context<ProposeAdmin>,
+        new_admin: Pubkey
+    ) -> Result<()> {
+        ctx.accounts.propose_admin(new_admin)?;
+        Ok(())
+    }
+
+    pub fn cancel_admin_transfer(
+        ctx: Context<ProposeAdmin>
+    ) -> Result<()> {
+        ctx.accounts.cancel_admin_transfer()?;
+        Ok(())
+    }
+
+    pub fn accept_admin(
+        ctx: Context<AcceptAdmin>
+    ) -> Result<()> {
+        ctx.accounts.accept_admin()?;
+        Ok(())
+    }
+
+    pub fn set_roles(
+        ctx: Context<SetRoles>,
+        tier_manager: Option<Pubkey>,
+        whitelister: Option<Pubkey>,
+        fee_manager: Option<Pubkey>,
+        pauser: Option<Pubkey>
+    ) -> Result<()> {
+        ctx.accounts.set_roles(
+            tier_manager,
+            whitelister,
+            fee_manager,
+            pauser
         )?;
         Ok(())
     }
-}
+}
\ No newline at end of file
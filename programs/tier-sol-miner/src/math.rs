@@ -157,6 +157,12 @@ use crate::errors::MinerError;
 //     }
 // }
 
+/// Fees and rewards are expressed in basis points of this value
+pub const BASIS_POINTS: u64 = 10_000;
+
+/// Scale of the accumulated reward-per-share index of emission tiers
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
 pub fn muldiv(a: u128, b: u128, c: u128) -> Option<u128> {
     a.checked_mul(b)?.checked_div(c)
 }
@@ -171,7 +177,7 @@ pub fn to_u64(val: u128) -> Result<u64, MinerError> {
 
 pub fn calculate_fee(amount: u128, fee: u128) -> Result<u64, MinerError> {
     match muldiv(amount, fee, 
-        to_u128(10000)?
+        to_u128(BASIS_POINTS)?
     ) {
       Some(val) => to_u64(val),
       None => Err(MinerError::MathsError)
@@ -186,4 +192,9 @@ pub fn calculate_interest(amount: u128, apy: u128, interval: u128) -> Result<u64
         },
         None => Err(MinerError::MathsError)
     }
-}
\ No newline at end of file
+}
+/// APY raised by a boost expressed in basis points of it
+pub fn boosted_apy(apy: u64, boost: u64) -> Result<u64, MinerError> {
+    let extra = calculate_fee(to_u128(apy)?, to_u128(boost)?)?;
+    Ok(apy.saturating_add(extra))
+}
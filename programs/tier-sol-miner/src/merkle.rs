@@ -0,0 +1,20 @@
+use anchor_lang::prelude::*;
+use solana_program::hash::hashv;
+
+/// Leaf of the allowlist tree: sha256(leaf_index_le || beneficiary)
+pub fn allowlist_leaf(index: u32, beneficiary: &Pubkey) -> [u8; 32] {
+    hashv(&[&index.to_le_bytes(), beneficiary.as_ref()]).to_bytes()
+}
+
+/// Verifies a proof built with sorted pairs, so no direction bits are needed
+pub fn verify_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let mut computed = leaf;
+    for node in proof.iter() {
+        computed = if computed <= *node {
+            hashv(&[&computed, node]).to_bytes()
+        } else {
+            hashv(&[node, &computed]).to_bytes()
+        };
+    }
+    computed == *root
+}
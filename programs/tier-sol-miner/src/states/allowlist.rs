@@ -0,0 +1,51 @@
+use anchor_lang::prelude::*;
+use crate::errors::MinerError;
+use crate::merkle::{allowlist_leaf, verify_proof};
+
+#[account]
+pub struct AllowList {
+    pub root: [u8; 32],
+    pub tier: Pubkey,
+    pub expiry: u64,
+    pub capacity: u32,
+    pub bump: u8,
+    pub claimed: Vec<u8>
+}
+
+impl Space for AllowList {
+    const INIT_SPACE: usize = 32 + 32 + 8 + 4 + 1 + 4;
+}
+
+impl AllowList {
+    /// Number of bytes needed by the claimed bitmap for `capacity` leaves
+    pub fn bitmap_len(capacity: u32) -> usize {
+        (capacity as usize).div_ceil(8)
+    }
+
+    pub fn is_claimed(&self, index: u32) -> bool {
+        self.claimed[(index / 8) as usize] & (1 << (index % 8)) != 0
+    }
+
+    pub fn set_claimed(&mut self, index: u32) {
+        self.claimed[(index / 8) as usize] |= 1 << (index % 8);
+    }
+
+    /// Checks `proof` for the leaf of `beneficiary` at `index` and marks it
+    /// as claimed
+    pub fn claim(&mut self, index: u32, beneficiary: &Pubkey, proof: &[[u8; 32]], now: u64) -> Result<()> {
+        if now > self.expiry {
+            return err!(MinerError::ExpiredWhiteList);
+        }
+        if index >= self.capacity {
+            return err!(MinerError::InvalidProof);
+        }
+        if self.is_claimed(index) {
+            return err!(MinerError::AlreadyClaimed);
+        }
+        if !verify_proof(proof, &self.root, allowlist_leaf(index, beneficiary)) {
+            return err!(MinerError::InvalidProof);
+        }
+        self.set_claimed(index);
+        Ok(())
+    }
+}
@@ -1,7 +1,15 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::TokenAccount;
+use crate::errors::MinerError;
+use crate::math::{BASIS_POINTS, calculate_fee, to_u128};
+use crate::states::referral::REFERRAL_LEVELS;
+use crate::states::tier::TierInfo;
 
 #[account]
 pub struct MineInfo {
+    /// Key that created the mine, together with `id` it seeds the mine PDA
+    pub creator: Pubkey,
+    pub id: u64,
     pub admin: Pubkey,
     pub token_mint: Pubkey,
     pub fee_collector: Pubkey,
@@ -11,18 +19,258 @@ pub struct MineInfo {
     pub referral_reward: u64,
     pub bump: u8,
     pub current_tier_nonce: u8,
-    pub is_active: bool
+    pub is_active: bool,
+    pub pending_admin: Option<Pubkey>,
+    pub timelock_delay: u64,
+    pub max_dev_fee: u64,
+    pub max_early_withdrawal_fee: u64,
+    pub max_referral_reward: u64,
+    /// Mint staked in token mode, `None` while the mine stakes SOL
+    pub stake_mint: Option<Pubkey>,
+    /// Mint interest is paid in, may be the same as the stake mint
+    pub reward_mint: Option<Pubkey>,
+    /// Whether claimed interest also credits the staker's referrer
+    pub referral_on_claim: bool,
+    /// Deposit rewards for the referrer's referrer and the one above it,
+    /// zero turns a level off
+    pub upline_referral_rewards: [u64; 2]
+}
+
+/// Parameters a mine is created with
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct MineConfig {
+    pub fee_collector: Pubkey,
+    pub penalty_fee_collector: Pubkey,
+    pub token_mint: Pubkey,
+    pub dev_fee: u64,
+    pub early_withdrawal_fee: u64,
+    pub referral_reward: u64,
+    pub timelock_delay: u64,
+    pub max_dev_fee: u64,
+    pub max_early_withdrawal_fee: u64,
+    pub max_referral_reward: u64
 }
 
 impl Space for MineInfo {
-    const INIT_SPACE: usize = (32 * 4) + (8 * 3) + 3;
+    const INIT_SPACE: usize = (32 * 5) + (8 * 10) + 4 + ((1 + 32) * 3);
+}
+
+impl MineInfo {
+    pub fn is_native(&self) -> bool {
+        self.stake_mint.is_none()
+    }
+
+    /// Whether interest is paid in the staked asset, always the case for
+    /// native mines
+    pub fn pays_in_stake_mint(&self) -> bool {
+        self.reward_mint == self.stake_mint
+    }
+
+    /// Whether the mine can pay the interest of `tier`. Fixed and continuous
+    /// tiers work out interest in units of the stake mint, so they need it
+    /// to be the reward mint, emission tiers pay out a funded budget.
+    pub fn pays_interest_of(&self, tier: &TierInfo) -> bool {
+        tier.is_emission() || self.pays_in_stake_mint()
+    }
+
+    /// Referral reward of a level of the referrer chain, 0 being the
+    /// direct referrer
+    pub fn referral_rate(&self, level: usize) -> u64 {
+        match level {
+            0 => self.referral_reward,
+            _ => self.upline_referral_rewards.get(level - 1).copied().unwrap_or(0)
+        }
+    }
+
+    /// Splits an amount paid out of the vault into the dev fee, the early
+    /// withdrawal penalty when `is_early` and what is left for the holder
+    pub fn payout(&self, amount: u64, is_early: bool) -> Result<Payout> {
+        let dev_fee = calculate_fee(to_u128(amount)?, to_u128(self.dev_fee)?)?;
+        let penalty = if is_early {
+            calculate_fee(to_u128(amount)?, to_u128(self.early_withdrawal_fee)?)?
+        } else {
+            0_u64
+        };
+        Ok(Payout {
+            amount_out: amount.saturating_sub(dev_fee).saturating_sub(penalty),
+            dev_fee,
+            penalty
+        })
+    }
+
+    /// Checks every fee against its cap and that the dev fee plus the early
+    /// withdrawal penalty can never take more than the full amount
+    pub fn validate_fees(&self) -> Result<()> {
+        if self.max_dev_fee > BASIS_POINTS
+            || self.max_early_withdrawal_fee > BASIS_POINTS
+            || self.max_referral_reward > BASIS_POINTS {
+            return err!(MinerError::InvalidArgument);
+        }
+        if self.dev_fee > self.max_dev_fee {
+            return err!(MinerError::DevFeeTooHigh);
+        }
+        if self.early_withdrawal_fee > self.max_early_withdrawal_fee {
+            return err!(MinerError::EarlyWithdrawalFeeTooHigh);
+        }
+        if self.referral_reward > self.max_referral_reward {
+            return err!(MinerError::ReferralRewardTooHigh);
+        }
+        if (1..REFERRAL_LEVELS).any(|level| self.referral_rate(level) > self.referral_rate(level - 1)) {
+            return err!(MinerError::InvalidReferralLevels);
+        }
+        if self.dev_fee.saturating_add(self.early_withdrawal_fee) > BASIS_POINTS {
+            return err!(MinerError::CombinedFeeTooHigh);
+        }
+        Ok(())
+    }
 }
 
 #[account]
 pub struct MineVault {
-    pub bump: u8
+    pub bump: u8,
+    pub total_principal: u64,
+    pub total_promised_interest: u64
 }
 
 impl Space for MineVault {
-    const INIT_SPACE: usize = 1;
-}
\ No newline at end of file
+    const INIT_SPACE: usize = 1 + (8 * 2);
+}
+
+impl MineVault {
+    /// Principal plus interest and referral rewards owed to stakers. Funded
+    /// rewards are not tracked on their own, they are the vault balance
+    /// above this amount.
+    pub fn total_liabilities(&self) -> u64 {
+        self.total_principal.saturating_add(self.total_promised_interest)
+    }
+}
+
+/// Receiver of a part of a payout
+#[derive(Clone, Copy)]
+pub enum Payee {
+    Owner,
+    FeeCollector,
+    PenaltyCollector
+}
+
+/// Amount paid out of the vault split between the position holder and the
+/// fee collectors, see `MineInfo::payout`
+pub struct Payout {
+    pub amount_out: u64,
+    pub dev_fee: u64,
+    pub penalty: u64
+}
+
+impl Payout {
+    /// Sends every part of the payout with `transfer`, which moves lamports
+    /// or tokens depending on the mine mode
+    pub fn send(&self, mut transfer: impl FnMut(Payee, u64) -> Result<()>) -> Result<()> {
+        transfer(Payee::FeeCollector, self.dev_fee)?;
+        transfer(Payee::PenaltyCollector, self.penalty)?;
+        transfer(Payee::Owner, self.amount_out)
+    }
+}
+
+/// What the vaults lack to cover their liabilities. Native mines and token
+/// mines with a single vault count all of it as interest.
+#[derive(Clone, Copy, Default)]
+pub struct Shortfall {
+    pub principal: u64,
+    pub interest: u64
+}
+
+impl Shortfall {
+    /// Shortfall once `interest` the vault already owed but had not
+    /// recorded, like continuous accrual, is counted
+    pub fn owing(self, interest: u64) -> Self {
+        Shortfall {
+            interest: self.interest.saturating_add(interest),
+            ..self
+        }
+    }
+
+    pub fn is_covered(&self) -> bool {
+        self.principal == 0 && self.interest == 0
+    }
+}
+
+/// Shortfall of the vault lamports above its rent exempt minimum
+pub fn shortfall(mine_vault: &Account<MineVault>) -> Result<Shortfall> {
+    let rent = Rent::get()?.minimum_balance(mine_vault.to_account_info().data_len());
+    let available = mine_vault.get_lamports().saturating_sub(rent);
+    Ok(Shortfall {
+        principal: 0,
+        interest: mine_vault.total_liabilities().saturating_sub(available)
+    })
+}
+
+/// Token mode counterpart of `shortfall`. Principal is owed in the stake
+/// mint and interest in the reward mint, which may share a vault.
+pub fn token_shortfall(
+    mine_vault: &MineVault,
+    stake_vault: &InterfaceAccount<TokenAccount>,
+    reward_vault: &InterfaceAccount<TokenAccount>
+) -> Shortfall {
+    if stake_vault.key() == reward_vault.key() {
+        return Shortfall {
+            principal: 0,
+            interest: mine_vault.total_liabilities().saturating_sub(stake_vault.amount)
+        };
+    }
+    Shortfall {
+        principal: mine_vault.total_principal.saturating_sub(stake_vault.amount),
+        interest: mine_vault.total_promised_interest.saturating_sub(reward_vault.amount)
+    }
+}
+
+/// Shortfall of the vaults of either mode, token mode mines have to pass
+/// their stake and reward vaults. The vault balances are read again so
+/// transfers made earlier in the instruction are counted.
+pub fn mine_shortfall<'info>(
+    mine_info: &MineInfo,
+    mine_vault: &Account<'info, MineVault>,
+    stake_vault: &Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    reward_vault: &Option<Box<InterfaceAccount<'info, TokenAccount>>>
+) -> Result<Shortfall> {
+    if mine_info.is_native() {
+        return shortfall(mine_vault);
+    }
+    let (Some(stake_vault), Some(reward_vault)) = (stake_vault, reward_vault) else {
+        return err!(MinerError::MissingTokenAccounts);
+    };
+    let mut stake_vault = stake_vault.clone();
+    let mut reward_vault = reward_vault.clone();
+    stake_vault.reload()?;
+    reward_vault.reload()?;
+    Ok(token_shortfall(mine_vault, &stake_vault, &reward_vault))
+}
+
+/// Errors when the vaults cannot cover everything owed to stakers
+pub fn check_mine_solvency<'info>(
+    mine_info: &MineInfo,
+    mine_vault: &Account<'info, MineVault>,
+    stake_vault: &Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    reward_vault: &Option<Box<InterfaceAccount<'info, TokenAccount>>>
+) -> Result<()> {
+    if !mine_shortfall(mine_info, mine_vault, stake_vault, reward_vault)?.is_covered() {
+        return err!(MinerError::InsufficientVaultBalance);
+    }
+    Ok(())
+}
+
+/// Errors when the vaults end up further short than `before`. Instructions
+/// that pay out what they take off the liabilities pass this on a vault
+/// that is already short, so stakers can still get out.
+pub fn check_shortfall_kept<'info>(
+    mine_info: &MineInfo,
+    mine_vault: &Account<'info, MineVault>,
+    stake_vault: &Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    reward_vault: &Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    before: Shortfall
+) -> Result<()> {
+    let after = mine_shortfall(mine_info, mine_vault, stake_vault, reward_vault)?;
+    if after.principal > before.principal || after.interest > before.interest {
+        return err!(MinerError::InsufficientVaultBalance);
+    }
+    Ok(())
+}
@@ -2,4 +2,7 @@ pub mod tier;
 pub mod mine;
 pub mod user;
 pub mod referral;
-pub mod whitelist;
\ No newline at end of file
+pub mod whitelist;
+pub mod allowlist;
+pub mod roles;
+pub mod pending;
\ No newline at end of file
@@ -0,0 +1,33 @@
+use anchor_lang::prelude::*;
+
+/// Mine parameter change waiting for `effective_ts` before it can be applied
+#[account]
+pub struct PendingMineChange {
+    pub proposer: Pubkey,
+    pub dev_fee: Option<u64>,
+    pub early_withdrawal_fee: Option<u64>,
+    pub referral_reward: Option<u64>,
+    pub upline_referral_rewards: Option<[u64; 2]>,
+    pub timelock_delay: Option<u64>,
+    pub effective_ts: u64,
+    pub bump: u8
+}
+
+impl Space for PendingMineChange {
+    const INIT_SPACE: usize = 32 + (4 * (1 + 8)) + (1 + 16) + 8 + 1;
+}
+
+/// Tier parameter change waiting for `effective_ts` before it can be applied
+#[account]
+pub struct PendingTierChange {
+    pub proposer: Pubkey,
+    pub tier: Pubkey,
+    pub apy: Option<u64>,
+    pub lock_duration: Option<u64>,
+    pub effective_ts: u64,
+    pub bump: u8
+}
+
+impl Space for PendingTierChange {
+    const INIT_SPACE: usize = (32 * 2) + (2 * (1 + 8)) + 8 + 1;
+}
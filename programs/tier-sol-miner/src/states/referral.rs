@@ -1,14 +1,48 @@
 use anchor_lang::prelude::*;
+use crate::math::{calculate_fee, to_u128};
+
+/// Levels of the referrer chain credited on deposits, the direct referrer
+/// being level 0
+pub const REFERRAL_LEVELS: usize = 3;
 
 #[account]
 pub struct ReferralInfo {
     pub user_info: Pubkey,
     pub owner: Pubkey,
+    /// Unclaimed earnings across all levels
     pub earnings: u64,
+    /// Positions referred directly
     pub count: u64,
-    pub bump: u8
+    pub bump: u8,
+    /// Positions referred at each level of the chain
+    pub level_counts: [u64; REFERRAL_LEVELS],
+    /// Rewards ever credited at each level of the chain
+    pub level_earnings: [u64; REFERRAL_LEVELS]
 }
 
 impl Space for ReferralInfo {
-    const INIT_SPACE: usize = (32 * 2) + (8 * 2) + 1;
-}
\ No newline at end of file
+    const INIT_SPACE: usize = (32 * 2) + (8 * 2) + 1 + (8 * REFERRAL_LEVELS * 2);
+}
+
+impl ReferralInfo {
+    /// Counts a new position referred at `level`, only direct referrals
+    /// add to `count`
+    pub fn add_referral(&mut self, level: usize) {
+        if level == 0 {
+            self.count = self.count.saturating_add(1);
+        }
+        self.level_counts[level] = self.level_counts[level].saturating_add(1);
+    }
+
+    /// Adds the referral reward on `amount` to the earnings of `level`,
+    /// returns the bonus credited
+    pub fn credit(&mut self, level: usize, amount: u64, referral_reward: u64) -> Result<u64> {
+        let bonus = calculate_fee(
+            to_u128(amount)?,
+            to_u128(referral_reward)?
+        )?;
+        self.earnings = self.earnings.saturating_add(bonus);
+        self.level_earnings[level] = self.level_earnings[level].saturating_add(bonus);
+        Ok(bonus)
+    }
+}
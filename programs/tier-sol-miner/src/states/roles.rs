@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+/// Operator keys allowed to run admin actions without the treasury admin.
+/// The mine admin always holds every role.
+#[account]
+pub struct MineRoles {
+    pub tier_manager: Pubkey,
+    pub whitelister: Pubkey,
+    pub fee_manager: Pubkey,
+    pub pauser: Pubkey,
+    pub bump: u8
+}
+
+impl Space for MineRoles {
+    const INIT_SPACE: usize = (32 * 4) + 1;
+}
+
+impl MineRoles {
+    pub fn is_tier_manager(&self, admin: &Pubkey, key: &Pubkey) -> bool {
+        self.tier_manager == *key || admin == key
+    }
+
+    pub fn is_whitelister(&self, admin: &Pubkey, key: &Pubkey) -> bool {
+        self.whitelister == *key || admin == key
+    }
+
+    pub fn is_fee_manager(&self, admin: &Pubkey, key: &Pubkey) -> bool {
+        self.fee_manager == *key || admin == key
+    }
+
+    pub fn is_pauser(&self, admin: &Pubkey, key: &Pubkey) -> bool {
+        self.pauser == *key || admin == key
+    }
+}
@@ -1,16 +1,212 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+use spl_token_group_interface::state::TokenGroupMember;
+use crate::errors::MinerError;
+use crate::math::{muldiv, to_u128, to_u64, REWARD_PRECISION};
+
+/// How interest is earned by positions in a tier
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AccrualMode {
+    /// Interest for the full lock is promised upfront at deposit
+    Fixed,
+    /// Interest accrues per second since the position's last checkpoint
+    Continuous,
+    /// A funded reward budget is emitted per second and shared pro-rata
+    /// by stake, payouts can never exceed what was funded
+    Emission
+}
+
+/// Most assets a tier can accept for its token gate
+pub const MAX_TIER_GATES: usize = 4;
+
+/// Kind of asset that passes a tier's token gate
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum GateKind {
+    /// Fungible mint, the holder needs at least the asset's minimum
+    Mint,
+    /// Token-2022 group, holding any one member mint qualifies
+    Collection
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct GateAsset {
+    pub kind: GateKind,
+    /// Mint or group address depending on the kind
+    pub key: Pubkey,
+    pub minimum: u64
+}
+
+impl Space for GateAsset {
+    const INIT_SPACE: usize = 1 + 32 + 8;
+}
+
+/// Most boost brackets a tier can define
+pub const MAX_BOOST_BRACKETS: usize = 4;
+
+/// APY boost for stakers holding at least `min_balance` of the mine's
+/// token mint
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct BoostBracket {
+    pub min_balance: u64,
+    /// Added to the APY in basis points of it
+    pub boost: u64
+}
+
+impl Space for BoostBracket {
+    const INIT_SPACE: usize = 8 + 8;
+}
 
 #[account]
 pub struct TierInfo {
+    /// Mine the tier belongs to
+    pub mine: Pubkey,
     pub minimum_token_amount: u64,
     pub total_locked: u64,
     pub apy: u64,
     pub lock_duration: u64,
     pub is_active: bool,
     pub bump: u8,
-    pub nonce: u8
+    pub nonce: u8,
+    pub accrual_mode: AccrualMode,
+    /// Lamports emitted per second for emission tiers
+    pub reward_rate: u64,
+    /// Funded lamports that have not been emitted yet
+    pub reward_remaining: u64,
+    pub last_reward_ts: u64,
+    /// Rewards emitted per staked lamport, scaled by `REWARD_PRECISION`
+    pub acc_reward_per_share: u128,
+    /// Mint of the liquid receipt token for positions in this tier
+    pub receipt_mint: Option<Pubkey>,
+    /// Gating tokens are locked in the mine's gate escrow for the life of
+    /// a position instead of being checked against the wallet balance
+    pub escrow_gating: bool,
+    /// Assets accepted by the token gate, when empty the mine's token mint
+    /// and `minimum_token_amount` apply
+    pub gates: Vec<GateAsset>,
+    /// APY boost brackets sorted by balance
+    pub boosts: Vec<BoostBracket>,
+    /// Principal backing receipts of positions closed by
+    /// `terminate_staking`, redeemable by any receipt holder
+    pub detached_receipts: u64
 }
 
 impl Space for TierInfo {
-    const INIT_SPACE: usize = (8 * 4) + 3;
-}
\ No newline at end of file
+    const INIT_SPACE: usize = 32 + (8 * 4) + 3 + 1 + (8 * 3) + 16 + (1 + 32) + 1 + 4 + (GateAsset::INIT_SPACE * MAX_TIER_GATES)
+        + 4 + (BoostBracket::INIT_SPACE * MAX_BOOST_BRACKETS) + 8;
+}
+
+impl TierInfo {
+    pub fn is_fixed(&self) -> bool {
+        self.accrual_mode == AccrualMode::Fixed
+    }
+
+    pub fn is_emission(&self) -> bool {
+        self.accrual_mode == AccrualMode::Emission
+    }
+
+    /// Whether a token account passes the tier's token gate. `gate_mint` is
+    /// the mint of the held token, it is only read for collection gates.
+    pub fn admits(
+        &self,
+        token_mint: &Pubkey,
+        token_account: &TokenAccount,
+        gate_mint: &Option<InterfaceAccount<Mint>>
+    ) -> bool {
+        if self.admits_balance(token_mint, &token_account.mint, token_account.amount) {
+            return true;
+        }
+        self.gates.iter().any(|gate| gate.kind == GateKind::Collection && token_account.amount > 0 && match gate_mint {
+            Some(mint) => mint.key() == token_account.mint && collection_of(mint) == Some(gate.key),
+            None => false
+        })
+    }
+
+    /// Whether the tier admits `amount` of `mint` by the mine's token mint
+    /// or one of its mint gates. Collection gates need the mint account and
+    /// are only checked by `admits`.
+    pub fn admits_balance(&self, token_mint: &Pubkey, mint: &Pubkey, amount: u64) -> bool {
+        if self.gates.is_empty() {
+            return mint == token_mint && amount >= self.minimum_token_amount;
+        }
+        self.gates.iter().any(|gate| gate.kind == GateKind::Mint && *mint == gate.key && amount >= gate.minimum)
+    }
+
+    /// Whether the tier admits holders that have no gating token at all
+    pub fn is_ungated(&self) -> bool {
+        self.gates.is_empty() && self.minimum_token_amount == 0
+    }
+
+    /// Boost of the highest bracket reached by the balance of a token
+    /// account, only the mine's token mint counts towards it
+    pub fn boost_for(&self, token_mint: &Pubkey, token_account: &TokenAccount) -> u64 {
+        if token_account.mint != *token_mint {
+            return 0;
+        }
+        self.boosts.iter()
+            .filter(|bracket| token_account.amount >= bracket.min_balance)
+            .map(|bracket| bracket.boost)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Emits rewards for the time since the last update into the
+    /// reward-per-share index. Nothing is emitted while the tier is empty.
+    pub fn update_emission(&mut self, now: u64) -> Result<()> {
+        if !self.is_emission() || now <= self.last_reward_ts {
+            return Ok(());
+        }
+        if self.total_locked > 0 {
+            let elapsed = now.saturating_sub(self.last_reward_ts);
+            let reward = elapsed
+                .saturating_mul(self.reward_rate)
+                .min(self.reward_remaining);
+            let increment = match muldiv(
+                to_u128(reward)?,
+                REWARD_PRECISION,
+                to_u128(self.total_locked)?
+            ) {
+                Some(val) => val,
+                None => return err!(MinerError::MathsError)
+            };
+            self.acc_reward_per_share = self.acc_reward_per_share.saturating_add(increment);
+            self.reward_remaining = self.reward_remaining.saturating_sub(reward);
+        }
+        self.last_reward_ts = now;
+        Ok(())
+    }
+
+    /// Share of the index already accounted for a given principal
+    pub fn reward_debt(&self, total_locked: u64) -> Result<u128> {
+        match muldiv(
+            to_u128(total_locked)?,
+            self.acc_reward_per_share,
+            REWARD_PRECISION
+        ) {
+            Some(val) => Ok(val),
+            None => err!(MinerError::MathsError)
+        }
+    }
+
+    /// Rewards owed to a position since its debt was last synced
+    pub fn pending_emission(&self, total_locked: u64, reward_debt: u128) -> Result<u64> {
+        let accumulated = self.reward_debt(total_locked)?;
+        Ok(to_u64(accumulated.saturating_sub(reward_debt))?)
+    }
+}
+
+/// Group a Token-2022 mint is a member of. Membership is written by the
+/// group's update authority, so it can be trusted as verified.
+fn collection_of(mint: &InterfaceAccount<Mint>) -> Option<Pubkey> {
+    let info = mint.to_account_info();
+    if info.owner != &spl_token_2022::ID {
+        return None;
+    }
+    let data = info.try_borrow_data().ok()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data).ok()?;
+    let member = state.get_extension::<TokenGroupMember>().ok()?;
+    if member.mint != mint.key() {
+        return None;
+    }
+    Some(member.group)
+}
@@ -1,4 +1,9 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::TokenAccount;
+use crate::errors::MinerError;
+use crate::math::{boosted_apy, calculate_interest, to_u128};
+use crate::states::tier::{TierInfo, AccrualMode};
+use crate::states::mine::{MineInfo, MineVault, Payout};
 
 #[account]
 pub struct UserInfo {
@@ -8,9 +13,475 @@ pub struct UserInfo {
     pub accrued_interest: u64,
     pub lock_ts: u64,
     pub tier: Pubkey,
-    pub is_whitelist: bool
+    pub is_whitelist: bool,
+    pub index: u64,
+    /// Tier terms the position was opened under, tier updates do not
+    /// apply until the owner calls `refresh_terms`
+    pub apy: u64,
+    pub lock_duration: u64,
+    /// Last time interest was settled into `accrued_interest` for
+    /// continuous accrual tiers
+    pub last_accrual_ts: u64,
+    /// Emission tier rewards already accounted for this position
+    pub reward_debt: u128,
+    /// Principal represented by receipt tokens, it stays locked until
+    /// they are burned
+    pub receipt_amount: u64,
+    /// Position NFT mint, once set its holder acts on the position
+    /// instead of `owner`
+    pub nft_mint: Option<Pubkey>,
+    /// Gating tokens locked in escrow for this position, released when
+    /// the position is withdrawn or terminated
+    pub escrowed_amount: u64,
+    /// APY boost from the gating balance in basis points, recomputed when
+    /// the owner deposits, increases, compounds or claims
+    pub boost: u64,
+    /// Position that referred the staker, its referral account is credited
+    /// on later top ups
+    pub referrer: Option<Pubkey>,
+    /// Referrers above the direct one, copied from the referrer's position
+    /// when the staker was referred
+    pub upline_referrers: [Option<Pubkey>; 2],
+    /// Token account the gate was last checked on, `enforce_gate` checks
+    /// this account
+    pub gate_account: Option<Pubkey>
 }
 
 impl Space for UserInfo {
-    const INIT_SPACE: usize = (32 * 2) + (3 * 8) + 2;
+    const INIT_SPACE: usize = (32 * 2) + (8 * 10) + 2 + 16 + ((1 + 32) * 5);
+}
+
+impl UserInfo {
+    /// Empty position of `owner` in `tier`, its principal and terms are set
+    /// by `open`
+    pub fn new(bump: u8, owner: Pubkey, index: u64, tier: Pubkey) -> Self {
+        Self {
+            bump,
+            owner,
+            total_locked: 0,
+            accrued_interest: 0,
+            lock_ts: 0,
+            tier,
+            is_whitelist: false,
+            index,
+            apy: 0,
+            lock_duration: 0,
+            last_accrual_ts: 0,
+            reward_debt: 0,
+            receipt_amount: 0,
+            nft_mint: None,
+            escrowed_amount: 0,
+            boost: 0,
+            referrer: None,
+            upline_referrers: [None; 2],
+            gate_account: None
+        }
+    }
+
+    /// Wallet allowed to act on the position. That is the holder of the
+    /// position NFT when one was minted, `None` if it is not provided.
+    pub fn authority(&self, position_nft: &Option<InterfaceAccount<TokenAccount>>) -> Option<Pubkey> {
+        match self.nft_mint {
+            None => Some(self.owner),
+            Some(nft_mint) => match position_nft {
+                Some(account) if account.mint == nft_mint && account.amount == 1 => Some(account.owner),
+                _ => None
+            }
+        }
+    }
+
+    /// Whether the position passes a tier's gate. Positions with tokens in
+    /// escrow keep the gate they locked under, others need the provided
+    /// token account to be admitted by the tier.
+    pub fn meets_gate(&self, admitted: bool) -> bool {
+        self.escrowed_amount > 0 || admitted
+    }
+
+    /// Whether the escrowed tokens can stand in for the gate of another
+    /// tier. The escrow holds the mine's token mint, so the tier must accept
+    /// it and ask for no more than what is locked.
+    pub fn escrow_covers(&self, token_mint: &Pubkey, to_tier: &TierInfo) -> bool {
+        self.escrowed_amount > 0 && to_tier.admits_balance(token_mint, token_mint, self.escrowed_amount)
+    }
+
+    /// APY the position earns at, its terms plus the gating boost
+    pub fn effective_apy(&self) -> Result<u64> {
+        Ok(boosted_apy(self.apy, self.boost)?)
+    }
+
+    /// Part of a fixed position's promised interest that is already earned.
+    /// What the current terms would pay for the rest of the lock is not.
+    pub fn earned_interest(&self, now: u64) -> Result<u64> {
+        let remaining = self.lock_ts
+            .saturating_add(self.lock_duration)
+            .saturating_sub(now)
+            .min(self.lock_duration);
+        match calculate_interest(
+            to_u128(self.total_locked)?,
+            to_u128(self.effective_apy()?)?,
+            to_u128(remaining)?
+        ) {
+            Ok(val) => Ok(self.accrued_interest.saturating_sub(val)),
+            Err(_) => err!(MinerError::MathsError)
+        }
+    }
+
+    /// Interest earned since the last checkpoint under continuous accrual
+    pub fn pending_interest(&self, now: u64) -> Result<u64> {
+        match calculate_interest(
+            to_u128(self.total_locked)?,
+            to_u128(self.effective_apy()?)?,
+            to_u128(now.saturating_sub(self.last_accrual_ts))?
+        ) {
+            Ok(val) => Ok(val),
+            Err(_) => err!(MinerError::MathsError)
+        }
+    }
+
+    /// Settles pending interest into `accrued_interest` and moves the
+    /// checkpoint to `now`. Returns the amount settled.
+    pub fn checkpoint(&mut self, now: u64) -> Result<u64> {
+        let pending = self.pending_interest(now)?;
+        self.accrued_interest = self.accrued_interest.saturating_add(pending);
+        self.last_accrual_ts = now;
+        Ok(pending)
+    }
+
+    /// Settles whatever the position earned since its last checkpoint into
+    /// `accrued_interest`. Returns the amount that is new to the vault
+    /// liabilities, emission rewards are already counted when funded.
+    pub fn settle(&mut self, tier_info: &mut TierInfo, now: u64) -> Result<u64> {
+        match tier_info.accrual_mode {
+            AccrualMode::Fixed => Ok(0),
+            AccrualMode::Continuous => self.checkpoint(now),
+            AccrualMode::Emission => {
+                tier_info.update_emission(now)?;
+                let pending = tier_info.pending_emission(self.total_locked, self.reward_debt)?;
+                self.accrued_interest = self.accrued_interest.saturating_add(pending);
+                self.reward_debt = tier_info.reward_debt(self.total_locked)?;
+                self.last_accrual_ts = now;
+                Ok(0)
+            }
+        }
+    }
+
+    /// Interest the position earned that the vault liabilities do not count
+    /// yet, which is what `settle` would add to them now
+    pub fn unsettled_interest(&self, tier_info: &TierInfo, now: u64) -> Result<u64> {
+        match tier_info.accrual_mode {
+            AccrualMode::Continuous => self.pending_interest(now),
+            _ => Ok(0)
+        }
+    }
+
+    /// Whether the lock of the position is still running
+    pub fn is_locked(&self, now: u64) -> bool {
+        now.saturating_sub(self.lock_ts) < self.lock_duration
+    }
+
+    /// Starts the position on `amount` of deposited principal under the
+    /// current terms of the tier and adds it to the tier and the vault
+    /// liabilities. Only fixed tiers promise interest upfront.
+    pub fn open(
+        &mut self,
+        tier_info: &mut TierInfo,
+        mine_vault: &mut MineVault,
+        amount: u64,
+        boost: u64,
+        now: u64
+    ) -> Result<()> {
+        // Bring the emission index up to date before the tier grows
+        tier_info.update_emission(now)?;
+        let interest = if !tier_info.is_fixed() {
+            0
+        } else {
+            match calculate_interest(
+                to_u128(amount)?,
+                to_u128(boosted_apy(tier_info.apy, boost)?)?,
+                to_u128(tier_info.lock_duration)?
+            ) {
+                Ok(val) => val,
+                Err(_) => return err!(MinerError::MathsError)
+            }
+        };
+        self.total_locked = amount;
+        self.accrued_interest = interest;
+        self.lock_ts = now;
+        self.apy = tier_info.apy;
+        self.lock_duration = tier_info.lock_duration;
+        self.last_accrual_ts = now;
+        self.reward_debt = tier_info.reward_debt(amount)?;
+        self.boost = boost;
+
+        tier_info.total_locked = tier_info.total_locked.saturating_add(amount);
+        mine_vault.total_principal = mine_vault.total_principal.saturating_add(amount);
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest.saturating_add(interest);
+        Ok(())
+    }
+
+    /// Adds `amount` of deposited principal to the position and restarts
+    /// the lock. What was earned on the old principal is settled first,
+    /// fixed positions keep the interest of the elapsed lock and are
+    /// promised a full lock on the new principal.
+    pub fn top_up(
+        &mut self,
+        tier_info: &mut TierInfo,
+        mine_vault: &mut MineVault,
+        amount: u64,
+        boost: u64,
+        now: u64
+    ) -> Result<()> {
+        let settled_interest = self.settle(tier_info, now)?;
+        let previous_interest = self.accrued_interest;
+        let previous_apy = self.effective_apy()?;
+        let new_total_locked = self.total_locked.saturating_add(amount);
+        // The boost follows the gating balance held at top up time
+        self.boost = boost;
+        if tier_info.is_fixed() {
+            let current_interest = match calculate_interest(
+                to_u128(self.total_locked)?,
+                to_u128(previous_apy)?,
+                to_u128(now.saturating_sub(self.lock_ts))?
+            ) {
+                Ok(val) => val,
+                Err(_) => return err!(MinerError::MathsError)
+            };
+            let new_interest = match calculate_interest(
+                to_u128(new_total_locked)?,
+                to_u128(self.effective_apy()?)?,
+                to_u128(self.lock_duration)?
+            ) {
+                Ok(val) => val,
+                Err(_) => return err!(MinerError::MathsError)
+            };
+            self.accrued_interest = new_interest.saturating_add(current_interest);
+        }
+        self.lock_ts = now;
+        self.total_locked = new_total_locked;
+        self.reward_debt = tier_info.reward_debt(new_total_locked)?;
+
+        tier_info.total_locked = tier_info.total_locked.saturating_add(amount);
+        mine_vault.total_principal = mine_vault.total_principal.saturating_add(amount);
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest
+            .saturating_add(settled_interest)
+            .saturating_sub(previous_interest)
+            .saturating_add(self.accrued_interest);
+        Ok(())
+    }
+
+    /// Takes what the position earned out of it. Fixed positions claimed
+    /// after their lock restart it on the full interest, claimed early they
+    /// pay the early withdrawal fee and restart with nothing. Returns the
+    /// claimed interest and how it is paid out.
+    pub fn claim(
+        &mut self,
+        tier_info: &mut TierInfo,
+        mine_info: &MineInfo,
+        mine_vault: &mut MineVault,
+        boost: u64,
+        now: u64
+    ) -> Result<(u64, Payout)> {
+        let settled_interest = self.settle(tier_info, now)?;
+        // Interest from here on follows the current gating balance
+        self.boost = boost;
+        let interest = self.accrued_interest;
+        let is_early = tier_info.is_fixed() && self.is_locked(now);
+        self.accrued_interest = if !tier_info.is_fixed() || is_early {
+            // Only what was earned so far is paid out
+            0
+        } else {
+            match calculate_interest(
+                to_u128(self.total_locked)?,
+                to_u128(self.effective_apy()?)?,
+                to_u128(self.lock_duration)?
+            ) {
+                Ok(val) => val,
+                Err(_) => return err!(MinerError::MathsError)
+            }
+        };
+        if tier_info.is_fixed() {
+            self.lock_ts = now;
+        }
+
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest
+            .saturating_add(settled_interest)
+            .saturating_sub(interest)
+            .saturating_add(self.accrued_interest);
+        Ok((interest, mine_info.payout(interest, is_early)?))
+    }
+
+    /// Restakes what the position earned as principal, fixed positions only
+    /// once their lock has ended. Returns the compounded interest.
+    pub fn compound(
+        &mut self,
+        tier_info: &mut TierInfo,
+        mine_vault: &mut MineVault,
+        boost: u64,
+        now: u64
+    ) -> Result<u64> {
+        let settled_interest = self.settle(tier_info, now)?;
+        // Interest from here on follows the current gating balance
+        self.boost = boost;
+        let current_interest = self.accrued_interest;
+        let new_interest = if !tier_info.is_fixed() {
+            // Continuous and emission tiers can compound what was earned at any time
+            0_u64
+        } else {
+            if self.is_locked(now) {
+                return err!(MinerError::OperationNotAllowed)
+            }
+            let new_interest = match calculate_interest(
+                to_u128(self.total_locked.saturating_add(current_interest))?,
+                to_u128(self.effective_apy()?)?,
+                to_u128(self.lock_duration)?
+            ) {
+                Ok(val) => val,
+                Err(_) => return err!(MinerError::MathsError)
+            };
+            self.lock_ts = now;
+            new_interest
+        };
+        self.total_locked = self.total_locked.saturating_add(current_interest);
+        self.accrued_interest = new_interest;
+        self.reward_debt = tier_info.reward_debt(self.total_locked)?;
+
+        // The compounded interest becomes principal
+        tier_info.total_locked = tier_info.total_locked.saturating_add(current_interest);
+        mine_vault.total_principal = mine_vault.total_principal.saturating_add(current_interest);
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest
+            .saturating_add(settled_interest)
+            .saturating_sub(current_interest)
+            .saturating_add(new_interest);
+        Ok(current_interest)
+    }
+
+    /// Takes `amount` of principal out of the position along with the share
+    /// of fixed interest the remaining principal is no longer promised,
+    /// other tiers keep everything earned in the position. Returns the
+    /// interest taken out.
+    pub fn withdraw_part(
+        &mut self,
+        tier_info: &mut TierInfo,
+        mine_vault: &mut MineVault,
+        amount: u64,
+        now: u64
+    ) -> Result<u64> {
+        let settled_interest = self.settle(tier_info, now)?;
+        let remaining = self.total_locked.saturating_sub(amount);
+        let remaining_interest = if !tier_info.is_fixed() {
+            self.accrued_interest
+        } else {
+            match calculate_interest(
+                to_u128(remaining)?,
+                to_u128(self.effective_apy()?)?,
+                to_u128(self.lock_duration)?
+            ) {
+                Ok(val) => val.min(self.accrued_interest),
+                Err(_) => return err!(MinerError::MathsError)
+            }
+        };
+        let withdrawn_interest = self.accrued_interest.saturating_sub(remaining_interest);
+        self.total_locked = remaining;
+        self.accrued_interest = remaining_interest;
+        self.reward_debt = tier_info.reward_debt(remaining)?;
+
+        tier_info.total_locked = tier_info.total_locked.saturating_sub(amount);
+        mine_vault.total_principal = mine_vault.total_principal.saturating_sub(amount);
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest
+            .saturating_add(settled_interest)
+            .saturating_sub(withdrawn_interest);
+        Ok(withdrawn_interest)
+    }
+
+    /// Moves the position from `from_tier` to `to_tier` and restarts its
+    /// lock under the terms of the destination. Fixed positions moved before
+    /// their lock ends only take out the interest earned so far and pay the
+    /// early withdrawal fee on it, unless the destination lock is not
+    /// shorter. Returns the settled interest and how it is paid out, the
+    /// caller points the position at the destination tier.
+    pub fn migrate(
+        &mut self,
+        from_tier: &mut TierInfo,
+        to_tier: &mut TierInfo,
+        mine_info: &MineInfo,
+        mine_vault: &mut MineVault,
+        boost: u64,
+        now: u64
+    ) -> Result<(u64, Payout)> {
+        let settled_interest = self.settle(from_tier, now)?;
+        let previous_interest = self.accrued_interest;
+        let is_locked = from_tier.is_fixed() && self.is_locked(now);
+
+        // Settle the interest earned so far in the elapsed lock, other
+        // tiers already hold exactly what was earned
+        let earned_interest = if !is_locked {
+            self.accrued_interest
+        } else {
+            self.earned_interest(now)?
+        };
+        // Upgrading to a lock that is at least as long skips the penalty
+        let is_early = is_locked && to_tier.lock_duration < self.lock_duration;
+        let payout = mine_info.payout(earned_interest, is_early)?;
+
+        // Restart the lock in the destination tier
+        to_tier.update_emission(now)?;
+        self.apy = to_tier.apy;
+        self.boost = boost;
+        let new_interest = if !to_tier.is_fixed() {
+            0
+        } else {
+            match calculate_interest(
+                to_u128(self.total_locked)?,
+                to_u128(self.effective_apy()?)?,
+                to_u128(to_tier.lock_duration)?
+            ) {
+                Ok(val) => val,
+                Err(_) => return err!(MinerError::MathsError)
+            }
+        };
+        self.accrued_interest = new_interest;
+        self.lock_ts = now;
+        self.last_accrual_ts = now;
+        self.reward_debt = to_tier.reward_debt(self.total_locked)?;
+        self.lock_duration = to_tier.lock_duration;
+
+        from_tier.total_locked = from_tier.total_locked.saturating_sub(self.total_locked);
+        to_tier.total_locked = to_tier.total_locked.saturating_add(self.total_locked);
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest
+            .saturating_add(settled_interest)
+            .saturating_sub(previous_interest)
+            .saturating_add(new_interest);
+        Ok((earned_interest, payout))
+    }
+
+    /// Settles the position and takes all of its principal and interest
+    /// out of the tier and the vault liabilities, for positions that are
+    /// being closed
+    pub fn close(
+        &mut self,
+        tier_info: &mut TierInfo,
+        mine_vault: &mut MineVault,
+        now: u64
+    ) -> Result<()> {
+        let settled_interest = self.settle(tier_info, now)?;
+        tier_info.total_locked = tier_info.total_locked.saturating_sub(self.total_locked);
+        mine_vault.total_principal = mine_vault.total_principal.saturating_sub(self.total_locked);
+        mine_vault.total_promised_interest = mine_vault.total_promised_interest
+            .saturating_add(settled_interest)
+            .saturating_sub(self.accrued_interest);
+        Ok(())
+    }
+}
+
+/// Tracks how many positions a wallet has opened. The next position is
+/// created at `[b"user", mine, owner, position_count]`.
+#[account]
+pub struct UserCounter {
+    pub owner: Pubkey,
+    pub position_count: u64,
+    pub bump: u8
+}
+
+impl Space for UserCounter {
+    const INIT_SPACE: usize = 32 + 8 + 1;
 }
\ No newline at end of file
@@ -0,0 +1,304 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use anchor_spl::token_interface::{self, Burn, Mint, MintTo, TokenAccount, TokenInterface, TransferChecked};
+use crate::states::mine::{MineInfo, MineVault, Payee, Payout};
+use crate::math::{calculate_fee, to_u128};
+use crate::errors::MinerError;
+
+/// Moves tokens from an account owned by the signer
+pub fn transfer_from_user<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    authority: &Signer<'info>,
+    amount: u64
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: from.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: authority.to_account_info()
+            }
+        ),
+        amount,
+        mint.decimals
+    )
+}
+
+/// Moves lamports out of the mine vault, which the program owns
+pub fn transfer_lamports<'info>(
+    mine_vault: &Account<'info, MineVault>,
+    to: &AccountInfo<'info>,
+    amount: u64
+) -> Result<()> {
+    mine_vault.sub_lamports(amount)?;
+    to.add_lamports(amount)?;
+    Ok(())
+}
+
+/// Moves tokens out of a token vault, signed by the mine vault PDA
+pub fn transfer_from_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    mine_vault: &Account<'info, MineVault>,
+    mine: &Pubkey,
+    amount: u64
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let signer_seeds: &[&[&[u8]]] = &[&[b"mine-vault", mine.as_ref(), &[mine_vault.bump]]];
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: mine_vault.to_account_info()
+            },
+            signer_seeds
+        ),
+        amount,
+        mint.decimals
+    )
+}
+
+/// Mints tokens of a mine owned mint, signed by the mine vault PDA
+pub fn mint_from_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    mine_vault: &Account<'info, MineVault>,
+    mine: &Pubkey,
+    amount: u64
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[b"mine-vault", mine.as_ref(), &[mine_vault.bump]]];
+    token_interface::mint_to(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            MintTo {
+                mint: mint.to_account_info(),
+                to: to.to_account_info(),
+                authority: mine_vault.to_account_info()
+            },
+            signer_seeds
+        ),
+        amount
+    )
+}
+
+/// Burns tokens from an account owned by the signer
+pub fn burn_from_user<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    authority: &Signer<'info>,
+    amount: u64
+) -> Result<()> {
+    token_interface::burn(
+        CpiContext::new(
+            token_program.to_account_info(),
+            Burn {
+                mint: mint.to_account_info(),
+                from: from.to_account_info(),
+                authority: authority.to_account_info()
+            }
+        ),
+        amount
+    )
+}
+
+/// Moves lamports from the signer into the mine vault
+pub fn transfer_lamports_from_user<'info>(
+    system_program: &Program<'info, System>,
+    from: &Signer<'info>,
+    to: &AccountInfo<'info>,
+    amount: u64
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            Transfer {
+                from: from.to_account_info(),
+                to: to.clone()
+            }
+        ),
+        amount
+    )
+}
+
+/// Token accounts a token mode mine moves one of its mints through. Native
+/// mines leave them out. `holder` is the token account of the position
+/// holder, or of the signer on deposits.
+pub struct TokenRoute<'a, 'info> {
+    pub token_program: &'a Option<Interface<'info, TokenInterface>>,
+    pub mint: &'a Option<Box<InterfaceAccount<'info, Mint>>>,
+    pub vault: &'a Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub holder: &'a Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub fee_collector: &'a Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub penalty_collector: &'a Option<Box<InterfaceAccount<'info, TokenAccount>>>
+}
+
+impl<'a, 'info> TokenRoute<'a, 'info> {
+    /// Token program, mint and vault of the route
+    fn vault(&self) -> Result<(&'a Interface<'info, TokenInterface>, &'a InterfaceAccount<'info, Mint>, &'a InterfaceAccount<'info, TokenAccount>)> {
+        match (self.token_program, self.mint, self.vault) {
+            (Some(token_program), Some(mint), Some(vault)) => Ok((token_program, mint, vault)),
+            _ => err!(MinerError::MissingTokenAccounts)
+        }
+    }
+
+    /// Token account a part of a payout goes to
+    fn account_of(&self, payee: Payee) -> Result<&'a InterfaceAccount<'info, TokenAccount>> {
+        let account = match payee {
+            Payee::Owner => self.holder,
+            Payee::FeeCollector => self.fee_collector,
+            Payee::PenaltyCollector => self.penalty_collector
+        };
+        match account {
+            Some(account) => Ok(account),
+            None => err!(MinerError::MissingTokenAccounts)
+        }
+    }
+}
+
+/// Deposit of the signer into the mine, lamports into the mine vault on
+/// native mines or the stake mint into its token vault on token mode mines
+pub struct Deposit<'a, 'info> {
+    pub signer: &'a Signer<'info>,
+    pub mine_info: &'a Account<'info, MineInfo>,
+    pub mine_vault: &'a Account<'info, MineVault>,
+    pub system_program: &'a Program<'info, System>,
+    pub tokens: TokenRoute<'a, 'info>
+}
+
+impl<'a, 'info> Deposit<'a, 'info> {
+    /// Sends the dev fee of `amount` to the fee collector, whose wallet is
+    /// `fee_collector` on native mines, and the rest to the vault. Returns
+    /// the dev fee and what arrived in the vault, mints with a transfer fee
+    /// deliver less than was sent.
+    pub fn take(&self, amount: u64, fee_collector: &AccountInfo<'info>) -> Result<(u64, u64)> {
+        let dev_fee = calculate_fee(to_u128(amount)?, to_u128(self.mine_info.dev_fee)?)?;
+        if self.mine_info.is_native() {
+            transfer_lamports_from_user(self.system_program, self.signer, fee_collector, dev_fee)?;
+        } else {
+            let (token_program, mint, _) = self.tokens.vault()?;
+            transfer_from_user(
+                token_program,
+                self.tokens.account_of(Payee::Owner)?,
+                self.tokens.account_of(Payee::FeeCollector)?,
+                mint,
+                self.signer,
+                dev_fee
+            )?;
+        }
+        Ok((dev_fee, self.fund(amount.saturating_sub(dev_fee))?))
+    }
+
+    /// Sends `amount` to the vault without fees, returns what arrived
+    pub fn fund(&self, amount: u64) -> Result<u64> {
+        if self.mine_info.is_native() {
+            transfer_lamports_from_user(self.system_program, self.signer, &self.mine_vault.to_account_info(), amount)?;
+            return Ok(amount);
+        }
+        let (token_program, mint, vault) = self.tokens.vault()?;
+        let mut vault = vault.clone();
+        let before_deposit = vault.amount;
+        transfer_from_user(
+            token_program,
+            self.tokens.account_of(Payee::Owner)?,
+            &vault,
+            mint,
+            self.signer,
+            amount
+        )?;
+        vault.reload()?;
+        Ok(vault.amount.saturating_sub(before_deposit))
+    }
+}
+
+/// Pays out of the mine, lamports from the mine vault to the wallets of the
+/// payees on native mines or tokens from the vault of a `TokenRoute` on
+/// token mode mines
+pub struct Payer<'a, 'info> {
+    pub mine_info: &'a Account<'info, MineInfo>,
+    pub mine_vault: &'a Account<'info, MineVault>,
+    pub owner: AccountInfo<'info>,
+    pub fee_collector: AccountInfo<'info>,
+    pub penalty_collector: AccountInfo<'info>
+}
+
+impl<'a, 'info> Payer<'a, 'info> {
+    /// Sends every part of `payout`, through `tokens` on token mode mines
+    pub fn send(&self, payout: &Payout, tokens: &TokenRoute<'_, 'info>) -> Result<()> {
+        if self.mine_info.is_native() {
+            return payout.send(|payee, amount| {
+                let to = match payee {
+                    Payee::Owner => &self.owner,
+                    Payee::FeeCollector => &self.fee_collector,
+                    Payee::PenaltyCollector => &self.penalty_collector
+                };
+                transfer_lamports(self.mine_vault, to, amount)
+            });
+        }
+        let (token_program, mint, vault) = tokens.vault()?;
+        payout.send(|payee, amount| {
+            if amount == 0 {
+                return Ok(());
+            }
+            transfer_from_vault(
+                token_program,
+                vault,
+                tokens.account_of(payee)?,
+                mint,
+                self.mine_vault,
+                &self.mine_info.key(),
+                amount
+            )
+        })
+    }
+
+    /// Splits `amount` into the holder's part and fees, see
+    /// `MineInfo::payout`, and sends it
+    pub fn pay(&self, amount: u64, is_early: bool, tokens: &TokenRoute<'_, 'info>) -> Result<Payout> {
+        let payout = self.mine_info.payout(amount, is_early)?;
+        self.send(&payout, tokens)?;
+        Ok(payout)
+    }
+
+    /// Pays out principal and interest of a position. Native mines pay both
+    /// as one amount of lamports, token mode mines pay them separately from
+    /// the stake and reward vaults as the mints may differ.
+    pub fn pay_position(
+        &self,
+        principal: u64,
+        interest: u64,
+        is_early: bool,
+        stake: &TokenRoute<'_, 'info>,
+        reward: &TokenRoute<'_, 'info>
+    ) -> Result<Payout> {
+        if self.mine_info.is_native() {
+            return self.pay(principal.saturating_add(interest), is_early, stake);
+        }
+        let principal_payout = self.pay(principal, is_early, stake)?;
+        let interest_payout = self.pay(interest, is_early, reward)?;
+        Ok(Payout {
+            amount_out: principal_payout.amount_out.saturating_add(interest_payout.amount_out),
+            dev_fee: principal_payout.dev_fee.saturating_add(interest_payout.dev_fee),
+            penalty: principal_payout.penalty.saturating_add(interest_payout.penalty)
+        })
+    }
+}
@@ -9,9 +9,14 @@ import {
 import { TOKEN_DECIMALS, MAX_FEE, FEE_BASIS_POINTS } from "./constants";
 
 const init = async (
+    mineId: number,
     devFee: number,
     earlyClaimFee: number,
-    referralReward: number
+    referralReward: number,
+    timelockDelay: number,
+    maxDevFee: number,
+    maxEarlyClaimFee: number,
+    maxReferralReward: number
 ) => {
     const provider = anchor.AnchorProvider.env();
     anchor.setProvider(provider);
@@ -44,27 +49,37 @@ const init = async (
     // Program Initialization Transaction
     const program = anchor.workspace.TierSolMiner as Program<TierSolMiner>;
     const [mineAccount, ] = anchor.web3.PublicKey.findProgramAddressSync(
-        [Buffer.from("mine")], 
+        [Buffer.from("mine"), admin.publicKey.toBuffer(), new anchor.BN(mineId).toArrayLike(Buffer, "le", 8)], 
         program.programId
     );
     const [mineVault, ] = anchor.web3.PublicKey.findProgramAddressSync(
-        [Buffer.from("mine-vault")], 
+        [Buffer.from("mine-vault"), mineAccount.toBuffer()], 
+        program.programId
+    );
+    const [roles, ] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("roles"), mineAccount.toBuffer()], 
         program.programId
     );
     const initAccounts = {
         initializer: admin.publicKey,
         mineInfo: mineAccount,
         mineVault,
+        roles,
         systemProgram: anchor.web3.SystemProgram.programId
     };
     console.log("Sending Program Initialize Instruction...")
     const sig = await program.methods.initialize(
+        new anchor.BN(mineId),
         admin.publicKey,
         admin.publicKey,
         mintKeyPair.publicKey,
         new anchor.BN(devFee),
         new anchor.BN(earlyClaimFee),
-        new anchor.BN(referralReward)
+        new anchor.BN(referralReward),
+        new anchor.BN(timelockDelay),
+        new anchor.BN(maxDevFee),
+        new anchor.BN(maxEarlyClaimFee),
+        new anchor.BN(maxReferralReward)
     )
         .accounts({ ...initAccounts })
         .rpc()
@@ -72,7 +87,12 @@ const init = async (
 }
 
 init(
+    0, // Mine Id
     500, // DevFee 5%
     4000, // Early Claim Fee 40%
-    1000 // Referral Reward 10%
+    1000, // Referral Reward 10%
+    60, // Timelock Delay 1 minute
+    1000, // Max DevFee 10%
+    5000, // Max Early Claim Fee 50%
+    2000 // Max Referral Reward 20%
 )
\ No newline at end of file
@@ -2,8 +2,9 @@ import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { TierSolMiner } from "../target/types/tier_sol_miner";
 import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL, Transaction, sendAndConfirmTransaction } from "@solana/web3.js";
-import { TOKEN_2022_PROGRAM_ID, ExtensionType, getMintLen, createInitializeMintInstruction, createInitializeTransferFeeConfigInstruction, getOrCreateAssociatedTokenAccount, mintTo } from "@solana/spl-token";
+import { createMint as createSplMint, TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, ExtensionType, getMintLen, createInitializeMintInstruction, createInitializeTransferFeeConfigInstruction, getOrCreateAssociatedTokenAccount, getAssociatedTokenAddressSync, getTokenMetadata, transferChecked, ASSOCIATED_TOKEN_PROGRAM_ID, mintTo, burn } from "@solana/spl-token";
 import { expect } from "chai";
+import { createHash } from "crypto";
 
 describe("tier-sol-miner", () => {
   // Configure the client to use the local cluster.
@@ -78,23 +79,68 @@ describe("tier-sol-miner", () => {
     return ata.address
   }
   const sleep = (ms: number) => new Promise((r) => setTimeout(r, ms));
+  const sha256 = (...parts: Buffer[]) => createHash("sha256").update(Buffer.concat(parts)).digest();
+  const allowlistLeaf = (index: number, beneficiary: PublicKey) => {
+    const indexBuf = Buffer.alloc(4);
+    indexBuf.writeUInt32LE(index);
+    return sha256(indexBuf, beneficiary.toBuffer());
+  }
+  const hashPair = (a: Buffer, b: Buffer) => Buffer.compare(a, b) <= 0 ? sha256(a, b) : sha256(b, a);
 
   const program = anchor.workspace.TierSolMiner as Program<TierSolMiner>;
+  const positionSeed = (index: number) => new anchor.BN(index).toArrayLike(Buffer, "le", 8);
+  const referralPda = (userInfo: PublicKey) => PublicKey.findProgramAddressSync(
+    [Buffer.from("referral"), userInfo.toBuffer()],
+    program.programId
+  )[0];
+  // Events are read back from the logs of the transaction that emitted them
+  const eventParser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+  const eventOf = async (signature: string, name: string): Promise<any> => {
+    let tx = null;
+    while (tx === null) {
+      tx = await connection.getTransaction(signature, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+      if (tx === null) await sleep(200);
+    }
+    const event = [...eventParser.parseLogs(tx.meta.logMessages)].find((event) => event.name === name);
+    expect(event, `${name} was not emitted`).to.not.equal(undefined);
+    return event.data;
+  }
   const TOKEN_DECIMALS = 6;
   const stakeAmount = 10; // 10 SOLs
   const devFee = 100 // 1%;
   const earlyClaimFee = 6000 // 60%;
   const referralReward = 1000 // 10%;
+  const timelockDelay = 2; // 2 seconds
+  const maxDevFee = 1000 // 10%;
+  const maxEarlyClaimFee = 8000 // 80%;
+  const maxReferralReward = 2000 // 20%;
   const mintKeyPair = new Keypair();
   const initializer = new Keypair();
   const user1 = new Keypair();
   const user2 = new Keypair();
   const user3 = new Keypair();
   const user4 = new Keypair();
-  const [user1InfoPk, _1] = PublicKey.findProgramAddressSync([Buffer.from("user"), user1.publicKey.toBuffer()], program.programId);
-  const [user2InfoPk, _2] = PublicKey.findProgramAddressSync([Buffer.from("user"), user2.publicKey.toBuffer()], program.programId);
-  const [user3InfoPk, _3] = PublicKey.findProgramAddressSync([Buffer.from("user"), user3.publicKey.toBuffer()], program.programId);
-  const [user4InfoPk, _4] = PublicKey.findProgramAddressSync([Buffer.from("user"), user4.publicKey.toBuffer()], program.programId);
+  const mineId = new anchor.BN(0);
+  const [mineAccount, bump] = PublicKey.findProgramAddressSync(
+    [Buffer.from("mine"), initializer.publicKey.toBuffer(), mineId.toArrayLike(Buffer, "le", 8)], 
+    program.programId
+  );
+  const [mineVault, ] = PublicKey.findProgramAddressSync(
+    [Buffer.from("mine-vault"), mineAccount.toBuffer()], 
+    program.programId
+  );
+  const [roles, ] = PublicKey.findProgramAddressSync(
+    [Buffer.from("roles"), mineAccount.toBuffer()], 
+    program.programId
+  );
+  const [user1CounterPk, ] = PublicKey.findProgramAddressSync([Buffer.from("user-counter"), mineAccount.toBuffer(), user1.publicKey.toBuffer()], program.programId);
+  const [user1InfoPk, _1] = PublicKey.findProgramAddressSync([Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(0)], program.programId);
+  const [user2CounterPk, ] = PublicKey.findProgramAddressSync([Buffer.from("user-counter"), mineAccount.toBuffer(), user2.publicKey.toBuffer()], program.programId);
+  const [user2InfoPk, _2] = PublicKey.findProgramAddressSync([Buffer.from("user"), mineAccount.toBuffer(), user2.publicKey.toBuffer(), positionSeed(0)], program.programId);
+  const [user3CounterPk, ] = PublicKey.findProgramAddressSync([Buffer.from("user-counter"), mineAccount.toBuffer(), user3.publicKey.toBuffer()], program.programId);
+  const [user3InfoPk, _3] = PublicKey.findProgramAddressSync([Buffer.from("user"), mineAccount.toBuffer(), user3.publicKey.toBuffer(), positionSeed(0)], program.programId);
+  const [user4CounterPk, ] = PublicKey.findProgramAddressSync([Buffer.from("user-counter"), mineAccount.toBuffer(), user4.publicKey.toBuffer()], program.programId);
+  const [user4InfoPk, _4] = PublicKey.findProgramAddressSync([Buffer.from("user"), mineAccount.toBuffer(), user4.publicKey.toBuffer(), positionSeed(0)], program.programId);
   const [user1refInfoPK, _5] = PublicKey.findProgramAddressSync([Buffer.from("referral"), user1InfoPk.toBuffer()], program.programId);
   // const [user2refInfoPK, _6] = PublicKey.findProgramAddressSync([Buffer.from("referral"), user2InfoPk.toBuffer()], program.programId);
   // const [user3refInfoPK, _7] = PublicKey.findProgramAddressSync([Buffer.from("referral"), user3InfoPk.toBuffer()], program.programId);
@@ -105,24 +151,44 @@ describe("tier-sol-miner", () => {
   let user4Ata: PublicKey;
   const feeCollector = new Keypair();
   const penaltyCollector = new Keypair();
-  const [mineAccount, bump] = PublicKey.findProgramAddressSync(
-    [Buffer.from("mine")], 
-    program.programId
-  );
-  const [mineVault, ] = PublicKey.findProgramAddressSync(
-    [Buffer.from("mine-vault")], 
-    program.programId
-  );
+  const mineConfig = (overrides = {}) => ({
+    feeCollector: feeCollector.publicKey,
+    penaltyFeeCollector: penaltyCollector.publicKey,
+    tokenMint: mintKeyPair.publicKey,
+    devFee: new anchor.BN(devFee),
+    earlyWithdrawalFee: new anchor.BN(earlyClaimFee),
+    referralReward: new anchor.BN(referralReward),
+    timelockDelay: new anchor.BN(timelockDelay),
+    maxDevFee: new anchor.BN(maxDevFee),
+    maxEarlyWithdrawalFee: new anchor.BN(maxEarlyClaimFee),
+    maxReferralReward: new anchor.BN(maxReferralReward),
+    ...overrides
+  });
+  // Token accounts of the shared instructions, native mines leave them out
+  const noTokenAccounts = {
+    stakeMint: null,
+    rewardMint: null,
+    userStakeAccount: null,
+    userRewardAccount: null,
+    adminRewardAccount: null,
+    stakeVault: null,
+    rewardVault: null,
+    feeCollectorStakeAccount: null,
+    feeCollectorRewardAccount: null,
+    penaltyCollectorStakeAccount: null,
+    penaltyCollectorRewardAccount: null,
+    tokenProgram: null
+  };
   const [ tier1, bump1] = PublicKey.findProgramAddressSync(
-    [Buffer.from("tier"), Uint8Array.from([0])], 
+    [Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([0])], 
     program.programId
   );
   const [ tier2, bump2] = PublicKey.findProgramAddressSync(
-    [Buffer.from("tier"), Uint8Array.from([1])], 
+    [Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([1])], 
     program.programId
   );
   const [ tier3, bump3] = PublicKey.findProgramAddressSync(
-    [Buffer.from("tier"), Uint8Array.from([2])], 
+    [Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([2])], 
     program.programId
   );
   const tierInfo = {
@@ -164,17 +230,11 @@ describe("tier-sol-miner", () => {
     const accounts = {
       initializer: initializer.publicKey,
       mineInfo: mineAccount,
+      roles,
       mineVault,
       systemProgram: SystemProgram.programId
     };
-    await program.methods.initialize(
-      feeCollector.publicKey,
-      penaltyCollector.publicKey,
-      mintKeyPair.publicKey,
-      new anchor.BN(devFee),
-      new anchor.BN(earlyClaimFee),
-      new anchor.BN(referralReward)
-    )
+    await program.methods.initialize(mineId, mineConfig())
       .accounts({ ...accounts })
       .signers([initializer])
       .rpc()
@@ -184,6 +244,7 @@ describe("tier-sol-miner", () => {
     const addTierAccounts = {
       admin: initializer.publicKey,
       mineInfo: mineAccount,
+      roles,
       systemProgram: SystemProgram.programId
     }
     for (const tier of tierArray) {
@@ -191,6 +252,7 @@ describe("tier-sol-miner", () => {
         new anchor.BN(tierInfo[tier].apy),
         new anchor.BN(tierInfo[tier].minimumTokenAmount),
         new anchor.BN(tierInfo[tier].lockDuration),
+        { fixed: {} }
       )
         .accounts({ ...addTierAccounts, tierInfo: tierInfo[tier].tierAddress})
         .signers([initializer])
@@ -222,6 +284,8 @@ describe("tier-sol-miner", () => {
   it("Mine Information Verification", async () => {
     const mineInfo = await program.account.mineInfo.fetch(mineAccount);
     expect(mineInfo.admin.toString()).to.equals(initializer.publicKey.toString());
+    expect(mineInfo.creator.toString()).to.equals(initializer.publicKey.toString());
+    expect(mineInfo.id.toNumber()).to.equals(mineId.toNumber());
     expect(mineInfo.tokenMint.toString()).to.equals(mintKeyPair.publicKey.toString());
     expect(mineInfo.feeCollector.toString()).to.equals(feeCollector.publicKey.toString());
     expect(mineInfo.penaltyFeeCollector.toString()).to.equals(penaltyCollector.publicKey.toString());
@@ -236,7 +300,7 @@ describe("tier-sol-miner", () => {
   it("Tier Info Verification", async () => {
     for (const tier of tierArray) {
       const expectedInfo = tierInfo[tier];
-      const [addr, _] = PublicKey.findProgramAddressSync([Buffer.from("tier"), Uint8Array.from([expectedInfo.nonce])], program.programId);
+      const [addr, _] = PublicKey.findProgramAddressSync([Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([expectedInfo.nonce])], program.programId);
       const info = await program.account.tierInfo.fetch(expectedInfo.tierAddress);
       expect(info.apy.toNumber()).to.equals(expectedInfo.apy);
       expect(info.minimumTokenAmount.toNumber()).to.equals(expectedInfo.minimumTokenAmount);
@@ -250,25 +314,126 @@ describe("tier-sol-miner", () => {
     expect(mineInfo.currentTierNonce).to.equals(3);
   });
 
+  it("Multiple Mines Test", async () => {
+    const secondMineId = new anchor.BN(1);
+    const [secondMine, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mine"), initializer.publicKey.toBuffer(), secondMineId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [secondVault, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mine-vault"), secondMine.toBuffer()],
+      program.programId
+    );
+    const [secondRoles, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("roles"), secondMine.toBuffer()],
+      program.programId
+    );
+    const [secondTier, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), secondMine.toBuffer(), Uint8Array.from([0])],
+      program.programId
+    );
+    await program.methods.initialize(
+      secondMineId,
+      mineConfig({ feeCollector: penaltyCollector.publicKey, penaltyFeeCollector: feeCollector.publicKey })
+    )
+      .accounts({
+        initializer: initializer.publicKey,
+        mineInfo: secondMine,
+        roles: secondRoles,
+        mineVault: secondVault,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    await program.methods.addTier(
+      new anchor.BN(tierInfo.tier1.apy),
+      new anchor.BN(tierInfo.tier1.minimumTokenAmount),
+      new anchor.BN(tierInfo.tier1.lockDuration),
+      { fixed: {} }
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: secondMine,
+        roles: secondRoles,
+        tierInfo: secondTier,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+
+    // Each mine keeps its own collectors, tiers and nonce
+    const secondInfo = await program.account.mineInfo.fetch(secondMine);
+    expect(secondInfo.feeCollector.toString()).to.equals(penaltyCollector.publicKey.toString());
+    expect(secondInfo.currentTierNonce).to.equals(1);
+    const secondTierInfo = await program.account.tierInfo.fetch(secondTier);
+    expect(secondTierInfo.mine.toString()).to.equals(secondMine.toString());
+    expect(secondTier.toString()).to.not.equals(tier1.toString());
+    const mineInfo = await program.account.mineInfo.fetch(mineAccount);
+    expect(mineInfo.currentTierNonce).to.equals(3);
+    const tierInfo1 = await program.account.tierInfo.fetch(tier1);
+    expect(tierInfo1.mine.toString()).to.equals(mineAccount.toString());
+  });
+
+  it("Mine Fee Cap Test", async () => {
+    const cappedMineId = new anchor.BN(3);
+    const [cappedMine, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mine"), initializer.publicKey.toBuffer(), cappedMineId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const accounts = {
+      initializer: initializer.publicKey,
+      mineInfo: cappedMine,
+      roles: PublicKey.findProgramAddressSync([Buffer.from("roles"), cappedMine.toBuffer()], program.programId)[0],
+      mineVault: PublicKey.findProgramAddressSync([Buffer.from("mine-vault"), cappedMine.toBuffer()], program.programId)[0],
+      systemProgram: SystemProgram.programId
+    };
+    const cases = [
+      { overrides: { devFee: new anchor.BN(maxDevFee + 1) }, code: "DevFeeTooHigh" },
+      { overrides: { earlyWithdrawalFee: new anchor.BN(maxEarlyClaimFee + 1) }, code: "EarlyWithdrawalFeeTooHigh" },
+      { overrides: { referralReward: new anchor.BN(maxReferralReward + 1) }, code: "ReferralRewardTooHigh" }
+    ];
+    for (const { overrides, code } of cases) {
+      try {
+        await program.methods.initialize(cappedMineId, mineConfig(overrides))
+          .accounts({ ...accounts })
+          .signers([initializer])
+          .rpc()
+          .then(confirm);
+        expect.fail(`initialized a mine with a fee above its cap, expected ${code}`);
+      } catch (error) {
+        expect(error.error.errorCode.code).to.equals(code);
+      }
+    }
+    expect(await connection.getAccountInfo(cappedMine)).to.equals(null);
+  });
+
   it("Init Staking Test [No Referrer]", async () => {
     const accounts = {
       signer: user1.publicKey,
+      userCounter: user1CounterPk,
       userInfo: user1InfoPk,
       tokenAccount: user1Ata,
+      gateMint: null,
       mineInfo: mineAccount,
       mineVault,
       tierInfo: tier1,
       feeCollector: feeCollector.publicKey,
+      gateEscrow: null,
+      tokenMint: null,
+      tokenProgram: null,
       systemProgram: SystemProgram.programId
     }
     const tierInfo = await program.account.tierInfo.fetch(tier1);
+    const beforeInitVaultInfo = await program.account.mineVault.fetch(mineVault);
     const beforeInitVaultBalance = await connection.getBalance(mineVault);
     const beforeInitFeeCollectorBalance = await connection.getBalance(feeCollector.publicKey);
-    await program.methods.initializeStaking(
+    const stakeSig = await program.methods.initializeStaking(
       tierInfo.nonce,
       new anchor.BN(stakeAmount*LAMPORTS_PER_SOL)
     )
-      .accounts({ ...accounts })
+      .accounts({ ...noTokenAccounts, ...accounts })
       .signers([user1])
       .rpc()
       .then(confirm)
@@ -281,6 +446,13 @@ describe("tier-sol-miner", () => {
     const expectedTotalLocked = (stakeAmount * LAMPORTS_PER_SOL) - expectedDevFee;
     const expectedAccruedInterest = calculateInterest(expectedTotalLocked, tierInfo.apy.toNumber(), tierInfo.lockDuration.toNumber());
 
+    const afterInitVaultInfo = await program.account.mineVault.fetch(mineVault);
+    expect(
+      afterInitVaultInfo.totalPrincipal.toNumber() - beforeInitVaultInfo.totalPrincipal.toNumber()
+    ).to.equals(expectedTotalLocked);
+    expect(
+      afterInitVaultInfo.totalPromisedInterest.toNumber() - beforeInitVaultInfo.totalPromisedInterest.toNumber()
+    ).to.equals(expectedAccruedInterest);
     expect(userInfo.owner.toString()).to.equals(user1.publicKey.toString());
     expect(userInfo.totalLocked.toString()).to.equals(expectedTotalLocked.toString());
     expect(expectedAccruedInterest).to.equals(userInfo.accruedInterest.toNumber());
@@ -290,29 +462,45 @@ describe("tier-sol-miner", () => {
     expect(afterInitVaultBalance - beforeInitVaultBalance).to.equals(expectedTotalLocked);
     expect(afterInitFeeCollectorBalance - beforeInitFeeCollectorBalance).to.equals(expectedDevFee);
     expect(afterInitTierInfo.totalLocked.toNumber() - tierInfo.totalLocked.toNumber()).to.equals(expectedTotalLocked);
+    const deposited = await eventOf(stakeSig, "StakeDeposited");
+    expect(deposited.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(deposited.userInfo.toString()).to.equals(user1InfoPk.toString());
+    expect(deposited.tier.toString()).to.equals(tier1.toString());
+    expect(deposited.amount.toNumber()).to.equals(expectedTotalLocked);
+    expect(deposited.devFee.toNumber()).to.equals(expectedDevFee);
+    expect(deposited.accruedInterest.toNumber()).to.equals(expectedAccruedInterest);
+    expect(deposited.isWhitelist).to.equals(false);
+    expect(deposited.boost.toNumber()).to.equals(userInfo.boost.toNumber());
   });
 
   it("Init Staking Test [Referrer]", async () => {
     const accounts = {
       signer: user2.publicKey,
+      userCounter: user2CounterPk,
       userInfo: user2InfoPk,
       tokenAccount: user2Ata,
+      gateMint: null,
       mineInfo: mineAccount,
       mineVault,
       tierInfo: tier2,
       referrerUserInfo: user1InfoPk,
       referrerInfo: user1refInfoPK,
+      secondReferrerInfo: null,
+      thirdReferrerInfo: null,
       feeCollector: feeCollector.publicKey,
+      gateEscrow: null,
+      tokenMint: null,
+      tokenProgram: null,
       systemProgram: SystemProgram.programId
     }
     const tierInfo = await program.account.tierInfo.fetch(tier2);
     const beforeInitVaultBalance = await connection.getBalance(mineVault);
     const beforeInitFeeCollectorBalance = await connection.getBalance(feeCollector.publicKey);
-    await program.methods.initializeStakingWithReferrer(
+    const stakeSig = await program.methods.initializeStakingWithReferrer(
       tierInfo.nonce,
       new anchor.BN(stakeAmount*LAMPORTS_PER_SOL)
     )
-      .accounts({ ...accounts })
+      .accounts({ ...noTokenAccounts, ...accounts })
       .signers([user2])
       .rpc()
       .then(confirm)
@@ -333,6 +521,8 @@ describe("tier-sol-miner", () => {
     expect(userInfo.lockTs.toNumber()).to.greaterThan(1000000);
     expect(userInfo.tier.toString()).to.equals(tier2.toString());
     expect(userInfo.isWhitelist).to.equals(false);
+    expect(userInfo.apy.toNumber()).to.equals(tierInfo.apy.toNumber());
+    expect(userInfo.lockDuration.toNumber()).to.equals(tierInfo.lockDuration.toNumber());
     expect(afterInitVaultBalance - beforeInitVaultBalance).to.equals(expectedTotalLocked);
     expect(afterInitFeeCollectorBalance - beforeInitFeeCollectorBalance).to.equals(expectedDevFee);
     expect(afterInitTierInfo.totalLocked.toNumber() - tierInfo.totalLocked.toNumber()).to.equals(expectedTotalLocked);
@@ -341,16 +531,581 @@ describe("tier-sol-miner", () => {
     expect(referralInfo.count.toNumber()).to.equals(1);
     expect(referralInfo.owner.toString()).to.equals(user1.publicKey.toString());
     expect(referralInfo.userInfo.toString()).to.equals(user1InfoPk.toString())
+    expect(userInfo.referrer.toString()).to.equals(user1InfoPk.toString());
+    const credited = await eventOf(stakeSig, "ReferralCredited");
+    expect(credited.referrer.toString()).to.equals(user1.publicKey.toString());
+    expect(credited.referrerInfo.toString()).to.equals(user1refInfoPK.toString());
+    expect(credited.userInfo.toString()).to.equals(user2InfoPk.toString());
+    expect(credited.level).to.equals(0);
+    expect(credited.bonus.toNumber()).to.equals(expectedReferralBonus);
+  });
+
+  it("Referral Top Up Test", async () => {
+    const accounts = {
+      signer: user2.publicKey,
+      userInfo: user2InfoPk,
+      positionNft: null,
+      tokenAccount: user2Ata,
+      gateMint: null,
+      mineInfo: mineAccount,
+      mineVault,
+      tierInfo: tier2,
+      secondReferrerInfo: null,
+      thirdReferrerInfo: null,
+      feeCollector: feeCollector.publicKey,
+      systemProgram: SystemProgram.programId
+    };
+    // Only the referral account of the position's own referrer is accepted
+    try {
+      await program.methods.increaseStake(new anchor.BN(stakeAmount*LAMPORTS_PER_SOL))
+        .accounts({ ...noTokenAccounts, ...accounts, referrerInfo: user2InfoPk })
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("credited an account that is not the referrer's");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    // The referrer cannot be skipped on a top up
+    try {
+      await program.methods.increaseStake(new anchor.BN(stakeAmount*LAMPORTS_PER_SOL))
+        .accounts({ ...noTokenAccounts, ...accounts, referrerInfo: null })
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("topped up a referred position without crediting the referrer");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    const beforeReferralInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+    await program.methods.increaseStake(new anchor.BN(stakeAmount*LAMPORTS_PER_SOL))
+      .accounts({ ...noTokenAccounts, ...accounts, referrerInfo: user1refInfoPK })
+      .signers([user2])
+      .rpc()
+      .then(confirm);
+    const afterReferralInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+    const expectedDevFee = (devFee * stakeAmount * LAMPORTS_PER_SOL)/10000;
+    const expectedBonus = ((stakeAmount * LAMPORTS_PER_SOL) - expectedDevFee) * referralReward/10000;
+    expect(afterReferralInfo.earnings.toNumber() - beforeReferralInfo.earnings.toNumber()).to.equals(expectedBonus);
+    expect(afterReferralInfo.count.toNumber()).to.equals(beforeReferralInfo.count.toNumber());
+
+    // Compounding tops up the position with its interest and credits the referrer too
+    const compoundAccounts = {
+      signer: user2.publicKey,
+      userInfo: user2InfoPk,
+      positionNft: null,
+      tokenAccount: user2Ata,
+      gateMint: null,
+      mineInfo: mineAccount,
+      mineVault,
+      tierInfo: tier2,
+      secondReferrerInfo: null,
+      thirdReferrerInfo: null
+    };
+    await sleep(6*1000);
+    try {
+      await program.methods.compound()
+        .accounts({ ...noTokenAccounts, ...compoundAccounts, referrerInfo: null })
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("compounded a referred position without crediting the referrer");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    const beforeCompUserInfo = await program.account.userInfo.fetch(user2InfoPk);
+    await program.methods.compound()
+      .accounts({ ...noTokenAccounts, ...compoundAccounts, referrerInfo: user1refInfoPK })
+      .signers([user2])
+      .rpc()
+      .then(confirm);
+    const afterCompReferralInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+    const expectedCompBonus = Math.floor(beforeCompUserInfo.accruedInterest.toNumber() * referralReward/10000);
+    expect(
+      afterCompReferralInfo.earnings.toNumber() - afterReferralInfo.earnings.toNumber()
+    ).to.equals(expectedCompBonus);
+  });
+
+  it("Init Staking Test [Second Position]", async () => {
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(1)],
+      program.programId
+    );
+    await program.methods.initializeStaking(
+      tierInfo["tier1"].nonce,
+      new anchor.BN(stakeAmount*LAMPORTS_PER_SOL)
+    )
+      .accounts({
+        ...noTokenAccounts,
+        signer: user1.publicKey,
+        userCounter: user1CounterPk,
+        userInfo: positionPk,
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: tier1,
+        feeCollector: feeCollector.publicKey,
+        gateEscrow: null,
+        tokenMint: null,
+        tokenProgram: null,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm)
+    const counter = await program.account.userCounter.fetch(user1CounterPk);
+    const firstPosition = await program.account.userInfo.fetch(user1InfoPk);
+    const secondPosition = await program.account.userInfo.fetch(positionPk);
+    expect(counter.positionCount.toNumber()).to.equals(2);
+    expect(firstPosition.index.toNumber()).to.equals(0);
+    expect(secondPosition.index.toNumber()).to.equals(1);
+    expect(secondPosition.owner.toString()).to.equals(user1.publicKey.toString());
+  });
+
+  it("Migrate Tier Test", async () => {
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(1)],
+      program.programId
+    );
+    await mintToAccount(user1.publicKey, 1000);
+    const beforeFromTierInfo = await program.account.tierInfo.fetch(tier1);
+    const beforeToTierInfo = await program.account.tierInfo.fetch(tier2);
+    const beforeUserInfo = await program.account.userInfo.fetch(positionPk);
+    const beforeVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const beforePenaltyCollectorBal = await connection.getBalance(penaltyCollector.publicKey);
+    const migrateSig = await program.methods.migrateTier(
+      tierInfo["tier2"].nonce
+    )
+      .accounts({
+        ...noTokenAccounts,
+        signer: user1.publicKey,
+        userInfo: positionPk,
+        positionNft: null,
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        fromTierInfo: tier1,
+        toTierInfo: tier2,
+        feeCollector: feeCollector.publicKey,
+        penaltyCollector: penaltyCollector.publicKey
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm)
+    const afterFromTierInfo = await program.account.tierInfo.fetch(tier1);
+    const afterToTierInfo = await program.account.tierInfo.fetch(tier2);
+    const afterUserInfo = await program.account.userInfo.fetch(positionPk);
+    const afterVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const afterPenaltyCollectorBal = await connection.getBalance(penaltyCollector.publicKey);
+    const locked = beforeUserInfo.totalLocked.toNumber();
+    expect(afterUserInfo.tier.toString()).to.equals(tier2.toString());
+    expect(afterUserInfo.totalLocked.toNumber()).to.equals(locked);
+    expect(afterUserInfo.accruedInterest.toNumber()).to.equals(
+      calculateInterest(locked, tierInfo["tier2"].apy, tierInfo["tier2"].lockDuration)
+    );
+    expect(beforeFromTierInfo.totalLocked.toNumber() - afterFromTierInfo.totalLocked.toNumber()).to.equals(locked);
+    expect(afterToTierInfo.totalLocked.toNumber() - beforeToTierInfo.totalLocked.toNumber()).to.equals(locked);
+    // The settled interest is paid out and the new lock is promised in its place
+    expect(afterVaultInfo.totalPrincipal.toNumber()).to.equals(beforeVaultInfo.totalPrincipal.toNumber());
+    expect(
+      afterVaultInfo.totalPromisedInterest.toNumber() - beforeVaultInfo.totalPromisedInterest.toNumber()
+    ).to.equals(afterUserInfo.accruedInterest.toNumber() - beforeUserInfo.accruedInterest.toNumber());
+    // Same lock duration counts as an upgrade so no penalty is charged
+    expect(afterPenaltyCollectorBal).to.equals(beforePenaltyCollectorBal);
+    const migrated = await eventOf(migrateSig, "TierMigrated");
+    expect(migrated.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(migrated.userInfo.toString()).to.equals(positionPk.toString());
+    expect(migrated.fromTier.toString()).to.equals(tier1.toString());
+    expect(migrated.toTier.toString()).to.equals(tier2.toString());
+    expect(migrated.penalty.toNumber()).to.equals(0);
+    expect(migrated.accruedInterest.toNumber()).to.equals(afterUserInfo.accruedInterest.toNumber());
+  });
+
+  it("Partial Withdrawal Test", async () => {
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(1)],
+      program.programId
+    );
+    const amount = 1 * LAMPORTS_PER_SOL;
+    const beforeUserInfo = await program.account.userInfo.fetch(positionPk);
+    const beforeTierInfo = await program.account.tierInfo.fetch(tier2);
+    const beforeVaultBal = await connection.getBalance(mineVault);
+    const beforeVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const withdrawSig = await program.methods.withdrawPartial(
+      new anchor.BN(amount)
+    )
+      .accounts({
+        ...noTokenAccounts,
+        signer: user1.publicKey,
+        userInfo: positionPk,
+        positionNft: null,
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: tier2,
+        feeCollector: feeCollector.publicKey,
+        penaltyCollector: penaltyCollector.publicKey,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm)
+    const afterUserInfo = await program.account.userInfo.fetch(positionPk);
+    const afterTierInfo = await program.account.tierInfo.fetch(tier2);
+    const afterVaultBal = await connection.getBalance(mineVault);
+    const expectedRemaining = beforeUserInfo.totalLocked.toNumber() - amount;
+    const expectedInterest = calculateInterest(expectedRemaining, tierInfo["tier2"].apy, tierInfo["tier2"].lockDuration);
+    const withdrawnInterest = beforeUserInfo.accruedInterest.toNumber() - afterUserInfo.accruedInterest.toNumber();
+    expect(afterUserInfo.totalLocked.toNumber()).to.equals(expectedRemaining);
+    expect(afterUserInfo.accruedInterest.toNumber()).to.equals(expectedInterest);
+    expect(afterUserInfo.lockTs.toNumber()).to.equals(beforeUserInfo.lockTs.toNumber());
+    expect(beforeTierInfo.totalLocked.toNumber() - afterTierInfo.totalLocked.toNumber()).to.equals(amount);
+    expect(beforeVaultBal - afterVaultBal).to.equals(amount + withdrawnInterest);
+    const afterVaultInfo = await program.account.mineVault.fetch(mineVault);
+    expect(beforeVaultInfo.totalPrincipal.toNumber() - afterVaultInfo.totalPrincipal.toNumber()).to.equals(amount);
+    expect(
+      beforeVaultInfo.totalPromisedInterest.toNumber() - afterVaultInfo.totalPromisedInterest.toNumber()
+    ).to.equals(withdrawnInterest);
+    const withdrawn = await eventOf(withdrawSig, "StakeWithdrawn");
+    expect(withdrawn.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(withdrawn.userInfo.toString()).to.equals(positionPk.toString());
+    expect(withdrawn.tier.toString()).to.equals(tier2.toString());
+    expect(withdrawn.principal.toNumber()).to.equals(amount);
+    expect(withdrawn.interest.toNumber()).to.equals(withdrawnInterest);
+    expect(withdrawn.amountOut.toNumber() + withdrawn.devFee.toNumber() + withdrawn.penalty.toNumber()).to.equals(amount + withdrawnInterest);
+    expect(withdrawn.isPartial).to.equals(true);
+  });
+
+  it("Receipt Token Test", async () => {
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(1)],
+      program.programId
+    );
+    const [receiptMint, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt"), tier2.toBuffer()],
+      program.programId
+    );
+    const receiptMintSig = await program.methods.initReceiptMint()
+      .accounts({
+        ...noTokenAccounts,
+        admin: initializer.publicKey,
+        tierInfo: tier2,
+        mineInfo: mineAccount,
+        roles,
+        mineVault,
+        receiptMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const tier2Info = await program.account.tierInfo.fetch(tier2);
+    expect(tier2Info.receiptMint.toString()).to.equals(receiptMint.toString());
+    const created = await eventOf(receiptMintSig, "ReceiptMintCreated");
+    expect(created.tier.toString()).to.equals(tier2.toString());
+    expect(created.receiptMint.toString()).to.equals(receiptMint.toString());
+
+    const receiptAccount = await getOrCreateAssociatedTokenAccount(
+      connection,
+      initializer,
+      receiptMint,
+      user1.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_PROGRAM_ID
+    );
+    const position = await program.account.userInfo.fetch(positionPk);
+    const receiptAccounts = {
+      signer: user1.publicKey,
+      userInfo: positionPk,
+      positionNft: null,
+      mineInfo: mineAccount,
+      tierInfo: tier2,
+      receiptMint,
+      receiptAccount: receiptAccount.address,
+      tokenProgram: TOKEN_PROGRAM_ID
+    };
+    const receiptAmount = 2 * LAMPORTS_PER_SOL;
+    const mintSig = await program.methods.mintReceipt(new anchor.BN(receiptAmount))
+      .accounts({...receiptAccounts, mineVault})
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    let balance = await connection.getTokenAccountBalance(receiptAccount.address);
+    expect(balance.value.amount).to.equals(receiptAmount.toString());
+    const minted = await eventOf(mintSig, "ReceiptMinted");
+    expect(minted.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(minted.userInfo.toString()).to.equals(positionPk.toString());
+    expect(minted.receiptMint.toString()).to.equals(receiptMint.toString());
+    expect(minted.amount.toNumber()).to.equals(receiptAmount);
+    expect(minted.receiptAmount.toNumber()).to.equals(position.receiptAmount.toNumber() + receiptAmount);
+
+    // Principal backed by receipts cannot leave the position
+    try {
+      await program.methods.withdrawPartial(new anchor.BN(position.totalLocked.toNumber() - receiptAmount + 1))
+        .accounts({
+          ...noTokenAccounts,
+          signer: user1.publicKey,
+          userInfo: positionPk,
+          positionNft: null,
+          tokenAccount: user1Ata,
+          gateMint: null,
+          mineInfo: mineAccount,
+          mineVault,
+          tierInfo: tier2,
+          feeCollector: feeCollector.publicKey,
+          penaltyCollector: penaltyCollector.publicKey,
+          systemProgram: SystemProgram.programId
+        })
+        .signers([user1])
+        .rpc()
+        .then(confirm);
+      expect.fail("withdrew principal backed by receipts");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+
+    // Receipts are fungible across the tier, only the position holder can
+    // redeem them against the position
+    const user2ReceiptAccount = await getOrCreateAssociatedTokenAccount(
+      connection,
+      initializer,
+      receiptMint,
+      user2.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_PROGRAM_ID
+    );
+    const amount = 1 * LAMPORTS_PER_SOL;
+    await transferChecked(
+      connection,
+      user1,
+      receiptAccount.address,
+      receiptMint,
+      user2ReceiptAccount.address,
+      user1,
+      amount,
+      9,
+      [],
+      undefined,
+      TOKEN_PROGRAM_ID
+    );
+    const burnAccounts = {
+      signer: user1.publicKey,
+      userInfo: positionPk,
+      positionNft: null,
+      mineInfo: mineAccount,
+      mineVault,
+      tierInfo: tier2,
+      receiptMint,
+      receiptAccount: receiptAccount.address,
+      feeCollector: feeCollector.publicKey,
+      penaltyCollector: penaltyCollector.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID
+    };
+    try {
+      await program.methods.burnReceipt(new anchor.BN(amount))
+        .accounts({...noTokenAccounts, ...burnAccounts, signer: user2.publicKey, receiptAccount: user2ReceiptAccount.address})
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("redeemed receipts against another holder's position");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("Unauthorized");
+    }
+    await transferChecked(
+      connection,
+      user2,
+      user2ReceiptAccount.address,
+      receiptMint,
+      receiptAccount.address,
+      user2,
+      amount,
+      9,
+      [],
+      undefined,
+      TOKEN_PROGRAM_ID
+    );
+
+    // The position is still locked so the early withdrawal fee applies
+    const beforeUser1Bal = await connection.getBalance(user1.publicKey);
+    const beforeVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const burnSig = await program.methods.burnReceipt(new anchor.BN(amount))
+      .accounts({...noTokenAccounts, ...burnAccounts})
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    const afterUser1Bal = await connection.getBalance(user1.publicKey);
+    const afterVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const expectedDevFee = (amount * devFee)/10000;
+    const expectedPenalty = (amount * earlyClaimFee)/10000;
+    expect(afterUser1Bal - beforeUser1Bal).to.equals(amount - expectedDevFee - expectedPenalty);
+    expect(
+      beforeVaultInfo.totalPrincipal.toNumber() - afterVaultInfo.totalPrincipal.toNumber()
+    ).to.equals(amount);
+    balance = await connection.getTokenAccountBalance(receiptAccount.address);
+    expect(balance.value.amount).to.equals(amount.toString());
+    let afterPosition = await program.account.userInfo.fetch(positionPk);
+    expect(afterPosition.totalLocked.toNumber()).to.equals(position.totalLocked.toNumber() - amount);
+    expect(afterPosition.receiptAmount.toNumber()).to.equals(receiptAmount - amount);
+    const burned = await eventOf(burnSig, "ReceiptBurned");
+    expect(burned.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(burned.userInfo.toString()).to.equals(positionPk.toString());
+    expect(burned.receiptMint.toString()).to.equals(receiptMint.toString());
+    expect(burned.amount.toNumber()).to.equals(amount);
+    expect(burned.amountOut.toNumber()).to.equals(amount - expectedDevFee - expectedPenalty);
+    expect(burned.devFee.toNumber()).to.equals(expectedDevFee);
+    expect(burned.penalty.toNumber()).to.equals(expectedPenalty);
+    expect(burned.receiptAmount.toNumber()).to.equals(receiptAmount - amount);
+
+    // The rest of the receipts are redeemed the same way
+    await program.methods.burnReceipt(new anchor.BN(receiptAmount - amount))
+      .accounts({...noTokenAccounts, ...burnAccounts})
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    balance = await connection.getTokenAccountBalance(receiptAccount.address);
+    expect(balance.value.amount).to.equals("0");
+    afterPosition = await program.account.userInfo.fetch(positionPk);
+    expect(afterPosition.receiptAmount.toNumber()).to.equals(0);
+    expect(afterPosition.totalLocked.toNumber()).to.equals(position.totalLocked.toNumber() - receiptAmount);
+  });
+
+  it("Position NFT Test", async () => {
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(1)],
+      program.programId
+    );
+    const [positionMint, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("position"), positionPk.toBuffer()],
+      program.programId
+    );
+    const user1PositionAccount = getAssociatedTokenAddressSync(positionMint, user1.publicKey, false, TOKEN_2022_PROGRAM_ID);
+    const nftSig = await program.methods.mintPositionNft("https://example.com/position.json")
+      .accounts({
+        signer: user1.publicKey,
+        userInfo: positionPk,
+        mineInfo: mineAccount,
+        mineVault,
+        positionMint,
+        positionAccount: user1PositionAccount,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    const position = await program.account.userInfo.fetch(positionPk);
+    expect(position.nftMint.toString()).to.equals(positionMint.toString());
+    const metadata = await getTokenMetadata(connection, positionMint, undefined, TOKEN_2022_PROGRAM_ID);
+    expect(metadata.additionalMetadata).to.deep.include(["amount", position.totalLocked.toString()]);
+    const nftMinted = await eventOf(nftSig, "PositionNftMinted");
+    expect(nftMinted.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(nftMinted.userInfo.toString()).to.equals(positionPk.toString());
+    expect(nftMinted.nftMint.toString()).to.equals(positionMint.toString());
+
+    // Hand the position over to user2
+    const user2PositionAccount = await getOrCreateAssociatedTokenAccount(
+      connection,
+      initializer,
+      positionMint,
+      user2.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await transferChecked(
+      connection,
+      user1,
+      user1PositionAccount,
+      positionMint,
+      user2PositionAccount.address,
+      user1,
+      1,
+      0,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const claimAccounts = {
+      userInfo: positionPk,
+      mineInfo: mineAccount,
+      mineVault,
+      tierInfo: tier2,
+      referrerInfo: null,
+      feeCollector: feeCollector.publicKey,
+      penaltyCollector: penaltyCollector.publicKey
+    };
+    try {
+      await program.methods.claimInterest()
+        .accounts({
+          ...noTokenAccounts,
+          ...claimAccounts,
+          signer: user1.publicKey,
+          positionNft: user1PositionAccount,
+          tokenAccount: user1Ata,
+          gateMint: null
+        })
+        .signers([user1])
+        .rpc()
+        .then(confirm);
+      expect.fail("claimed a position without holding its NFT");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    const beforeClaimUserBal = await connection.getBalance(user2.publicKey);
+    await program.methods.claimInterest()
+      .accounts({
+        ...noTokenAccounts,
+        ...claimAccounts,
+        signer: user2.publicKey,
+        positionNft: user2PositionAccount.address,
+        tokenAccount: user2Ata,
+        gateMint: null
+      })
+      .signers([user2])
+      .rpc()
+      .then(confirm);
+    const afterClaimUserBal = await connection.getBalance(user2.publicKey);
+    expect(afterClaimUserBal).to.greaterThan(beforeClaimUserBal);
+
+    // Return the NFT so later tests can keep using the position
+    await transferChecked(
+      connection,
+      user2,
+      user2PositionAccount.address,
+      positionMint,
+      user1PositionAccount,
+      user2,
+      1,
+      0,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
   });
 
   it("Increase stake Test", async () => {
     const accounts = {
       signer: user1.publicKey,
       userInfo: user1InfoPk,
+      positionNft: null,
       tokenAccount: user1Ata,
+      gateMint: null,
       mineInfo: mineAccount,
       mineVault,
       tierInfo: tier1,
+      referrerInfo: null,
+      secondReferrerInfo: null,
+      thirdReferrerInfo: null,
       feeCollector: feeCollector.publicKey,
       systemProgram: SystemProgram.programId
     };
@@ -359,10 +1114,10 @@ describe("tier-sol-miner", () => {
     const beforeIncrFeeCollectorBalance = await connection.getBalance(feeCollector.publicKey);
     const beforeIncrUserInfo = await program.account.userInfo.fetch(user1InfoPk);
     await sleep(5 * 1000); // Sleep for 5 seconds before increasing stake
-    await program.methods.increaseStake(
+    const increaseSig = await program.methods.increaseStake(
       new anchor.BN(stakeAmount*LAMPORTS_PER_SOL)
     )
-      .accounts({ ...accounts })
+      .accounts({ ...noTokenAccounts, ...accounts })
       .signers([user1])
       .rpc()
       .then(confirm)
@@ -385,11 +1140,19 @@ describe("tier-sol-miner", () => {
     expect(tInfo.totalLocked.toNumber() - beforeIncrTierInfo.totalLocked.toNumber()).to.equals(actualDeposit);
     expect(afterIncrVaultBalance - beforeIncrVaultBalance).to.equals(actualDeposit);
     expect(afterIncrFeeCollectorBalance - beforeIncrFeeCollectorBalance).to.equals(expectedDevFee);
+    const increased = await eventOf(increaseSig, "StakeIncreased");
+    expect(increased.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(increased.userInfo.toString()).to.equals(user1InfoPk.toString());
+    expect(increased.tier.toString()).to.equals(tier1.toString());
+    expect(increased.amount.toNumber()).to.equals(actualDeposit);
+    expect(increased.devFee.toNumber()).to.equals(expectedDevFee);
+    expect(increased.totalLocked.toNumber()).to.equals(expectedNewTotalLocked);
+    expect(increased.accruedInterest.toNumber()).to.equals(userInfo.accruedInterest.toNumber());
   });
 
   it("Account WhiteList Test", async () => {
     const [whitelistInfoPK, _] = PublicKey.findProgramAddressSync(
-      [Buffer.from("whitelist"), user3.publicKey.toBuffer()], 
+      [Buffer.from("whitelist"), mineAccount.toBuffer(), user3.publicKey.toBuffer()], 
       program.programId
     );
     const accounts = {
@@ -397,12 +1160,14 @@ describe("tier-sol-miner", () => {
       beneficiary: user3.publicKey,
       whitelistInfo: whitelistInfoPK,
       tierInfo: tier3,
+      mineInfo: mineAccount,
+      roles,
       systemProgram: SystemProgram.programId
     }
     const tInfo = await program.account.tierInfo.fetch(tier3);
     const now = Date.now()/1000;
     const expiry = now + 10;
-    await program.methods.whitelistAccount(
+    const whitelistSig = await program.methods.whitelistAccount(
       tInfo.nonce,
       new anchor.BN(expiry)
     )
@@ -415,32 +1180,126 @@ describe("tier-sol-miner", () => {
     expect(whitelistInfo.beneficiary.toString()).to.equals(user3.publicKey.toString());
     expect(whitelistInfo.tier.toString()).to.equals(tier3.toString());
     expect(whitelistInfo.expiry.toNumber()).to.equals(Math.floor(expiry));
+    const whitelisted = await eventOf(whitelistSig, "WhitelistUpdated");
+    expect(whitelisted.beneficiary.toString()).to.equals(user3.publicKey.toString());
+    expect(whitelisted.tier.toString()).to.equals(tier3.toString());
+    expect(whitelisted.expiry.toNumber()).to.equals(Math.floor(expiry));
+    expect(whitelisted.revoked).to.equals(false);
   });
 
-  it("Init WhiteList Test", async () => {
-    const [whitelistInfoPK, _1] = PublicKey.findProgramAddressSync(
-      [Buffer.from("whitelist"), user3.publicKey.toBuffer()], 
+  it("Account WhiteList Failure Test [Not Admin]", async () => {
+    const [whitelistInfoPK, _] = PublicKey.findProgramAddressSync(
+      [Buffer.from("whitelist"), mineAccount.toBuffer(), user4.publicKey.toBuffer()], 
       program.programId
     );
     const accounts = {
-      signer: user3.publicKey,
-      userInfo: user3InfoPk,
-      mineInfo: mineAccount,
-      mineVault,
+      admin: user4.publicKey,
+      beneficiary: user4.publicKey,
       whitelistInfo: whitelistInfoPK,
       tierInfo: tier3,
-      feeCollector: feeCollector.publicKey,
+      mineInfo: mineAccount,
+      roles,
       systemProgram: SystemProgram.programId
-    };
+    }
+    try {
+      await program.methods.whitelistAccount(
+        tierInfo["tier3"].nonce,
+        new anchor.BN(Date.now()/1000 + 10)
+      ).accounts({ ...accounts }).signers([user4]).rpc().then(confirm);
+      expect.fail("non admin whitelisted an account");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+  });
 
-    const tierInfo = await program.account.tierInfo.fetch(tier3);
-    const beforeInitVaultBalance = await connection.getBalance(mineVault);
-    const beforeInitFeeCollectorBalance = await connection.getBalance(feeCollector.publicKey);
-    await program.methods.initializeWhitelist(
+  it("Update WhiteList Test", async () => {
+    const [whitelistInfoPK, _] = PublicKey.findProgramAddressSync(
+      [Buffer.from("whitelist"), mineAccount.toBuffer(), user3.publicKey.toBuffer()], 
+      program.programId
+    );
+    const accounts = {
+      admin: initializer.publicKey,
+      beneficiary: user3.publicKey,
+      whitelistInfo: whitelistInfoPK,
+      tierInfo: tier3,
+      mineInfo: mineAccount,
+      roles
+    }
+    const expiry = Math.floor(Date.now()/1000) + 60;
+    await program.methods.updateWhitelist(
+      new anchor.BN(expiry)
+    )
+      .accounts({ ...accounts })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+
+    const whitelistInfo = await program.account.whitelistInfo.fetch(whitelistInfoPK);
+    expect(whitelistInfo.tier.toString()).to.equals(tier3.toString());
+    expect(whitelistInfo.expiry.toNumber()).to.equals(expiry);
+  });
+
+  it("Revoke WhiteList Test", async () => {
+    const [whitelistInfoPK, _] = PublicKey.findProgramAddressSync(
+      [Buffer.from("whitelist"), mineAccount.toBuffer(), user4.publicKey.toBuffer()], 
+      program.programId
+    );
+    await program.methods.whitelistAccount(
+      tierInfo["tier3"].nonce,
+      new anchor.BN(Date.now()/1000 + 10)
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        beneficiary: user4.publicKey,
+        whitelistInfo: whitelistInfoPK,
+        tierInfo: tier3,
+        mineInfo: mineAccount,
+        roles,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    await program.methods.revokeWhitelist()
+      .accounts({
+        admin: initializer.publicKey,
+        beneficiary: user4.publicKey,
+        whitelistInfo: whitelistInfoPK,
+        mineInfo: mineAccount,
+        roles
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const info = await connection.getAccountInfo(whitelistInfoPK);
+    expect(info).to.equals(null);
+  });
+
+  it("Init WhiteList Test", async () => {
+    const [whitelistInfoPK, _1] = PublicKey.findProgramAddressSync(
+      [Buffer.from("whitelist"), mineAccount.toBuffer(), user3.publicKey.toBuffer()], 
+      program.programId
+    );
+    const accounts = {
+      signer: user3.publicKey,
+      userCounter: user3CounterPk,
+      userInfo: user3InfoPk,
+      mineInfo: mineAccount,
+      mineVault,
+      whitelistInfo: whitelistInfoPK,
+      tierInfo: tier3,
+      feeCollector: feeCollector.publicKey,
+      systemProgram: SystemProgram.programId
+    };
+
+    const tierInfo = await program.account.tierInfo.fetch(tier3);
+    const beforeInitVaultBalance = await connection.getBalance(mineVault);
+    const beforeInitFeeCollectorBalance = await connection.getBalance(feeCollector.publicKey);
+    await program.methods.initializeWhitelist(
       tierInfo.nonce, 
       new anchor.BN(stakeAmount*LAMPORTS_PER_SOL)
     )
-      .accounts({ ...accounts })
+      .accounts({ ...noTokenAccounts, ...accounts })
       .signers([user3])
       .rpc()
       .then(confirm)
@@ -465,16 +1324,85 @@ describe("tier-sol-miner", () => {
     expect(afterInitTierInfo.totalLocked.toNumber() - tierInfo.totalLocked.toNumber()).to.equals(expectedTotalLocked);
   });
 
+  it("Allowlist Publish And Consume Test", async () => {
+    const otherBeneficiary = new Keypair();
+    const leaf0 = allowlistLeaf(0, user4.publicKey);
+    const leaf1 = allowlistLeaf(1, otherBeneficiary.publicKey);
+    const root = hashPair(leaf0, leaf1);
+    const [allowlistPK, _] = PublicKey.findProgramAddressSync(
+      [Buffer.from("allowlist"), mineAccount.toBuffer(), root],
+      program.programId
+    );
+    const expiry = Math.floor(Date.now()/1000) + 60;
+    const publishSig = await program.methods.publishAllowlist(
+      tierInfo["tier3"].nonce,
+      Array.from(root),
+      new anchor.BN(expiry),
+      2
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        allowlist: allowlistPK,
+        tierInfo: tier3,
+        mineInfo: mineAccount,
+        roles,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const published = await eventOf(publishSig, "AllowlistPublished");
+    expect(published.allowlist.toString()).to.equals(allowlistPK.toString());
+    expect(published.tier.toString()).to.equals(tier3.toString());
+    expect(Buffer.from(published.root).equals(root)).to.equals(true);
+    expect(published.expiry.toNumber()).to.equals(expiry);
+    expect(published.capacity).to.equals(2);
+
+    const accounts = {
+      signer: user4.publicKey,
+      userCounter: user4CounterPk,
+      userInfo: user4InfoPk,
+      mineInfo: mineAccount,
+      mineVault,
+      allowlist: allowlistPK,
+      tierInfo: tier3,
+      feeCollector: feeCollector.publicKey,
+      systemProgram: SystemProgram.programId
+    };
+    await program.methods.initializeAllowlist(
+      tierInfo["tier3"].nonce,
+      new anchor.BN(stakeAmount*LAMPORTS_PER_SOL),
+      0,
+      [Array.from(leaf1)]
+    )
+      .accounts({ ...noTokenAccounts, ...accounts })
+      .signers([user4])
+      .rpc()
+      .then(confirm);
+
+    const allowlist = await program.account.allowList.fetch(allowlistPK);
+    const userInfo = await program.account.userInfo.fetch(user4InfoPk);
+    expect(allowlist.claimed[0]).to.equals(1);
+    expect(userInfo.isWhitelist).to.equals(true);
+    expect(userInfo.tier.toString()).to.equals(tier3.toString());
+  });
+
   it("Interest Compounding Failure Test [Lock duration not reached]", async () => {
     const accounts = {
       signer: user1.publicKey,
       userInfo: user1InfoPk,
+      positionNft: null,
       tokenAccount: user1Ata,
+      gateMint: null,
       mineInfo: mineAccount,
-      tierInfo: tier1
+      mineVault,
+      tierInfo: tier1,
+      referrerInfo: null,
+      secondReferrerInfo: null,
+      thirdReferrerInfo: null
     };
     try {
-      await program.methods.compound().accounts({...accounts}).signers([user1]).rpc().then(confirm)
+      await program.methods.compound().accounts({...noTokenAccounts, ...accounts}).signers([user1]).rpc().then(confirm)
     } catch (error) {
       expect(error).to.be.an('Error');
     }
@@ -486,13 +1414,19 @@ describe("tier-sol-miner", () => {
     const accounts = {
       signer: user1.publicKey,
       userInfo: user1InfoPk,
+      positionNft: null,
       tokenAccount: user1Ata,
+      gateMint: null,
       mineInfo: mineAccount,
-      tierInfo: tier1
+      mineVault,
+      tierInfo: tier1,
+      referrerInfo: null,
+      secondReferrerInfo: null,
+      thirdReferrerInfo: null
     };
     await sleep(5*1000);
-    await program.methods.compound()
-      .accounts({...accounts})
+    const compoundSig = await program.methods.compound()
+      .accounts({...noTokenAccounts, ...accounts})
       .signers([user1])
       .rpc()
       .then(confirm);
@@ -510,6 +1444,13 @@ describe("tier-sol-miner", () => {
     ).to.equals(beforeCompUserInfo.accruedInterest.toNumber());
     expect(afterCompUserInfo.totalLocked.toNumber()).to.equals(expectedNewTotalLocked);
     expect(afterCompUserInfo.lockTs.toNumber()).to.greaterThan(beforeCompUserInfo.lockTs.toNumber());
+    const compounded = await eventOf(compoundSig, "InterestCompounded");
+    expect(compounded.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(compounded.userInfo.toString()).to.equals(user1InfoPk.toString());
+    expect(compounded.tier.toString()).to.equals(tier1.toString());
+    expect(compounded.compounded.toNumber()).to.equals(beforeCompUserInfo.accruedInterest.toNumber());
+    expect(compounded.totalLocked.toNumber()).to.equals(expectedNewTotalLocked);
+    expect(compounded.accruedInterest.toNumber()).to.equals(expectedNewInterest);
   })
 
   it("Claim Interest Tests [No Penalty]", async () => {
@@ -517,16 +1458,20 @@ describe("tier-sol-miner", () => {
     const accounts = {
       signer: user1.publicKey,
       userInfo: user1InfoPk,
+      positionNft: null,
       tokenAccount: user1Ata,
+      gateMint: null,
       mineInfo: mineAccount,
       mineVault,
       tierInfo: tier1,
+      referrerInfo: null,
       feeCollector: feeCollector.publicKey,
       penaltyCollector: penaltyCollector.publicKey
     };
     const beforeClaimUserInfo = await program.account.userInfo.fetch(user1InfoPk);
     const beforeClaimUserBal = await connection.getBalance(user1.publicKey);
     const beforeClaimVaultBal = await connection.getBalance(mineVault);
+    const beforeClaimVaultInfo = await program.account.mineVault.fetch(mineVault);
     const beforeClaimFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
     const beforeClaimPenaltyCollectorBal = await connection.getBalance(penaltyCollector.publicKey);
     const expectedDevFee = (devFee * beforeClaimUserInfo.accruedInterest.toNumber())/10000;
@@ -535,8 +1480,8 @@ describe("tier-sol-miner", () => {
       beforeClaimUserInfo.totalLocked.toNumber(), 
       tierInfo['tier1'].apy, tierInfo['tier1'].lockDuration
     );
-    await program.methods.claimInterest()
-      .accounts({...accounts})
+    const claimSig = await program.methods.claimInterest()
+      .accounts({...noTokenAccounts, ...accounts})
       .signers([user1])
       .rpc({skipPreflight: true})
       .then(confirm)
@@ -552,6 +1497,20 @@ describe("tier-sol-miner", () => {
     expect(afterClaimUserBal - beforeClaimUserBal).to.equals(expectedAmountOut);
     expect(afterClaimUserInfo.lockTs.toNumber()).to.greaterThan(beforeClaimUserInfo.lockTs.toNumber());
     expect(afterClaimUserInfo.accruedInterest.toNumber()).to.equals(expectedNewInterest);
+    // The claimed interest leaves the liabilities and the restarted lock is promised
+    const afterClaimVaultInfo = await program.account.mineVault.fetch(mineVault);
+    expect(afterClaimVaultInfo.totalPrincipal.toNumber()).to.equals(beforeClaimVaultInfo.totalPrincipal.toNumber());
+    expect(
+      afterClaimVaultInfo.totalPromisedInterest.toNumber() - beforeClaimVaultInfo.totalPromisedInterest.toNumber()
+    ).to.equals(expectedNewInterest - beforeClaimUserInfo.accruedInterest.toNumber());
+    const claimed = await eventOf(claimSig, "InterestClaimed");
+    expect(claimed.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(claimed.userInfo.toString()).to.equals(user1InfoPk.toString());
+    expect(claimed.tier.toString()).to.equals(tier1.toString());
+    expect(claimed.interest.toNumber()).to.equals(beforeClaimUserInfo.accruedInterest.toNumber());
+    expect(claimed.amountOut.toNumber()).to.equals(expectedAmountOut);
+    expect(claimed.devFee.toNumber()).to.equals(expectedDevFee);
+    expect(claimed.penalty.toNumber()).to.equals(0);
   })
 
   it("Claim Interest Tests [Penalty]", async () => {
@@ -559,10 +1518,13 @@ describe("tier-sol-miner", () => {
     const accounts = {
       signer: user1.publicKey,
       userInfo: user1InfoPk,
+      positionNft: null,
       tokenAccount: user1Ata,
+      gateMint: null,
       mineInfo: mineAccount,
       mineVault,
       tierInfo: tier1,
+      referrerInfo: null,
       feeCollector: feeCollector.publicKey,
       penaltyCollector: penaltyCollector.publicKey
     };
@@ -575,7 +1537,7 @@ describe("tier-sol-miner", () => {
     const expectedPenaltyFee = (earlyClaimFee * beforeClaimUserInfo.accruedInterest.toNumber())/10000;
     const expectedAmountOut = beforeClaimUserInfo.accruedInterest.toNumber() - (expectedDevFee + expectedPenaltyFee);
     await program.methods.claimInterest()
-      .accounts({...accounts})
+      .accounts({...noTokenAccounts, ...accounts})
       .signers([user1])
       .rpc({skipPreflight: true})
       .then(confirm)
@@ -593,134 +1555,1945 @@ describe("tier-sol-miner", () => {
     expect(afterClaimUserInfo.accruedInterest.toNumber()).to.equals(0);
   })
 
-  it("Referral Withdrawal Test", async () => {
-    const accounts = {
-      signer: user1.publicKey,
-      userInfo: user1InfoPk,
-      referrerInfo: user1refInfoPK,
-      tokenAccount: user1Ata,
-      tierInfo: tier1,
-      mineInfo: mineAccount,
-      mineVault,
-      feeCollector: feeCollector.publicKey
-    };
-    const beforeClaimRefInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+  it("Continuous Accrual Test", async () => {
+    const [continuousTier, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([3])],
+      program.programId
+    );
+    await program.methods.addTier(
+      new anchor.BN(tierInfo["tier1"].apy),
+      new anchor.BN(0),
+      new anchor.BN(tierInfo["tier1"].lockDuration),
+      { continuous: {} }
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        tierInfo: continuousTier,
+        mineInfo: mineAccount,
+        roles,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const counter = await program.account.userCounter.fetch(user1CounterPk);
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(counter.positionCount.toNumber())],
+      program.programId
+    );
+    await program.methods.initializeStaking(
+      3,
+      new anchor.BN(stakeAmount*LAMPORTS_PER_SOL)
+    )
+      .accounts({
+        ...noTokenAccounts,
+        signer: user1.publicKey,
+        userCounter: user1CounterPk,
+        userInfo: positionPk,
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: continuousTier,
+        feeCollector: feeCollector.publicKey,
+        gateEscrow: null,
+        tokenMint: null,
+        tokenProgram: null,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    const beforeClaimUserInfo = await program.account.userInfo.fetch(positionPk);
+    expect(beforeClaimUserInfo.accruedInterest.toNumber()).to.equals(0);
+    expect(beforeClaimUserInfo.lastAccrualTs.toNumber()).to.equals(beforeClaimUserInfo.lockTs.toNumber());
+
+    // Claiming before the lock ends pays what was earned with no penalty
+    await sleep(2*1000);
+    const beforeClaimPenaltyCollectorBal = await connection.getBalance(penaltyCollector.publicKey);
     const beforeClaimUserBal = await connection.getBalance(user1.publicKey);
-    const beforeClaimVaultBal = await connection.getBalance(mineVault);
-    const beforeClaimFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
-    const expectedDevFee = (beforeClaimRefInfo.earnings.toNumber() * devFee)/10000;
-    const expectedAmountOut = beforeClaimRefInfo.earnings.toNumber() - expectedDevFee;
-    await program.methods.withdrawReferralRewards()
-      .accounts({...accounts})
+    await program.methods.claimInterest()
+      .accounts({
+        ...noTokenAccounts,
+        signer: user1.publicKey,
+        userInfo: positionPk,
+        positionNft: null,
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: continuousTier,
+        referrerInfo: null,
+        feeCollector: feeCollector.publicKey,
+        penaltyCollector: penaltyCollector.publicKey
+      })
       .signers([user1])
       .rpc()
       .then(confirm);
-    const afterClaimRefInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+    const afterClaimUserInfo = await program.account.userInfo.fetch(positionPk);
+    const afterClaimPenaltyCollectorBal = await connection.getBalance(penaltyCollector.publicKey);
     const afterClaimUserBal = await connection.getBalance(user1.publicKey);
-    const afterClaimVaultBal = await connection.getBalance(mineVault);
-    const afterClaimFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
-    expect(afterClaimRefInfo.earnings.toNumber()).to.equals(0);
-    expect(afterClaimUserBal - beforeClaimUserBal).to.equals(expectedAmountOut);
-    expect(afterClaimFeeCollectorBal - beforeClaimFeeCollectorBal).to.equals(expectedDevFee);
-    expect(beforeClaimVaultBal - afterClaimVaultBal).to.equals(beforeClaimRefInfo.earnings.toNumber());
+    const elapsed = afterClaimUserInfo.lastAccrualTs.toNumber() - beforeClaimUserInfo.lastAccrualTs.toNumber();
+    const earned = calculateInterest(beforeClaimUserInfo.totalLocked.toNumber(), tierInfo["tier1"].apy, elapsed);
+    expect(elapsed).to.greaterThan(0);
+    expect(afterClaimUserBal - beforeClaimUserBal).to.equals(earned - (devFee * earned)/10000);
+    expect(afterClaimPenaltyCollectorBal).to.equals(beforeClaimPenaltyCollectorBal);
+    expect(afterClaimUserInfo.accruedInterest.toNumber()).to.equals(0);
+    expect(afterClaimUserInfo.lockTs.toNumber()).to.equals(beforeClaimUserInfo.lockTs.toNumber());
   })
 
-  it("Terminate Staking Test [With Referral Info]", async () => {
-    const accounts = {
+  it("Emission Pool Test", async () => {
+    const [emissionTier, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([4])],
+      program.programId
+    );
+    const budget = LAMPORTS_PER_SOL / 100;
+    const rewardRate = budget / 4;
+    await program.methods.addTier(
+      new anchor.BN(0),
+      new anchor.BN(0),
+      new anchor.BN(tierInfo["tier1"].lockDuration),
+      { emission: {} }
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        tierInfo: emissionTier,
+        mineInfo: mineAccount,
+        roles,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const beforeFundVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const fundSig = await program.methods.fundEmission(
+      new anchor.BN(budget),
+      new anchor.BN(rewardRate)
+    )
+      .accounts({
+        ...noTokenAccounts,
+        admin: initializer.publicKey,
+        tierInfo: emissionTier,
+        mineInfo: mineAccount,
+        roles,
+        mineVault,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const afterFundVaultInfo = await program.account.mineVault.fetch(mineVault);
+    expect(
+      afterFundVaultInfo.totalPromisedInterest.toNumber() - beforeFundVaultInfo.totalPromisedInterest.toNumber()
+    ).to.equals(budget);
+    const funded = await eventOf(fundSig, "EmissionFunded");
+    expect(funded.tier.toString()).to.equals(emissionTier.toString());
+    expect(funded.amount.toNumber()).to.equals(budget);
+    expect(funded.rewardRate.toNumber()).to.equals(rewardRate);
+    expect(funded.rewardRemaining.toNumber()).to.equals(budget);
+
+    const counter = await program.account.userCounter.fetch(user1CounterPk);
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(counter.positionCount.toNumber())],
+      program.programId
+    );
+    await program.methods.initializeStaking(
+      4,
+      new anchor.BN(stakeAmount*LAMPORTS_PER_SOL)
+    )
+      .accounts({
+        ...noTokenAccounts,
+        signer: user1.publicKey,
+        userCounter: user1CounterPk,
+        userInfo: positionPk,
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: emissionTier,
+        feeCollector: feeCollector.publicKey,
+        gateEscrow: null,
+        tokenMint: null,
+        tokenProgram: null,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+
+    // Wait past the end of the budget so the whole of it is emitted
+    await sleep(6*1000);
+    const beforeClaimUserBal = await connection.getBalance(user1.publicKey);
+    await program.methods.claimInterest()
+      .accounts({
+        ...noTokenAccounts,
+        signer: user1.publicKey,
+        userInfo: positionPk,
+        positionNft: null,
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: emissionTier,
+        referrerInfo: null,
+        feeCollector: feeCollector.publicKey,
+        penaltyCollector: penaltyCollector.publicKey
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    const afterClaimUserBal = await connection.getBalance(user1.publicKey);
+    const emissionTierInfo = await program.account.tierInfo.fetch(emissionTier);
+    const paid = afterClaimUserBal - beforeClaimUserBal;
+    expect(emissionTierInfo.rewardRemaining.toNumber()).to.equals(0);
+    expect(paid).to.greaterThan(0);
+    expect(paid).to.lessThanOrEqual(budget - (devFee * budget)/10000);
+
+    // The unemitted budget of a stopped tier can be taken back
+    const emissionAccounts = {
       admin: initializer.publicKey,
-      userInfo: user1InfoPk,
-      referrerInfo: user1refInfoPK,
+      tierInfo: emissionTier,
       mineInfo: mineAccount,
-      mineVault,
-      tierInfo: tier1,
-      userAccount: user1.publicKey,
-      feeCollector: feeCollector.publicKey,
-      systemProgram: SystemProgram.programId
+      roles,
+      mineVault
     };
-    const userInfo = await program.account.userInfo.fetch(user1InfoPk);
-    const beforeTmUserBal = await connection.getBalance(user1.publicKey);
-    const beforeTmVaultBal = await connection.getBalance(mineVault);
-    const beforeTmFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
-    const expectedDevFee = (userInfo.totalLocked.toNumber() * devFee)/10000;
-    const expectedAmountOut = userInfo.totalLocked.toNumber() - expectedDevFee;
-    await program.methods.terminateStaking()
-      .accounts({...accounts})
+    await program.methods.fundEmission(new anchor.BN(budget), new anchor.BN(1))
+      .accounts({...noTokenAccounts, ...emissionAccounts, systemProgram: SystemProgram.programId})
       .signers([initializer])
       .rpc()
       .then(confirm);
-    const afterTmUserBal = await connection.getBalance(user1.publicKey);
-    const afterTmVaultBal = await connection.getBalance(mineVault);
-    const afterTmFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
-    expect(afterTmUserBal - beforeTmUserBal).to.equals(expectedAmountOut);
-    expect(afterTmFeeCollectorBal - beforeTmFeeCollectorBal).to.equals(expectedDevFee);
-    expect(beforeTmVaultBal - afterTmVaultBal).to.equals(userInfo.totalLocked.toNumber());
     try {
-      await program.account.userInfo.fetch(user1InfoPk);
-    } catch (error) {
-      expect(error).to.be.an('Error');
-    }
-    try {
-      await program.account.referralInfo.fetch(user1refInfoPK);
+      await program.methods.reclaimEmission()
+        .accounts(emissionAccounts)
+        .signers([initializer])
+        .rpc()
+        .then(confirm);
+      expect.fail("reclaimed the budget of a running emission");
     } catch (error) {
-      expect(error).to.be.an('Error');
+      expect(error.error.errorCode.code).to.equals("OperationNotAllowed");
     }
+    const tierAccounts = { admin: initializer.publicKey, tierInfo: emissionTier, mineInfo: mineAccount, roles };
+    const deactivateSig = await program.methods.updateTier(null, false, null)
+      .accounts(tierAccounts)
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const deactivated = await eventOf(deactivateSig, "TierUpdated");
+    expect(deactivated.tier.toString()).to.equals(emissionTier.toString());
+    expect(deactivated.nonce).to.equals(4);
+    expect(deactivated.isActive).to.equals(false);
+    expect(deactivated.accrualMode).to.deep.equal({ emission: {} });
+    expect(deactivated.escrowGating).to.equals(false);
+    const beforeReclaimVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const reclaimSig = await program.methods.reclaimEmission()
+      .accounts(emissionAccounts)
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const afterReclaimVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const reclaimedTierInfo = await program.account.tierInfo.fetch(emissionTier);
+    const reclaimed = beforeReclaimVaultInfo.totalPromisedInterest.toNumber() - afterReclaimVaultInfo.totalPromisedInterest.toNumber();
+    expect(reclaimedTierInfo.rewardRemaining.toNumber()).to.equals(0);
+    expect(reclaimed).to.greaterThan(0);
+    expect(reclaimed).to.lessThanOrEqual(budget);
+    const reclaimedEvent = await eventOf(reclaimSig, "EmissionReclaimed");
+    expect(reclaimedEvent.tier.toString()).to.equals(emissionTier.toString());
+    expect(reclaimedEvent.amount.toNumber()).to.equals(reclaimed);
+    await program.methods.updateTier(null, true, null)
+      .accounts(tierAccounts)
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
   })
 
-  it("Terminate Staking Test [No Referral Info]", async () => {
-    const accounts = {
-      admin: initializer.publicKey,
-      userInfo: user2InfoPk,
-      referrerInfo: null,
+  it("Escrow Gating Test", async () => {
+    const [escrowTier, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([5])],
+      program.programId
+    );
+    const [gateEscrow, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("gate-escrow"), mineAccount.toBuffer()],
+      program.programId
+    );
+    const minimumTokenAmount = 5 * 10**TOKEN_DECIMALS;
+    await program.methods.addTier(
+      new anchor.BN(tierInfo["tier1"].apy),
+      new anchor.BN(minimumTokenAmount),
+      new anchor.BN(tierInfo["tier1"].lockDuration),
+      { fixed: {} }
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        tierInfo: escrowTier,
+        mineInfo: mineAccount,
+        roles,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    await program.methods.updateTier(null, null, true)
+      .accounts({ admin: initializer.publicKey, tierInfo: escrowTier, mineInfo: mineAccount, roles })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    await program.methods.initGateEscrow()
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: mineAccount,
+        mineVault,
+        tokenMint: mintKeyPair.publicKey,
+        gateEscrow,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+
+    const counter = await program.account.userCounter.fetch(user1CounterPk);
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(counter.positionCount.toNumber())],
+      program.programId
+    );
+    const stakeAccounts = {
+      signer: user1.publicKey,
+      userCounter: user1CounterPk,
+      userInfo: positionPk,
+      tokenAccount: user1Ata,
+      gateMint: null,
       mineInfo: mineAccount,
       mineVault,
-      tierInfo: tier2,
-      userAccount: user2.publicKey,
+      tierInfo: escrowTier,
       feeCollector: feeCollector.publicKey,
       systemProgram: SystemProgram.programId
     };
-    const userInfo = await program.account.userInfo.fetch(user2InfoPk);
-    const beforeTmUserBal = await connection.getBalance(user2.publicKey);
-    const beforeTmVaultBal = await connection.getBalance(mineVault);
-    const beforeTmFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
-    const expectedDevFee = (userInfo.totalLocked.toNumber() * devFee)/10000;
-    const expectedAmountOut = userInfo.totalLocked.toNumber() - expectedDevFee;
-    await program.methods.terminateStaking()
-      .accounts({...accounts})
+    // The tier cannot be joined on a balance snapshot alone
+    try {
+      await program.methods.initializeStaking(5, new anchor.BN(stakeAmount*LAMPORTS_PER_SOL))
+        .accounts({...noTokenAccounts, ...stakeAccounts, gateEscrow: null, tokenMint: null, tokenProgram: null})
+        .signers([user1])
+        .rpc()
+        .then(confirm);
+      expect.fail("staked in an escrow gated tier without escrow");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    const gateAccounts = {
+      gateEscrow,
+      tokenMint: mintKeyPair.publicKey,
+      tokenProgram: TOKEN_2022_PROGRAM_ID
+    };
+    const escrowSig = await program.methods.initializeStaking(5, new anchor.BN(stakeAmount*LAMPORTS_PER_SOL))
+      .accounts({...noTokenAccounts, ...stakeAccounts, ...gateAccounts})
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    const position = await program.account.userInfo.fetch(positionPk);
+    let escrowBalance = await connection.getTokenAccountBalance(gateEscrow);
+    expect(position.escrowedAmount.toNumber()).to.greaterThan(0);
+    expect(escrowBalance.value.amount).to.equals(position.escrowedAmount.toString());
+    const escrowed = await eventOf(escrowSig, "GateEscrowed");
+    expect(escrowed.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(escrowed.userInfo.toString()).to.equals(positionPk.toString());
+    expect(escrowed.amount.toNumber()).to.equals(position.escrowedAmount.toNumber());
+
+    // The escrow only stands in for tiers that accept the mine's token mint
+    const tierGateAccounts = { admin: initializer.publicKey, tierInfo: tier1, mineInfo: mineAccount, roles };
+    const foreignMint = Keypair.generate().publicKey;
+    const gatesSig = await program.methods.setTierGates([
+      { kind: { mint: {} }, key: foreignMint, minimum: new anchor.BN(1) }
+    ])
+      .accounts(tierGateAccounts)
       .signers([initializer])
       .rpc()
       .then(confirm);
-    const afterTmUserBal = await connection.getBalance(user2.publicKey);
-    const afterTmVaultBal = await connection.getBalance(mineVault);
-    const afterTmFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
-    expect(afterTmUserBal - beforeTmUserBal).to.equals(expectedAmountOut);
-    expect(afterTmFeeCollectorBal - beforeTmFeeCollectorBal).to.equals(expectedDevFee);
-    expect(beforeTmVaultBal - afterTmVaultBal).to.equals(userInfo.totalLocked.toNumber());
+    const gatesUpdated = await eventOf(gatesSig, "TierGatesUpdated");
+    expect(gatesUpdated.tier.toString()).to.equals(tier1.toString());
+    expect(gatesUpdated.gates.length).to.equals(1);
+    expect(gatesUpdated.gates[0].kind).to.deep.equal({ mint: {} });
+    expect(gatesUpdated.gates[0].key.toString()).to.equals(foreignMint.toString());
+    expect(gatesUpdated.gates[0].minimum.toNumber()).to.equals(1);
     try {
-      await program.account.userInfo.fetch(user1InfoPk);
+      await program.methods.migrateTier(tierInfo["tier1"].nonce)
+        .accounts({
+          ...noTokenAccounts,
+          signer: user1.publicKey,
+          userInfo: positionPk,
+          positionNft: null,
+          tokenAccount: user1Ata,
+          gateMint: null,
+          mineInfo: mineAccount,
+          mineVault,
+          fromTierInfo: escrowTier,
+          toTierInfo: tier1,
+          feeCollector: feeCollector.publicKey,
+          penaltyCollector: penaltyCollector.publicKey
+        })
+        .signers([user1])
+        .rpc()
+        .then(confirm);
+      expect.fail("migrated on an escrow the destination tier does not accept");
     } catch (error) {
-      expect(error).to.be.an('Error');
+      expect(error.error.errorCode.code).to.equals("InvalidTokenAccount");
     }
-  })
+    await program.methods.setTierGates([])
+      .accounts(tierGateAccounts)
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+
+    // Withdrawing the position releases the escrowed tokens
+    const releaseSig = await program.methods.withdraw()
+      .accounts({
+        ...noTokenAccounts,
+        signer: user1.publicKey,
+        userInfo: positionPk,
+        positionNft: null,
+        referrerInfo: referralPda(positionPk),
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: escrowTier,
+        feeCollector: feeCollector.publicKey,
+        penaltyCollector: penaltyCollector.publicKey,
+        ...gateAccounts,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    escrowBalance = await connection.getTokenAccountBalance(gateEscrow);
+    expect(escrowBalance.value.amount).to.equals("0");
+    const released = await eventOf(releaseSig, "GateReleased");
+    expect(released.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(released.userInfo.toString()).to.equals(positionPk.toString());
+    expect(released.amount.toNumber()).to.equals(position.escrowedAmount.toNumber());
+  })
+
+  it("Enforce Gate Test", async () => {
+    const [gatedTier, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([6])],
+      program.programId
+    );
+    await program.methods.addTier(
+      new anchor.BN(tierInfo["tier1"].apy),
+      new anchor.BN(5 * 10**TOKEN_DECIMALS),
+      new anchor.BN(tierInfo["tier1"].lockDuration),
+      { fixed: {} }
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        tierInfo: gatedTier,
+        mineInfo: mineAccount,
+        roles,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const counter = await program.account.userCounter.fetchNullable(user4CounterPk);
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user4.publicKey.toBuffer(), positionSeed(counter ? counter.positionCount.toNumber() : 0)],
+      program.programId
+    );
+    await program.methods.initializeStaking(6, new anchor.BN(stakeAmount*LAMPORTS_PER_SOL))
+      .accounts({
+        ...noTokenAccounts,
+        signer: user4.publicKey,
+        userCounter: user4CounterPk,
+        userInfo: positionPk,
+        tokenAccount: user4Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: gatedTier,
+        feeCollector: feeCollector.publicKey,
+        gateEscrow: null,
+        tokenMint: null,
+        tokenProgram: null,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user4])
+      .rpc()
+      .then(confirm);
+    const [positionMint, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("position"), positionPk.toBuffer()],
+      program.programId
+    );
+    const user4PositionAccount = getAssociatedTokenAddressSync(positionMint, user4.publicKey, false, TOKEN_2022_PROGRAM_ID);
+    await program.methods.mintPositionNft("https://example.com/position.json")
+      .accounts({
+        signer: user4.publicKey,
+        userInfo: positionPk,
+        mineInfo: mineAccount,
+        mineVault,
+        positionMint,
+        positionAccount: user4PositionAccount,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user4])
+      .rpc()
+      .then(confirm);
+    const enforceAccounts = {
+      ...noTokenAccounts,
+      caller: user2.publicKey,
+      callerRewardAccount: null,
+      userInfo: positionPk,
+      positionNft: user4PositionAccount,
+      tokenAccount: user4Ata,
+      gateMint: mintKeyPair.publicKey,
+      mineInfo: mineAccount,
+      mineVault,
+      tierInfo: gatedTier,
+      toTierInfo: null
+    };
+    const staked = await program.account.userInfo.fetch(positionPk);
+    expect(staked.gateAccount.toString()).to.equals(user4Ata.toString());
+    // NFT positions are only checked against the NFT holder
+    try {
+      await program.methods.enforceGate()
+        .accounts({...enforceAccounts, positionNft: null})
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("enforced the gate without the position NFT");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("InvalidArgument");
+    }
+
+    // A buyer of the position moves the gate onto their own token account
+    const user3PositionAccount = await getOrCreateAssociatedTokenAccount(
+      connection,
+      initializer,
+      positionMint,
+      user3.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const handOver = (from: Keypair, fromAccount: PublicKey, toAccount: PublicKey) => transferChecked(
+      connection,
+      from,
+      fromAccount,
+      positionMint,
+      toAccount,
+      from,
+      1,
+      0,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const gateAccountUpdate = (holder: Keypair, positionNft: PublicKey, tokenAccount: PublicKey) => program.methods.updateGateAccount()
+      .accounts({
+        signer: holder.publicKey,
+        userInfo: positionPk,
+        positionNft,
+        tokenAccount,
+        gateMint: mintKeyPair.publicKey,
+        mineInfo: mineAccount,
+        tierInfo: gatedTier
+      })
+      .signers([holder])
+      .rpc()
+      .then(confirm);
+    await handOver(user4, user4PositionAccount, user3PositionAccount.address);
+    try {
+      await gateAccountUpdate(user4, user4PositionAccount, user4Ata);
+      expect.fail("moved the gate of a position without holding its NFT");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    const gateAccountSig = await gateAccountUpdate(user3, user3PositionAccount.address, user3Ata);
+    const gateAccountUpdated = await eventOf(gateAccountSig, "GateAccountUpdated");
+    expect(gateAccountUpdated.holder.toString()).to.equals(user3.publicKey.toString());
+    expect(gateAccountUpdated.userInfo.toString()).to.equals(positionPk.toString());
+    expect(gateAccountUpdated.gateAccount.toString()).to.equals(user3Ata.toString());
+    try {
+      await program.methods.enforceGate()
+        .accounts({...enforceAccounts, positionNft: user3PositionAccount.address, tokenAccount: user3Ata})
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("enforced the gate on the new holder");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("GateSatisfied");
+    }
+    await handOver(user3, user3PositionAccount.address, user4PositionAccount);
+    await gateAccountUpdate(user4, user4PositionAccount, user4Ata);
+    // Only the token account the gate was checked on can be inspected
+    try {
+      await program.methods.enforceGate()
+        .accounts({...enforceAccounts, tokenAccount: user3Ata})
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("enforced the gate on another token account");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    // Holders of the gating tokens cannot be kicked
+    try {
+      await program.methods.enforceGate()
+        .accounts({...enforceAccounts})
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("enforced the gate on a holder");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+
+    // Selling the gating tokens lets anyone stop the position's interest
+    await sleep(2*1000);
+    const balance = await connection.getTokenAccountBalance(user4Ata);
+    await transferChecked(
+      connection,
+      user4,
+      user4Ata,
+      mintKeyPair.publicKey,
+      user3Ata,
+      user4,
+      BigInt(balance.value.amount),
+      TOKEN_DECIMALS,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    // Positions cannot be moved to a tier with better terms
+    try {
+      await program.methods.enforceGate()
+        .accounts({...enforceAccounts, toTierInfo: tier2})
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("moved a kicked position to a better tier");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("InvalidTier");
+    }
+    const before = await program.account.userInfo.fetch(positionPk);
+    const beforeCallerBal = await connection.getBalance(user2.publicKey);
+    const enforceSig = await program.methods.enforceGate()
+      .accounts({...enforceAccounts})
+      .signers([user2])
+      .rpc()
+      .then(confirm);
+    const after = await program.account.userInfo.fetch(positionPk);
+    const afterCallerBal = await connection.getBalance(user2.publicKey);
+    expect(after.apy.toNumber()).to.equals(0);
+    expect(after.lockTs.toNumber()).to.equals(before.lockTs.toNumber());
+    expect(after.accruedInterest.toNumber()).to.lessThan(before.accruedInterest.toNumber());
+    expect(afterCallerBal).to.greaterThanOrEqual(beforeCallerBal - 10000);
+    const enforced = await eventOf(enforceSig, "GateEnforced");
+    expect(enforced.caller.toString()).to.equals(user2.publicKey.toString());
+    expect(enforced.owner.toString()).to.equals(user4.publicKey.toString());
+    expect(enforced.userInfo.toString()).to.equals(positionPk.toString());
+    expect(enforced.fromTier.toString()).to.equals(gatedTier.toString());
+    expect(enforced.toTier).to.equals(null);
+    expect(enforced.balance.toNumber()).to.equals(0);
+    expect(enforced.forfeitedInterest.toNumber()).to.equals(
+      before.accruedInterest.toNumber() - after.accruedInterest.toNumber()
+    );
+  })
+
+  it("Tier Gates Test", async () => {
+    const [listTier, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([7])],
+      program.programId
+    );
+    await program.methods.addTier(
+      new anchor.BN(tierInfo["tier1"].apy),
+      new anchor.BN(0),
+      new anchor.BN(tierInfo["tier1"].lockDuration),
+      { fixed: {} }
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        tierInfo: listTier,
+        mineInfo: mineAccount,
+        roles,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const altMint = await createSplMint(connection, initializer, initializer.publicKey, null, 0);
+    const user3AltAccount = await getOrCreateAssociatedTokenAccount(connection, initializer, altMint, user3.publicKey);
+    await mintTo(connection, initializer, altMint, user3AltAccount.address, initializer, 10);
+    await program.methods.setTierGates([
+      { kind: { mint: {} }, key: altMint, minimum: new anchor.BN(5) }
+    ])
+      .accounts({ admin: initializer.publicKey, tierInfo: listTier, mineInfo: mineAccount, roles })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const listTierInfo = await program.account.tierInfo.fetch(listTier);
+    expect(listTierInfo.gates.length).to.equals(1);
+    expect(listTierInfo.gates[0].key.toString()).to.equals(altMint.toString());
+
+    // The mine's token mint no longer passes the gate of this tier
+    const user1Counter = await program.account.userCounter.fetch(user1CounterPk);
+    const [user1PositionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(user1Counter.positionCount.toNumber())],
+      program.programId
+    );
+    const stakeAccounts = {
+      mineInfo: mineAccount,
+      mineVault,
+      tierInfo: listTier,
+      feeCollector: feeCollector.publicKey,
+      gateMint: null,
+      gateEscrow: null,
+      tokenMint: null,
+      tokenProgram: null,
+      systemProgram: SystemProgram.programId
+    };
+    try {
+      await program.methods.initializeStaking(7, new anchor.BN(stakeAmount*LAMPORTS_PER_SOL))
+        .accounts({
+          ...noTokenAccounts,
+          ...stakeAccounts,
+          signer: user1.publicKey,
+          userCounter: user1CounterPk,
+          userInfo: user1PositionPk,
+          tokenAccount: user1Ata
+        })
+        .signers([user1])
+        .rpc()
+        .then(confirm);
+      expect.fail("staked with a mint the tier does not accept");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+
+    const user3Counter = await program.account.userCounter.fetchNullable(user3CounterPk);
+    const [user3PositionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user3.publicKey.toBuffer(), positionSeed(user3Counter ? user3Counter.positionCount.toNumber() : 0)],
+      program.programId
+    );
+    await program.methods.initializeStaking(7, new anchor.BN(stakeAmount*LAMPORTS_PER_SOL))
+      .accounts({
+        ...noTokenAccounts,
+        ...stakeAccounts,
+        signer: user3.publicKey,
+        userCounter: user3CounterPk,
+        userInfo: user3PositionPk,
+        tokenAccount: user3AltAccount.address
+      })
+      .signers([user3])
+      .rpc()
+      .then(confirm);
+    const position = await program.account.userInfo.fetch(user3PositionPk);
+    expect(position.tier.toString()).to.equals(listTier.toString());
+
+    // Gate list tiers are enforced against the accepted assets
+    const enforceAccounts = {
+      ...noTokenAccounts,
+      caller: user2.publicKey,
+      callerRewardAccount: null,
+      userInfo: user3PositionPk,
+      positionNft: null,
+      tokenAccount: user3AltAccount.address,
+      gateMint: altMint,
+      mineInfo: mineAccount,
+      mineVault,
+      tierInfo: listTier,
+      toTierInfo: null
+    };
+    try {
+      await program.methods.enforceGate()
+        .accounts({...enforceAccounts})
+        .signers([user2])
+        .rpc()
+        .then(confirm);
+      expect.fail("enforced the gate on a holder of an accepted asset");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("GateSatisfied");
+    }
+    await burn(connection, user3, user3AltAccount.address, altMint, user3, 10);
+    await program.methods.enforceGate()
+      .accounts({...enforceAccounts})
+      .signers([user2])
+      .rpc()
+      .then(confirm);
+    const kicked = await program.account.userInfo.fetch(user3PositionPk);
+    expect(kicked.apy.toNumber()).to.equals(0);
+  })
+
+  it("Tier Boost Test", async () => {
+    const [boostTier, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), mineAccount.toBuffer(), Uint8Array.from([8])],
+      program.programId
+    );
+    await program.methods.addTier(
+      new anchor.BN(tierInfo["tier1"].apy),
+      new anchor.BN(0),
+      new anchor.BN(tierInfo["tier1"].lockDuration),
+      { fixed: {} }
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        tierInfo: boostTier,
+        mineInfo: mineAccount,
+        roles,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const boostAccounts = { admin: initializer.publicKey, tierInfo: boostTier, mineInfo: mineAccount, roles };
+    try {
+      await program.methods.setTierBoosts([
+        { minBalance: new anchor.BN(10), boost: new anchor.BN(1000) },
+        { minBalance: new anchor.BN(5), boost: new anchor.BN(2000) }
+      ])
+        .accounts(boostAccounts)
+        .signers([initializer])
+        .rpc()
+        .then(confirm);
+      expect.fail("set brackets that are not sorted by balance");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    // user1 holds enough of the token mint for the first bracket only
+    const boostsSig = await program.methods.setTierBoosts([
+      { minBalance: new anchor.BN(1), boost: new anchor.BN(1000) },
+      { minBalance: new anchor.BN(1_000_000_000), boost: new anchor.BN(5000) }
+    ])
+      .accounts(boostAccounts)
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const boostsUpdated = await eventOf(boostsSig, "TierBoostsUpdated");
+    expect(boostsUpdated.tier.toString()).to.equals(boostTier.toString());
+    expect(boostsUpdated.boosts.length).to.equals(2);
+    expect(boostsUpdated.boosts[0].minBalance.toNumber()).to.equals(1);
+    expect(boostsUpdated.boosts[0].boost.toNumber()).to.equals(1000);
+    expect(boostsUpdated.boosts[1].minBalance.toNumber()).to.equals(1_000_000_000);
+    expect(boostsUpdated.boosts[1].boost.toNumber()).to.equals(5000);
+
+    const user1Counter = await program.account.userCounter.fetch(user1CounterPk);
+    const [user1PositionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(user1Counter.positionCount.toNumber())],
+      program.programId
+    );
+    await program.methods.initializeStaking(8, new anchor.BN(stakeAmount*LAMPORTS_PER_SOL))
+      .accounts({
+        ...noTokenAccounts,
+        signer: user1.publicKey,
+        userCounter: user1CounterPk,
+        userInfo: user1PositionPk,
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: boostTier,
+        feeCollector: feeCollector.publicKey,
+        gateEscrow: null,
+        tokenMint: null,
+        tokenProgram: null,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    const position = await program.account.userInfo.fetch(user1PositionPk);
+    const boostedApy = tierInfo["tier1"].apy + (tierInfo["tier1"].apy * 1000)/10000;
+    expect(position.boost.toNumber()).to.equals(1000);
+    expect(position.accruedInterest.toNumber()).to.equals(
+      calculateInterest(position.totalLocked.toNumber(), boostedApy, tierInfo["tier1"].lockDuration)
+    );
+  })
+
+  it("Multi-level Referral Test", async () => {
+    const [pendingMineChange, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pending-mine"), mineAccount.toBuffer()],
+      program.programId
+    );
+    const queueAccounts = {
+      admin: initializer.publicKey,
+      pendingChange: pendingMineChange,
+      mineInfo: mineAccount,
+      roles,
+      systemProgram: SystemProgram.programId
+    };
+    const secondLevelReward = 500;
+    const thirdLevelReward = 250;
+    try {
+      await program.methods.queueMineChange(
+        null, null, null, [new anchor.BN(referralReward + 1), new anchor.BN(0)], null
+      )
+        .accounts({...queueAccounts}).signers([initializer]).rpc().then(confirm);
+      expect.fail("queued an upline reward above the direct one");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("InvalidReferralLevels");
+    }
+    const queueSig = await program.methods.queueMineChange(
+      null, // Dev Fee
+      null, // Early Withdrawal Fee
+      null, // Referral Reward
+      [new anchor.BN(secondLevelReward), new anchor.BN(thirdLevelReward)],
+      null // Timelock Delay
+    )
+      .accounts({...queueAccounts}).signers([initializer]).rpc().then(confirm);
+    const queued = await eventOf(queueSig, "MineChangeQueued");
+    expect(queued.devFee).to.equals(null);
+    expect(queued.earlyWithdrawalFee).to.equals(null);
+    expect(queued.referralReward).to.equals(null);
+    expect(queued.uplineReferralRewards.map((reward) => reward.toNumber())).to.deep.equal([secondLevelReward, thirdLevelReward]);
+    expect(queued.timelockDelay).to.equals(null);
+    const queuedChange = await program.account.pendingMineChange.fetch(pendingMineChange);
+    expect(queued.effectiveTs.toNumber()).to.equals(queuedChange.effectiveTs.toNumber());
+    await sleep((timelockDelay + 1) * 1000);
+    await program.methods.executeMineChange()
+      .accounts({
+        executor: user2.publicKey,
+        pendingChange: pendingMineChange,
+        proposer: initializer.publicKey,
+        mineInfo: mineAccount
+      })
+      .signers([user2])
+      .rpc()
+      .then(confirm);
+    const mineInfo = await program.account.mineInfo.fetch(mineAccount);
+    expect(mineInfo.uplineReferralRewards[0].toNumber()).to.equals(secondLevelReward);
+
+    // user3 is referred by user2's position, which user1's position referred
+    const [user2refInfoPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("referral"), user2InfoPk.toBuffer()],
+      program.programId
+    );
+    const user3Counter = await program.account.userCounter.fetchNullable(user3CounterPk);
+    const [user3PositionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user3.publicKey.toBuffer(), positionSeed(user3Counter ? user3Counter.positionCount.toNumber() : 0)],
+      program.programId
+    );
+    const beforeUplineInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+    const stakeAccounts = {
+      signer: user3.publicKey,
+      userCounter: user3CounterPk,
+      userInfo: user3PositionPk,
+      tokenAccount: user3Ata,
+      gateMint: null,
+      mineInfo: mineAccount,
+      mineVault,
+      tierInfo: tier1,
+      referrerUserInfo: user2InfoPk,
+      referrerInfo: user2refInfoPk,
+      thirdReferrerInfo: null,
+      feeCollector: feeCollector.publicKey,
+      gateEscrow: null,
+      tokenMint: null,
+      tokenProgram: null,
+      systemProgram: SystemProgram.programId
+    };
+    // The upline cannot be skipped while its level pays a reward
+    try {
+      await program.methods.initializeStakingWithReferrer(
+        tierInfo["tier1"].nonce,
+        new anchor.BN(stakeAmount*LAMPORTS_PER_SOL)
+      )
+        .accounts({ ...noTokenAccounts, ...stakeAccounts, secondReferrerInfo: null })
+        .signers([user3])
+        .rpc()
+        .then(confirm);
+      expect.fail("skipped the second level referrer");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("InvalidReferrer");
+    }
+    await program.methods.initializeStakingWithReferrer(
+      tierInfo["tier1"].nonce,
+      new anchor.BN(stakeAmount*LAMPORTS_PER_SOL)
+    )
+      .accounts({ ...noTokenAccounts, ...stakeAccounts, secondReferrerInfo: user1refInfoPK })
+      .signers([user3])
+      .rpc()
+      .then(confirm);
+    const position = await program.account.userInfo.fetch(user3PositionPk);
+    const directInfo = await program.account.referralInfo.fetch(user2refInfoPk);
+    const afterUplineInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+    const expectedDevFee = (devFee * stakeAmount * LAMPORTS_PER_SOL)/10000;
+    const expectedTotalLocked = (stakeAmount * LAMPORTS_PER_SOL) - expectedDevFee;
+    expect(position.referrer.toString()).to.equals(user2InfoPk.toString());
+    expect(position.uplineReferrers[0].toString()).to.equals(user1InfoPk.toString());
+    expect(position.uplineReferrers[1]).to.equals(null);
+    expect(directInfo.levelCounts[0].toNumber()).to.equals(1);
+    expect(directInfo.earnings.toNumber()).to.equals(expectedTotalLocked * referralReward/10000);
+    expect(afterUplineInfo.levelCounts[1].toNumber() - beforeUplineInfo.levelCounts[1].toNumber()).to.equals(1);
+    expect(afterUplineInfo.count.toNumber()).to.equals(beforeUplineInfo.count.toNumber());
+    expect(afterUplineInfo.earnings.toNumber() - beforeUplineInfo.earnings.toNumber()).to.equals(
+      expectedTotalLocked * secondLevelReward/10000
+    );
+    expect(afterUplineInfo.levelEarnings[0].toNumber()).to.equals(beforeUplineInfo.levelEarnings[0].toNumber());
+  })
+
+  it("Referral Withdrawal Test", async () => {
+    const accounts = {
+      signer: user1.publicKey,
+      userInfo: user1InfoPk,
+      positionNft: null,
+      referrerInfo: user1refInfoPK,
+      tokenAccount: user1Ata,
+      gateMint: null,
+      tierInfo: tier1,
+      mineInfo: mineAccount,
+      mineVault,
+      feeCollector: feeCollector.publicKey
+    };
+    const beforeClaimRefInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+    const beforeClaimUserBal = await connection.getBalance(user1.publicKey);
+    const beforeClaimVaultBal = await connection.getBalance(mineVault);
+    const beforeClaimFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
+    const expectedDevFee = (beforeClaimRefInfo.earnings.toNumber() * devFee)/10000;
+    const expectedAmountOut = beforeClaimRefInfo.earnings.toNumber() - expectedDevFee;
+    const referralSig = await program.methods.withdrawReferralRewards()
+      .accounts({...noTokenAccounts, ...accounts})
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    const afterClaimRefInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+    const afterClaimUserBal = await connection.getBalance(user1.publicKey);
+    const afterClaimVaultBal = await connection.getBalance(mineVault);
+    const afterClaimFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
+    expect(afterClaimRefInfo.earnings.toNumber()).to.equals(0);
+    expect(afterClaimUserBal - beforeClaimUserBal).to.equals(expectedAmountOut);
+    expect(afterClaimFeeCollectorBal - beforeClaimFeeCollectorBal).to.equals(expectedDevFee);
+    expect(beforeClaimVaultBal - afterClaimVaultBal).to.equals(beforeClaimRefInfo.earnings.toNumber());
+    const referralWithdrawn = await eventOf(referralSig, "ReferralWithdrawn");
+    expect(referralWithdrawn.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(referralWithdrawn.referrerInfo.toString()).to.equals(user1refInfoPK.toString());
+    expect(referralWithdrawn.earnings.toNumber()).to.equals(beforeClaimRefInfo.earnings.toNumber());
+    expect(referralWithdrawn.amountOut.toNumber()).to.equals(expectedAmountOut);
+    expect(referralWithdrawn.devFee.toNumber()).to.equals(expectedDevFee);
+  })
+
+  it("Terminate Staking Test [With Referral Info]", async () => {
+    const accounts = {
+      admin: initializer.publicKey,
+      userInfo: user1InfoPk,
+      positionNft: null,
+      referrerInfo: user1refInfoPK,
+      mineInfo: mineAccount,
+      roles,
+      mineVault,
+      tierInfo: tier1,
+      userAccount: user1.publicKey,
+      feeCollector: feeCollector.publicKey,
+      userTokenAccount: null,
+      gateEscrow: null,
+      tokenMint: null,
+      tokenProgram: null,
+      systemProgram: SystemProgram.programId
+    };
+    const userInfo = await program.account.userInfo.fetch(user1InfoPk);
+    const refInfo = await program.account.referralInfo.fetch(user1refInfoPK);
+    const beforeVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const beforeTmUserBal = await connection.getBalance(user1.publicKey);
+    const beforeTmVaultBal = await connection.getBalance(mineVault);
+    const beforeTmFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
+    const expectedDevFee = (userInfo.totalLocked.toNumber() * devFee)/10000;
+    const expectedAmountOut = userInfo.totalLocked.toNumber() - expectedDevFee;
+    const terminateSig = await program.methods.terminateStaking()
+      .accounts({...noTokenAccounts, ...accounts})
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const terminated = await eventOf(terminateSig, "StakeTerminated");
+    expect(terminated.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(terminated.userInfo.toString()).to.equals(user1InfoPk.toString());
+    expect(terminated.tier.toString()).to.equals(tier1.toString());
+    expect(terminated.principal.toNumber()).to.equals(userInfo.totalLocked.toNumber());
+    expect(terminated.amountOut.toNumber()).to.equals(expectedAmountOut);
+    expect(terminated.devFee.toNumber()).to.equals(expectedDevFee);
+    const afterTmUserBal = await connection.getBalance(user1.publicKey);
+    const afterTmVaultBal = await connection.getBalance(mineVault);
+    const afterTmFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
+    expect(afterTmUserBal - beforeTmUserBal).to.equals(expectedAmountOut);
+    expect(afterTmFeeCollectorBal - beforeTmFeeCollectorBal).to.equals(expectedDevFee);
+    expect(beforeTmVaultBal - afterTmVaultBal).to.equals(userInfo.totalLocked.toNumber());
+    // Interest and unclaimed referral earnings are forfeited with the position
+    const afterVaultInfo = await program.account.mineVault.fetch(mineVault);
+    expect(
+      beforeVaultInfo.totalPromisedInterest.toNumber() - afterVaultInfo.totalPromisedInterest.toNumber()
+    ).to.equals(userInfo.accruedInterest.toNumber() + refInfo.earnings.toNumber());
+    try {
+      await program.account.userInfo.fetch(user1InfoPk);
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    try {
+      await program.account.referralInfo.fetch(user1refInfoPK);
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+  })
+
+  it("Terminate Staking Test [No Referral Info]", async () => {
+    const accounts = {
+      admin: initializer.publicKey,
+      userInfo: user2InfoPk,
+      positionNft: null,
+      referrerInfo: referralPda(user2InfoPk),
+      mineInfo: mineAccount,
+      roles,
+      mineVault,
+      tierInfo: tier2,
+      userAccount: user2.publicKey,
+      feeCollector: feeCollector.publicKey,
+      userTokenAccount: null,
+      gateEscrow: null,
+      tokenMint: null,
+      tokenProgram: null,
+      systemProgram: SystemProgram.programId
+    };
+    // Outstanding receipts do not block the termination, the principal
+    // backing them stays in the vault for their holders
+    const [receiptMint, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt"), tier2.toBuffer()],
+      program.programId
+    );
+    const receiptAccount = getAssociatedTokenAddressSync(receiptMint, user2.publicKey, false, TOKEN_PROGRAM_ID);
+    const receiptAmount = 1 * LAMPORTS_PER_SOL;
+    await program.methods.mintReceipt(new anchor.BN(receiptAmount))
+      .accounts({
+        signer: user2.publicKey,
+        userInfo: user2InfoPk,
+        positionNft: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: tier2,
+        receiptMint,
+        receiptAccount,
+        tokenProgram: TOKEN_PROGRAM_ID
+      })
+      .signers([user2])
+      .rpc()
+      .then(confirm);
+    const beforeTierInfo = await program.account.tierInfo.fetch(tier2);
+    const userInfo = await program.account.userInfo.fetch(user2InfoPk);
+    const released = userInfo.totalLocked.toNumber() - receiptAmount;
+    const beforeTmUserBal = await connection.getBalance(user2.publicKey);
+    const beforeTmVaultBal = await connection.getBalance(mineVault);
+    const beforeTmFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
+    const expectedDevFee = (released * devFee)/10000;
+    const expectedAmountOut = released - expectedDevFee;
+    await program.methods.terminateStaking()
+      .accounts({...noTokenAccounts, ...accounts})
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const afterTmUserBal = await connection.getBalance(user2.publicKey);
+    const afterTmVaultBal = await connection.getBalance(mineVault);
+    const afterTmFeeCollectorBal = await connection.getBalance(feeCollector.publicKey);
+    expect(afterTmUserBal - beforeTmUserBal).to.equals(expectedAmountOut);
+    expect(afterTmFeeCollectorBal - beforeTmFeeCollectorBal).to.equals(expectedDevFee);
+    expect(beforeTmVaultBal - afterTmVaultBal).to.equals(released);
+    try {
+      await program.account.userInfo.fetch(user1InfoPk);
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    const afterTierInfo = await program.account.tierInfo.fetch(tier2);
+    expect(
+      afterTierInfo.detachedReceipts.toNumber() - beforeTierInfo.detachedReceipts.toNumber()
+    ).to.equals(receiptAmount);
+
+    // Detached receipts are redeemed without a position
+    const beforeBurnUserBal = await connection.getBalance(user2.publicKey);
+    await program.methods.burnReceipt(new anchor.BN(receiptAmount))
+      .accounts({
+        ...noTokenAccounts,
+        signer: user2.publicKey,
+        userInfo: null,
+        positionNft: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: tier2,
+        receiptMint,
+        receiptAccount,
+        feeCollector: feeCollector.publicKey,
+        penaltyCollector: penaltyCollector.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID
+      })
+      .signers([user2])
+      .rpc()
+      .then(confirm);
+    const afterBurnUserBal = await connection.getBalance(user2.publicKey);
+    expect(afterBurnUserBal - beforeBurnUserBal).to.equals(receiptAmount - (receiptAmount * devFee)/10000);
+    const finalTierInfo = await program.account.tierInfo.fetch(tier2);
+    expect(finalTierInfo.detachedReceipts.toNumber()).to.equals(beforeTierInfo.detachedReceipts.toNumber());
+  })
+
+  it("Fund Rewards Test", async () => {
+    const amount = 5 * LAMPORTS_PER_SOL;
+    const beforeVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const beforeVaultBal = await connection.getBalance(mineVault);
+    const fundSig = await program.methods.fundRewards(new anchor.BN(amount))
+      .accounts({
+        ...noTokenAccounts,
+        admin: initializer.publicKey,
+        mineInfo: mineAccount,
+        mineVault,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const afterVaultInfo = await program.account.mineVault.fetch(mineVault);
+    const afterVaultBal = await connection.getBalance(mineVault);
+    expect(afterVaultBal - beforeVaultBal).to.equals(amount);
+    // Funded rewards only grow the surplus, the liabilities are unchanged
+    expect(afterVaultInfo.totalPrincipal.toNumber()).to.equals(beforeVaultInfo.totalPrincipal.toNumber());
+    expect(afterVaultInfo.totalPromisedInterest.toNumber()).to.equals(beforeVaultInfo.totalPromisedInterest.toNumber());
+    const funded = await eventOf(fundSig, "RewardsFunded");
+    expect(funded.amount.toNumber()).to.equals(amount);
+  })
+
+  it("Withdraw Excess Test", async () => {
+    const vaultInfo = await program.account.mineVault.fetch(mineVault);
+    const vaultAccount = await connection.getAccountInfo(mineVault);
+    const rent = await connection.getMinimumBalanceForRentExemption(vaultAccount.data.length);
+    const liabilities = vaultInfo.totalPrincipal.toNumber() + vaultInfo.totalPromisedInterest.toNumber();
+    const excess = vaultAccount.lamports - rent - liabilities;
+    const accounts = {
+      admin: initializer.publicKey,
+      mineInfo: mineAccount,
+      mineVault
+    };
+    try {
+      await program.methods.withdrawExcess(new anchor.BN(excess + 1))
+        .accounts({...noTokenAccounts, ...accounts}).signers([initializer]).rpc().then(confirm);
+      expect.fail("withdrew into liabilities");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    const excessSig = await program.methods.withdrawExcess(new anchor.BN(excess))
+      .accounts({...noTokenAccounts, ...accounts})
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const afterVaultBal = await connection.getBalance(mineVault);
+    expect(afterVaultBal).to.equals(rent + liabilities);
+    const withdrawn = await eventOf(excessSig, "ExcessWithdrawn");
+    expect(withdrawn.admin.toString()).to.equals(initializer.publicKey.toString());
+    expect(withdrawn.amount.toNumber()).to.equals(excess);
+  })
+
+  it("Token Mode Test", async () => {
+    const [tokenVault, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("token-vault"), mineAccount.toBuffer(), mintKeyPair.publicKey.toBuffer()],
+      program.programId
+    );
+    await program.methods.initTokenVault()
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: mineAccount,
+        mineVault,
+        mint: mintKeyPair.publicKey,
+        tokenVault,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const vaultAccount = await connection.getAccountInfo(tokenVault);
+    expect(vaultAccount.owner.toString()).to.equals(TOKEN_2022_PROGRAM_ID.toString());
+
+    // The mine already holds SOL positions so it cannot switch assets
+    try {
+      await program.methods.enableTokenMode()
+        .accounts({
+          admin: initializer.publicKey,
+          mineInfo: mineAccount,
+          mineVault,
+          stakeMint: mintKeyPair.publicKey,
+          rewardMint: mintKeyPair.publicKey,
+          stakeVault: tokenVault,
+          rewardVault: tokenVault
+        })
+        .signers([initializer])
+        .rpc()
+        .then(confirm);
+      expect.fail("enabled token mode with open positions");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    const mineInfo = await program.account.mineInfo.fetch(mineAccount);
+    expect(mineInfo.stakeMint).to.equals(null);
+    expect(mineInfo.rewardMint).to.equals(null);
+  })
+
+  it("Token Mode Stake, Claim And Withdraw Test", async () => {
+    const tokenMineId = new anchor.BN(2);
+    const [tokenMine, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mine"), initializer.publicKey.toBuffer(), tokenMineId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [tokenMineVault, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mine-vault"), tokenMine.toBuffer()],
+      program.programId
+    );
+    const [tokenRoles, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("roles"), tokenMine.toBuffer()],
+      program.programId
+    );
+    const [tokenTier, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), tokenMine.toBuffer(), Uint8Array.from([0])],
+      program.programId
+    );
+    const [tokenVault, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("token-vault"), tokenMine.toBuffer(), mintKeyPair.publicKey.toBuffer()],
+      program.programId
+    );
+    const [counterPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user-counter"), tokenMine.toBuffer(), user3.publicKey.toBuffer()],
+      program.programId
+    );
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), tokenMine.toBuffer(), user3.publicKey.toBuffer(), positionSeed(0)],
+      program.programId
+    );
+    const tokenAta = async (owner: PublicKey) => (await getOrCreateAssociatedTokenAccount(
+      connection,
+      initializer,
+      mintKeyPair.publicKey,
+      owner,
+      undefined,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    )).address;
+    const balance = async (account: PublicKey) => Number((await connection.getTokenAccountBalance(account)).value.amount);
+    // Token 2022 keeps 8% of every transfer, at most 10 tokens
+    const received = (amount: number) => amount - Math.min(Math.ceil(amount * 800 / 10000), 10 * 10**TOKEN_DECIMALS);
+
+    // Create a mine that stakes and pays interest in the token
+    await program.methods.initialize(tokenMineId, mineConfig())
+      .accounts({
+        initializer: initializer.publicKey,
+        mineInfo: tokenMine,
+        roles: tokenRoles,
+        mineVault: tokenMineVault,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    await program.methods.addTier(
+      new anchor.BN(tierInfo.tier1.apy),
+      new anchor.BN(tierInfo.tier1.minimumTokenAmount),
+      new anchor.BN(tierInfo.tier1.lockDuration),
+      { fixed: {} }
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: tokenMine,
+        roles: tokenRoles,
+        tierInfo: tokenTier,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    await program.methods.initTokenVault()
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: tokenMine,
+        mineVault: tokenMineVault,
+        mint: mintKeyPair.publicKey,
+        tokenVault,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const tokenModeSig = await program.methods.enableTokenMode()
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: tokenMine,
+        mineVault: tokenMineVault,
+        stakeMint: mintKeyPair.publicKey,
+        rewardMint: mintKeyPair.publicKey,
+        stakeVault: tokenVault,
+        rewardVault: tokenVault
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const tokenMode = await eventOf(tokenModeSig, "TokenModeEnabled");
+    expect(tokenMode.mine.toString()).to.equals(tokenMine.toString());
+    expect(tokenMode.stakeMint.toString()).to.equals(mintKeyPair.publicKey.toString());
+    expect(tokenMode.rewardMint.toString()).to.equals(mintKeyPair.publicKey.toString());
+    expect(tokenMode.stakeVault.toString()).to.equals(tokenVault.toString());
+    expect(tokenMode.rewardVault.toString()).to.equals(tokenVault.toString());
+
+    // Fund the interest
+    const adminAta = await mintToAccount(initializer.publicKey, 1000);
+    await program.methods.fundRewards(new anchor.BN(1000 * 10**TOKEN_DECIMALS))
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: tokenMine,
+        mineVault: tokenMineVault,
+        rewardMint: mintKeyPair.publicKey,
+        adminRewardAccount: adminAta,
+        rewardVault: tokenVault,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const fundedRewards = await balance(tokenVault);
+    expect(fundedRewards).to.equals(received(1000 * 10**TOKEN_DECIMALS));
+
+    // Stake, the vault keeps what arrives after the transfer fee
+    const userAta = await mintToAccount(user3.publicKey, 100);
+    const feeAta = await tokenAta(feeCollector.publicKey);
+    const penaltyAta = await tokenAta(penaltyCollector.publicKey);
+    const deposit = 100 * 10**TOKEN_DECIMALS;
+    const depositFee = Math.floor(deposit * devFee / 10000);
+    await program.methods.initializeStaking(0, new anchor.BN(deposit))
+      .accounts({
+        signer: user3.publicKey,
+        userCounter: counterPk,
+        userInfo: positionPk,
+        tokenAccount: userAta,
+        gateMint: null,
+        mineInfo: tokenMine,
+        mineVault: tokenMineVault,
+        tierInfo: tokenTier,
+        feeCollector: feeCollector.publicKey,
+        gateEscrow: null,
+        tokenMint: null,
+        stakeMint: mintKeyPair.publicKey,
+        userStakeAccount: userAta,
+        stakeVault: tokenVault,
+        rewardVault: tokenVault,
+        feeCollectorStakeAccount: feeAta,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user3])
+      .rpc()
+      .then(confirm);
+    let position = await program.account.userInfo.fetch(positionPk);
+    const principal = position.totalLocked.toNumber();
+    expect(principal).to.equals(received(deposit - depositFee));
+    expect(await balance(tokenVault)).to.equals(fundedRewards + principal);
+    let vaultInfo = await program.account.mineVault.fetch(tokenMineVault);
+    expect(vaultInfo.totalPrincipal.toNumber()).to.equals(principal);
+    expect(vaultInfo.totalPromisedInterest.toNumber()).to.equals(position.accruedInterest.toNumber());
+
+    // The admin can only take back reward tokens above what the vault owes
+    const excessAccounts = {
+      admin: initializer.publicKey,
+      mineInfo: tokenMine,
+      mineVault: tokenMineVault,
+      rewardMint: mintKeyPair.publicKey,
+      adminRewardAccount: adminAta,
+      stakeVault: tokenVault,
+      rewardVault: tokenVault,
+      tokenProgram: TOKEN_2022_PROGRAM_ID
+    };
+    const excess = await balance(tokenVault) - principal - position.accruedInterest.toNumber();
+    try {
+      await program.methods.withdrawExcess(new anchor.BN(excess + 1))
+        .accounts(excessAccounts).signers([initializer]).rpc().then(confirm);
+      expect.fail("withdrew into liabilities");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    const excessTaken = 10 * 10**TOKEN_DECIMALS;
+    const adminBalance = await balance(adminAta);
+    await program.methods.withdrawExcess(new anchor.BN(excessTaken))
+      .accounts(excessAccounts)
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    expect(await balance(tokenVault)).to.equals(fundedRewards + principal - excessTaken);
+    expect(await balance(adminAta)).to.equals(adminBalance + received(excessTaken));
+
+    // Claim once the lock has ended, no penalty and the lock restarts
+    await sleep(6000);
+    const interest = position.accruedInterest.toNumber();
+    const interestFee = Math.floor(interest * devFee / 10000);
+    let userBalance = await balance(userAta);
+    await program.methods.claimInterest()
+      .accounts({
+        signer: user3.publicKey,
+        userInfo: positionPk,
+        positionNft: null,
+        tokenAccount: userAta,
+        gateMint: null,
+        mineInfo: tokenMine,
+        mineVault: tokenMineVault,
+        tierInfo: tokenTier,
+        referrerInfo: null,
+        feeCollector: feeCollector.publicKey,
+        penaltyCollector: penaltyCollector.publicKey,
+        rewardMint: mintKeyPair.publicKey,
+        userRewardAccount: userAta,
+        rewardVault: tokenVault,
+        stakeVault: tokenVault,
+        feeCollectorRewardAccount: feeAta,
+        penaltyCollectorRewardAccount: penaltyAta,
+        tokenProgram: TOKEN_2022_PROGRAM_ID
+      })
+      .signers([user3])
+      .rpc()
+      .then(confirm);
+    expect(await balance(userAta)).to.equals(userBalance + received(interest - interestFee));
+    position = await program.account.userInfo.fetch(positionPk);
+    expect(position.totalLocked.toNumber()).to.equals(principal);
+    expect(position.accruedInterest.toNumber()).to.equals(interest);
+    vaultInfo = await program.account.mineVault.fetch(tokenMineVault);
+    expect(vaultInfo.totalPromisedInterest.toNumber()).to.equals(interest);
+
+    // Withdraw after the new lock, principal and interest less the dev fee
+    await sleep(6000);
+    const principalFee = Math.floor(principal * devFee / 10000);
+    userBalance = await balance(userAta);
+    const vaultBalance = await balance(tokenVault);
+    const feeBalance = await balance(feeAta);
+    await program.methods.withdraw()
+      .accounts({
+        signer: user3.publicKey,
+        userInfo: positionPk,
+        positionNft: null,
+        referrerInfo: referralPda(positionPk),
+        tokenAccount: userAta,
+        gateMint: null,
+        mineInfo: tokenMine,
+        mineVault: tokenMineVault,
+        tierInfo: tokenTier,
+        feeCollector: feeCollector.publicKey,
+        penaltyCollector: penaltyCollector.publicKey,
+        gateEscrow: null,
+        tokenMint: null,
+        systemProgram: SystemProgram.programId,
+        stakeMint: mintKeyPair.publicKey,
+        rewardMint: mintKeyPair.publicKey,
+        stakeVault: tokenVault,
+        rewardVault: tokenVault,
+        userStakeAccount: userAta,
+        userRewardAccount: userAta,
+        feeCollectorStakeAccount: feeAta,
+        feeCollectorRewardAccount: feeAta,
+        penaltyCollectorStakeAccount: penaltyAta,
+        penaltyCollectorRewardAccount: penaltyAta,
+        tokenProgram: TOKEN_2022_PROGRAM_ID
+      })
+      .signers([user3])
+      .rpc()
+      .then(confirm);
+    expect(await balance(tokenVault)).to.equals(vaultBalance - principal - interest);
+    expect(await balance(userAta)).to.equals(
+      userBalance + received(principal - principalFee) + received(interest - interestFee)
+    );
+    expect(await balance(feeAta)).to.equals(feeBalance + received(principalFee) + received(interestFee));
+    expect(await program.account.userInfo.fetchNullable(positionPk)).to.equals(null);
+    vaultInfo = await program.account.mineVault.fetch(tokenMineVault);
+    expect(vaultInfo.totalPrincipal.toNumber()).to.equals(0);
+    expect(vaultInfo.totalPromisedInterest.toNumber()).to.equals(0);
+    const tierAccount = await program.account.tierInfo.fetch(tokenTier);
+    expect(tierAccount.totalLocked.toNumber()).to.equals(0);
+  })
+  it("Token Mode Partial Withdraw And Terminate Test", async () => {
+    const tokenMineId = new anchor.BN(2);
+    const [tokenMine, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mine"), initializer.publicKey.toBuffer(), tokenMineId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [tokenMineVault, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mine-vault"), tokenMine.toBuffer()],
+      program.programId
+    );
+    const [tokenRoles, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("roles"), tokenMine.toBuffer()],
+      program.programId
+    );
+    const [tokenTier, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), tokenMine.toBuffer(), Uint8Array.from([0])],
+      program.programId
+    );
+    const [tokenVault, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("token-vault"), tokenMine.toBuffer(), mintKeyPair.publicKey.toBuffer()],
+      program.programId
+    );
+    const [counterPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user-counter"), tokenMine.toBuffer(), user3.publicKey.toBuffer()],
+      program.programId
+    );
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), tokenMine.toBuffer(), user3.publicKey.toBuffer(), positionSeed(1)],
+      program.programId
+    );
+    const tokenAta = async (owner: PublicKey) => (await getOrCreateAssociatedTokenAccount(
+      connection,
+      initializer,
+      mintKeyPair.publicKey,
+      owner,
+      undefined,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    )).address;
+    const balance = async (account: PublicKey) => Number((await connection.getTokenAccountBalance(account)).value.amount);
+    // Token 2022 keeps 8% of every transfer, at most 10 tokens
+    const received = (amount: number) => amount - Math.min(Math.ceil(amount * 800 / 10000), 10 * 10**TOKEN_DECIMALS);
+
+    // Open a second position in the token mine
+    const userAta = await mintToAccount(user3.publicKey, 50);
+    const feeAta = await tokenAta(feeCollector.publicKey);
+    const penaltyAta = await tokenAta(penaltyCollector.publicKey);
+    await program.methods.initializeStaking(0, new anchor.BN(50 * 10**TOKEN_DECIMALS))
+      .accounts({
+        signer: user3.publicKey,
+        userCounter: counterPk,
+        userInfo: positionPk,
+        tokenAccount: userAta,
+        gateMint: null,
+        mineInfo: tokenMine,
+        mineVault: tokenMineVault,
+        tierInfo: tokenTier,
+        feeCollector: feeCollector.publicKey,
+        gateEscrow: null,
+        tokenMint: null,
+        stakeMint: mintKeyPair.publicKey,
+        userStakeAccount: userAta,
+        stakeVault: tokenVault,
+        rewardVault: tokenVault,
+        feeCollectorStakeAccount: feeAta,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([user3])
+      .rpc()
+      .then(confirm);
+    let position = await program.account.userInfo.fetch(positionPk);
+    const principal = position.totalLocked.toNumber();
+    const interest = position.accruedInterest.toNumber();
+
+    // Take half out while locked, the withdrawn share of interest goes along
+    const amount = Math.floor(principal / 2);
+    let vaultBalance = await balance(tokenVault);
+    await program.methods.withdrawPartial(new anchor.BN(amount))
+      .accounts({
+        signer: user3.publicKey,
+        userInfo: positionPk,
+        positionNft: null,
+        tokenAccount: userAta,
+        gateMint: null,
+        mineInfo: tokenMine,
+        mineVault: tokenMineVault,
+        tierInfo: tokenTier,
+        feeCollector: feeCollector.publicKey,
+        penaltyCollector: penaltyCollector.publicKey,
+        systemProgram: SystemProgram.programId,
+        stakeMint: mintKeyPair.publicKey,
+        rewardMint: mintKeyPair.publicKey,
+        stakeVault: tokenVault,
+        rewardVault: tokenVault,
+        userStakeAccount: userAta,
+        userRewardAccount: userAta,
+        feeCollectorStakeAccount: feeAta,
+        feeCollectorRewardAccount: feeAta,
+        penaltyCollectorStakeAccount: penaltyAta,
+        penaltyCollectorRewardAccount: penaltyAta,
+        tokenProgram: TOKEN_2022_PROGRAM_ID
+      })
+      .signers([user3])
+      .rpc()
+      .then(confirm);
+    position = await program.account.userInfo.fetch(positionPk);
+    const remaining = position.totalLocked.toNumber();
+    const withdrawnInterest = interest - position.accruedInterest.toNumber();
+    expect(remaining).to.equals(principal - amount);
+    expect(await balance(tokenVault)).to.equals(vaultBalance - amount - withdrawnInterest);
+    let vaultInfo = await program.account.mineVault.fetch(tokenMineVault);
+    expect(vaultInfo.totalPrincipal.toNumber()).to.equals(remaining);
+    expect(vaultInfo.totalPromisedInterest.toNumber()).to.equals(position.accruedInterest.toNumber());
+
+    // The pauser closes the rest, the principal goes back less the dev fee
+    const remainingFee = Math.floor(remaining * devFee / 10000);
+    const userBalance = await balance(userAta);
+    vaultBalance = await balance(tokenVault);
+    await program.methods.terminateStaking()
+      .accounts({
+        admin: initializer.publicKey,
+        userInfo: positionPk,
+        positionNft: null,
+        referrerInfo: referralPda(positionPk),
+        mineInfo: tokenMine,
+        roles: tokenRoles,
+        mineVault: tokenMineVault,
+        tierInfo: tokenTier,
+        userAccount: user3.publicKey,
+        feeCollector: feeCollector.publicKey,
+        stakeMint: mintKeyPair.publicKey,
+        stakeVault: tokenVault,
+        userStakeAccount: userAta,
+        feeCollectorStakeAccount: feeAta,
+        userTokenAccount: null,
+        gateEscrow: null,
+        tokenMint: null,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    expect(await balance(tokenVault)).to.equals(vaultBalance - remaining);
+    expect(await balance(userAta)).to.equals(userBalance + received(remaining - remainingFee));
+    expect(await program.account.userInfo.fetchNullable(positionPk)).to.equals(null);
+    vaultInfo = await program.account.mineVault.fetch(tokenMineVault);
+    expect(vaultInfo.totalPrincipal.toNumber()).to.equals(0);
+    expect(vaultInfo.totalPromisedInterest.toNumber()).to.equals(0);
+  })
+
+  it("Token Mode Reward Mint Test", async () => {
+    const rewardMineId = new anchor.BN(4);
+    const [rewardMine, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mine"), initializer.publicKey.toBuffer(), rewardMineId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const [rewardMineVault, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mine-vault"), rewardMine.toBuffer()],
+      program.programId
+    );
+    const [rewardRoles, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("roles"), rewardMine.toBuffer()],
+      program.programId
+    );
+    const tierPda = (nonce: number) => PublicKey.findProgramAddressSync(
+      [Buffer.from("tier"), rewardMine.toBuffer(), Uint8Array.from([nonce])],
+      program.programId
+    )[0];
+    const vaultPda = (mint: PublicKey) => PublicKey.findProgramAddressSync(
+      [Buffer.from("token-vault"), rewardMine.toBuffer(), mint.toBuffer()],
+      program.programId
+    )[0];
+    const addTier = (nonce: number, accrualMode: object) => program.methods.addTier(
+      new anchor.BN(tierInfo.tier1.apy),
+      new anchor.BN(tierInfo.tier1.minimumTokenAmount),
+      new anchor.BN(tierInfo.tier1.lockDuration),
+      accrualMode
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: rewardMine,
+        roles: rewardRoles,
+        tierInfo: tierPda(nonce),
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const initTokenVault = (mint: PublicKey, tokenProgram: PublicKey) => program.methods.initTokenVault()
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: rewardMine,
+        mineVault: rewardMineVault,
+        mint,
+        tokenVault: vaultPda(mint),
+        tokenProgram,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+
+    // A fixed tier is added while the mine still stakes SOL
+    await program.methods.initialize(rewardMineId, mineConfig())
+      .accounts({
+        initializer: initializer.publicKey,
+        mineInfo: rewardMine,
+        roles: rewardRoles,
+        mineVault: rewardMineVault,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    await addTier(0, { fixed: {} });
+
+    // Stake the token and pay interest in a mint with other decimals
+    const rewardMint = await createSplMint(connection, initializer, initializer.publicKey, null, TOKEN_DECIMALS - 3);
+    await initTokenVault(mintKeyPair.publicKey, TOKEN_2022_PROGRAM_ID);
+    await initTokenVault(rewardMint, TOKEN_PROGRAM_ID);
+    await program.methods.enableTokenMode()
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: rewardMine,
+        mineVault: rewardMineVault,
+        stakeMint: mintKeyPair.publicKey,
+        rewardMint,
+        stakeVault: vaultPda(mintKeyPair.publicKey),
+        rewardVault: vaultPda(rewardMint)
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+
+    // Interest worked out on the stake cannot be paid in the reward mint
+    for (const accrualMode of [{ fixed: {} }, { continuous: {} }]) {
+      try {
+        await addTier(1, accrualMode);
+        expect.fail("added a tier paying stake units in the reward mint");
+      } catch (error) {
+        expect(error.error.errorCode.code).to.equals("RewardMintMismatch");
+      }
+    }
+    await addTier(1, { emission: {} });
+    const emissionTier = await program.account.tierInfo.fetch(tierPda(1));
+    expect(emissionTier.isActive).to.equals(true);
+
+    // Nor can a tier added before token mode take stakes
+    const userAta = await mintToAccount(user4.publicKey, 10);
+    try {
+      await program.methods.initializeStaking(0, new anchor.BN(10 * 10**TOKEN_DECIMALS))
+        .accounts({
+          ...noTokenAccounts,
+          signer: user4.publicKey,
+          userCounter: PublicKey.findProgramAddressSync(
+            [Buffer.from("user-counter"), rewardMine.toBuffer(), user4.publicKey.toBuffer()],
+            program.programId
+          )[0],
+          userInfo: PublicKey.findProgramAddressSync(
+            [Buffer.from("user"), rewardMine.toBuffer(), user4.publicKey.toBuffer(), positionSeed(0)],
+            program.programId
+          )[0],
+          tokenAccount: userAta,
+          gateMint: null,
+          mineInfo: rewardMine,
+          mineVault: rewardMineVault,
+          tierInfo: tierPda(0),
+          feeCollector: feeCollector.publicKey,
+          gateEscrow: null,
+          tokenMint: null,
+          stakeMint: mintKeyPair.publicKey,
+          userStakeAccount: userAta,
+          stakeVault: vaultPda(mintKeyPair.publicKey),
+          rewardVault: vaultPda(rewardMint),
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: SystemProgram.programId
+        })
+        .signers([user4])
+        .rpc()
+        .then(confirm);
+      expect.fail("staked in a fixed tier paying in another mint");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("RewardMintMismatch");
+    }
+  })
+
+  it("Set Roles Test", async () => {
+    const rolesSig = await program.methods.setRoles(
+      user2.publicKey, // Tier Manager
+      null, // Whitelister
+      null, // Fee Manager
+      null // Pauser
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        mineInfo: mineAccount,
+        roles
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const rolesInfo = await program.account.mineRoles.fetch(roles);
+    expect(rolesInfo.tierManager.toString()).to.equals(user2.publicKey.toString());
+    expect(rolesInfo.whitelister.toString()).to.equals(initializer.publicKey.toString());
+    const rolesUpdated = await eventOf(rolesSig, "RolesUpdated");
+    expect(rolesUpdated.tierManager.toString()).to.equals(user2.publicKey.toString());
+    expect(rolesUpdated.whitelister.toString()).to.equals(rolesInfo.whitelister.toString());
+    expect(rolesUpdated.feeManager.toString()).to.equals(rolesInfo.feeManager.toString());
+    expect(rolesUpdated.pauser.toString()).to.equals(rolesInfo.pauser.toString());
+
+    // Tier manager can update tiers but not mine fees
+    await program.methods.updateTier(null, true, null)
+      .accounts({ admin: user2.publicKey, tierInfo: tier1, mineInfo: mineAccount, roles })
+      .signers([user2])
+      .rpc()
+      .then(confirm);
+    try {
+      await program.methods.updateMine(feeCollector.publicKey, null, null, null)
+        .accounts({ admin: user2.publicKey, mineInfo: mineAccount, roles })
+        .signers([user2]).rpc().then(confirm);
+      expect.fail("tier manager updated fees");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+  })
 
   it("Update Tier Test", async () => {
     const accounts = {
       admin: initializer.publicKey,
       tierInfo: tier2,
-      mineInfo: mineAccount
+      mineInfo: mineAccount,
+      roles
     }
     const newApy = 3000;
     const newLockDuration = 5000;
     const newMinimumTokenAmount = 4000*10**TOKEN_DECIMALS;
+    const [pendingTierChange, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pending-tier"), tier2.toBuffer()],
+      program.programId
+    );
     await program.methods.updateTier(
       new anchor.BN(newMinimumTokenAmount),
-      new anchor.BN(newApy), // APY
-      new anchor.BN(newLockDuration), // Lock duration
-      false
+      false,
+      null
     )
       .accounts({...accounts})
       .signers([initializer])
       .rpc()
       .then(confirm);
+    const queueSig = await program.methods.queueTierChange(
+      new anchor.BN(newApy), // APY
+      new anchor.BN(newLockDuration) // Lock duration
+    )
+      .accounts({
+        ...accounts,
+        pendingChange: pendingTierChange,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const queued = await eventOf(queueSig, "TierChangeQueued");
+    const queuedChange = await program.account.pendingTierChange.fetch(pendingTierChange);
+    expect(queued.tier.toString()).to.equals(tier2.toString());
+    expect(queued.apy.toNumber()).to.equals(newApy);
+    expect(queued.lockDuration.toNumber()).to.equals(newLockDuration);
+    expect(queued.effectiveTs.toNumber()).to.equals(queuedChange.effectiveTs.toNumber());
+    const executeAccounts = {
+      executor: user2.publicKey,
+      pendingChange: pendingTierChange,
+      proposer: initializer.publicKey,
+      tierInfo: tier2
+    };
+    try {
+      await program.methods.executeTierChange()
+        .accounts({...executeAccounts}).signers([user2]).rpc().then(confirm);
+      expect.fail("executed a tier change before the timelock elapsed");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    await sleep((timelockDelay + 1) * 1000);
+    await program.methods.executeTierChange()
+      .accounts({...executeAccounts})
+      .signers([user2])
+      .rpc()
+      .then(confirm);
     const tierInfo = await program.account.tierInfo.fetch(tier2);
     expect(tierInfo.apy.toNumber()).to.equals(newApy);
     expect(tierInfo.lockDuration.toNumber()).to.equals(newLockDuration);
@@ -728,20 +3501,210 @@ describe("tier-sol-miner", () => {
     expect(tierInfo.isActive).to.equals(false);
   })
 
+  it("Refresh Terms Test", async () => {
+    const [positionPk, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), mineAccount.toBuffer(), user1.publicKey.toBuffer(), positionSeed(1)],
+      program.programId
+    );
+    // The open position keeps the terms it was migrated under
+    const beforeUserInfo = await program.account.userInfo.fetch(positionPk);
+    expect(beforeUserInfo.apy.toNumber()).to.equals(tierInfo["tier2"].apy);
+    expect(beforeUserInfo.lockDuration.toNumber()).to.equals(tierInfo["tier2"].lockDuration);
+    await program.methods.updateTier(
+      null,
+      true,
+      null
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        tierInfo: tier2,
+        mineInfo: mineAccount,
+        roles
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    // The minimum was raised by the tier update, top up the gating balance
+    await mintToAccount(user1.publicKey, 4000);
+    const [positionMint, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("position"), positionPk.toBuffer()],
+      program.programId
+    );
+    const refreshSig = await program.methods.refreshTerms()
+      .accounts({
+        signer: user1.publicKey,
+        userInfo: positionPk,
+        positionNft: getAssociatedTokenAddressSync(positionMint, user1.publicKey, false, TOKEN_2022_PROGRAM_ID),
+        tokenAccount: user1Ata,
+        gateMint: null,
+        mineInfo: mineAccount,
+        mineVault,
+        tierInfo: tier2,
+        stakeVault: null,
+        rewardVault: null
+      })
+      .signers([user1])
+      .rpc()
+      .then(confirm);
+    const newTierInfo = await program.account.tierInfo.fetch(tier2);
+    const afterUserInfo = await program.account.userInfo.fetch(positionPk);
+    expect(afterUserInfo.apy.toNumber()).to.equals(newTierInfo.apy.toNumber());
+    expect(afterUserInfo.lockDuration.toNumber()).to.equals(newTierInfo.lockDuration.toNumber());
+    expect(afterUserInfo.lockTs.toNumber()).to.greaterThanOrEqual(beforeUserInfo.lockTs.toNumber());
+    const refreshed = await eventOf(refreshSig, "TermsRefreshed");
+    expect(refreshed.owner.toString()).to.equals(user1.publicKey.toString());
+    expect(refreshed.userInfo.toString()).to.equals(positionPk.toString());
+    expect(refreshed.tier.toString()).to.equals(tier2.toString());
+    expect(refreshed.apy.toNumber()).to.equals(newTierInfo.apy.toNumber());
+    expect(refreshed.lockDuration.toNumber()).to.equals(newTierInfo.lockDuration.toNumber());
+    expect(refreshed.accruedInterest.toNumber()).to.equals(afterUserInfo.accruedInterest.toNumber());
+  })
+
+  it("Timelocked Mine Change Test", async () => {
+    const [pendingMineChange, ] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pending-mine"), mineAccount.toBuffer()],
+      program.programId
+    );
+    const newDevFee = 200;
+    const queueAccounts = {
+      admin: initializer.publicKey,
+      pendingChange: pendingMineChange,
+      mineInfo: mineAccount,
+      roles,
+      systemProgram: SystemProgram.programId
+    };
+    try {
+      await program.methods.queueMineChange(
+        new anchor.BN(maxDevFee + 1), null, null, null, null
+      )
+        .accounts({...queueAccounts}).signers([initializer]).rpc().then(confirm);
+      expect.fail("queued a dev fee above its cap");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("DevFeeTooHigh");
+    }
+    try {
+      await program.methods.queueMineChange(
+        null, new anchor.BN(maxEarlyClaimFee + 1), null, null, null
+      )
+        .accounts({...queueAccounts}).signers([initializer]).rpc().then(confirm);
+      expect.fail("queued an early withdrawal fee above its cap");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("EarlyWithdrawalFeeTooHigh");
+    }
+    try {
+      await program.methods.queueMineChange(
+        null, null, new anchor.BN(maxReferralReward + 1), null, null
+      )
+        .accounts({...queueAccounts}).signers([initializer]).rpc().then(confirm);
+      expect.fail("queued a referral reward above its cap");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("ReferralRewardTooHigh");
+    }
+    try {
+      await program.methods.queueMineChange(
+        null, new anchor.BN(maxEarlyClaimFee), null, null, null
+      )
+        .accounts({...queueAccounts}).signers([initializer]).rpc().then(confirm);
+      expect.fail("queued fees adding up to more than 100%");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.equals("CombinedFeeTooHigh");
+    }
+    await program.methods.queueMineChange(
+      new anchor.BN(newDevFee), // Dev Fee
+      null, // Early Withdrawal Fee
+      null, // Referral Reward
+      null, // Upline Referral Rewards
+      null // Timelock Delay
+    )
+      .accounts({
+        admin: initializer.publicKey,
+        pendingChange: pendingMineChange,
+        mineInfo: mineAccount,
+        roles,
+        systemProgram: SystemProgram.programId
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const cancelSig = await program.methods.cancelMineChange()
+      .accounts({
+        admin: initializer.publicKey,
+        pendingChange: pendingMineChange,
+        proposer: initializer.publicKey,
+        mineInfo: mineAccount,
+        roles
+      })
+      .signers([initializer])
+      .rpc()
+      .then(confirm);
+    const pending = await connection.getAccountInfo(pendingMineChange);
+    const mineInfo = await program.account.mineInfo.fetch(mineAccount);
+    expect(pending).to.equals(null);
+    expect(mineInfo.devFee.toNumber()).to.equals(devFee);
+    const cancelled = await eventOf(cancelSig, "ChangeCancelled");
+    expect(cancelled.pendingChange.toString()).to.equals(pendingMineChange.toString());
+  })
+
   it("Update Mine Test", async () => {
     const accounts = {
       admin: initializer.publicKey,
-      mineInfo: mineAccount
+      mineInfo: mineAccount,
+      roles
     }
-    await program.methods.updateMine(
+    const updateSig = await program.methods.updateMine(
       null, // Fee Collector
       null, // Penalty Collector
-      null, // Dev Fee
-      null, // Early Withdrawal Fee,
-      null, // Referral Reward,
-      false
+      false,
+      null // Referral on claim
     ).accounts({...accounts}).signers([initializer]).rpc().then(confirm);
     const mineInfo = await program.account.mineInfo.fetch(mineAccount);
     expect(mineInfo.isActive).to.equals(false);
+    const emitted = await eventOf(updateSig, "MineUpdated");
+    expect(emitted.mine.toString()).to.equals(mineAccount.toString());
+    expect(emitted.feeCollector.toString()).to.equals(mineInfo.feeCollector.toString());
+    expect(emitted.penaltyFeeCollector.toString()).to.equals(mineInfo.penaltyFeeCollector.toString());
+    expect(emitted.isActive).to.equals(false);
+    expect(emitted.devFee.toNumber()).to.equals(devFee);
+    expect(emitted.referralReward.toNumber()).to.equals(mineInfo.referralReward.toNumber());
+    expect(emitted.referralOnClaim).to.equals(mineInfo.referralOnClaim);
+  })
+
+  it("Admin Transfer Test", async () => {
+    const proposeAccounts = {
+      admin: initializer.publicKey,
+      mineInfo: mineAccount
+    };
+    const proposeSig = await program.methods.proposeAdmin(user4.publicKey)
+      .accounts({...proposeAccounts}).signers([initializer]).rpc().then(confirm);
+    const proposed = await eventOf(proposeSig, "AdminTransferProposed");
+    expect(proposed.admin.toString()).to.equals(initializer.publicKey.toString());
+    expect(proposed.pendingAdmin.toString()).to.equals(user4.publicKey.toString());
+    const cancelSig = await program.methods.cancelAdminTransfer()
+      .accounts({...proposeAccounts}).signers([initializer]).rpc().then(confirm);
+    const cancelled = await eventOf(cancelSig, "AdminTransferProposed");
+    expect(cancelled.pendingAdmin).to.equals(null);
+    let mineInfo = await program.account.mineInfo.fetch(mineAccount);
+    expect(mineInfo.pendingAdmin).to.equals(null);
+    try {
+      await program.methods.acceptAdmin()
+        .accounts({ newAdmin: user4.publicKey, mineInfo: mineAccount })
+        .signers([user4]).rpc().then(confirm);
+      expect.fail("accepted a cancelled admin transfer");
+    } catch (error) {
+      expect(error).to.be.an('Error');
+    }
+    await program.methods.proposeAdmin(user4.publicKey)
+      .accounts({...proposeAccounts}).signers([initializer]).rpc().then(confirm);
+    const acceptSig = await program.methods.acceptAdmin()
+      .accounts({ newAdmin: user4.publicKey, mineInfo: mineAccount })
+      .signers([user4])
+      .rpc()
+      .then(confirm);
+    const transferred = await eventOf(acceptSig, "AdminTransferred");
+    expect(transferred.previousAdmin.toString()).to.equals(initializer.publicKey.toString());
+    expect(transferred.newAdmin.toString()).to.equals(user4.publicKey.toString());
+    mineInfo = await program.account.mineInfo.fetch(mineAccount);
+    expect(mineInfo.admin.toString()).to.equals(user4.publicKey.toString());
+    expect(mineInfo.pendingAdmin).to.equals(null);
   })
 });